@@ -1,6 +1,10 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use lact_client::DaemonClient;
-use lact_schema::args::{CliArgs, CliCommand};
+use lact_schema::{
+    args::{CliArgs, CliCommand},
+    ApplyScriptFormat,
+};
+use std::{fs, path::Path, process};
 
 pub fn run(args: CliArgs) -> Result<()> {
     let rt = tokio::runtime::Builder::new_current_thread()
@@ -14,6 +18,20 @@ pub fn run(args: CliArgs) -> Result<()> {
             CliCommand::ListGpus => list_gpus(&args, &client).await,
             CliCommand::Info => info(&args, &client).await,
             CliCommand::Snapshot => snapshot(&client).await,
+            CliCommand::ImportCorectrl {
+                ref path,
+                ref name,
+                dry_run,
+            } => import_corectrl(&client, path, name.clone(), dry_run).await,
+            CliCommand::ExportScript {
+                ref profile,
+                format,
+            } => export_script(&args, &client, profile.clone(), format).await,
+            CliCommand::Run {
+                ref profile,
+                timeout_secs,
+                ref command,
+            } => run_with_profile(&args, &client, profile.clone(), timeout_secs, command).await,
         }
     })
 }
@@ -70,3 +88,72 @@ async fn snapshot(client: &DaemonClient) -> Result<()> {
     println!("Generated debug snapshot in {path}");
     Ok(())
 }
+
+async fn import_corectrl(
+    client: &DaemonClient,
+    path: &Path,
+    name: Option<String>,
+    dry_run: bool,
+) -> Result<()> {
+    let data = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+    let result = client.import_corectrl_profile(data, name, dry_run).await?;
+
+    if dry_run {
+        println!("{}", serde_yml::to_string(&result.profile)?);
+    } else {
+        println!("Created profile '{}'", result.name);
+    }
+
+    if !result.unmapped.is_empty() {
+        println!("The following settings could not be imported:");
+        for item in &result.unmapped {
+            println!("- {item}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn export_script(
+    args: &CliArgs,
+    client: &DaemonClient,
+    profile: Option<String>,
+    format: ApplyScriptFormat,
+) -> Result<()> {
+    let ids = extract_gpu_ids(args, client).await;
+    let id = match ids.as_slice() {
+        [] => bail!("No GPUs found"),
+        [id] => id,
+        _ => bail!("Multiple GPUs are available, select one with --gpu-id"),
+    };
+
+    let script = client.export_apply_script(id, profile, format).await?;
+    print!("{script}");
+    Ok(())
+}
+
+async fn run_with_profile(
+    args: &CliArgs,
+    client: &DaemonClient,
+    profile: String,
+    timeout_secs: Option<u64>,
+    command: &[String],
+) -> Result<()> {
+    let [program, program_args @ ..] = command else {
+        bail!("No command specified");
+    };
+
+    // The daemon also releases the lease if this process gets killed and the connection is closed
+    let lease_id = client
+        .acquire_profile_lease(Some(profile), args.gpu_id.clone(), timeout_secs)
+        .await?;
+
+    let status = process::Command::new(program).args(program_args).status();
+    client.release_profile_lease(lease_id).await?;
+
+    let status = status.with_context(|| format!("Could not run {program}"))?;
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
@@ -4,8 +4,8 @@ mod macros;
 
 pub use lact_schema as schema;
 use lact_schema::{
-    config::{GpuConfig, Profile, ProfileHooks},
-    ProcessList, ProfileRule,
+    config::{GpuConfig, Profile, ProfileHooks, ProfileTiming},
+    HookResult, ProcessList, ProfileRule, ProfileSelectionExplanation, ProfileSwitch,
 };
 
 use amdgpu_sysfs::gpu_handle::power_profile_mode::PowerProfileModesTable;
@@ -14,8 +14,8 @@ use connection::{tcp::TcpConnection, unix::UnixConnection, DaemonConnection};
 use nix::unistd::getuid;
 use schema::{
     request::{ConfirmCommand, ProfileBase, SetClocksCommand},
-    ClocksInfo, DeviceInfo, DeviceListEntry, DeviceStats, PowerStates, ProfilesInfo, Request,
-    Response, SystemInfo,
+    ApplyScriptFormat, ClocksInfo, DeviceInfo, DeviceListEntry, DeviceStats, PowerStates,
+    ProfileImportResult, ProfilesInfo, Request, Response, SystemInfo,
 };
 use serde::de::DeserializeOwned;
 use std::{
@@ -152,8 +152,45 @@ impl DaemonClient {
     }
 
     pub async fn set_profile(&self, name: Option<String>, auto_switch: bool) -> anyhow::Result<()> {
-        self.make_request(Request::SetProfile { name, auto_switch })
-            .await
+        self.make_request(Request::SetProfile {
+            name,
+            auto_switch,
+            gpu_id: None,
+        })
+        .await
+    }
+
+    /// Activates a profile only for the given GPU. `None` makes the GPU follow the current profile again.
+    pub async fn set_gpu_profile(
+        &self,
+        gpu_id: String,
+        name: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.make_request(Request::SetProfile {
+            name,
+            auto_switch: false,
+            gpu_id: Some(gpu_id),
+        })
+        .await
+    }
+
+    /// The profile stays active until the lease is released, this client disconnects or the timeout passes
+    pub async fn acquire_profile_lease(
+        &self,
+        name: Option<String>,
+        gpu_id: Option<String>,
+        timeout_secs: Option<u64>,
+    ) -> anyhow::Result<u64> {
+        self.make_request(Request::AcquireProfileLease {
+            name,
+            gpu_id,
+            timeout_secs,
+        })
+        .await
+    }
+
+    pub async fn release_profile_lease(&self, id: u64) -> anyhow::Result<bool> {
+        self.make_request(Request::ReleaseProfileLease { id }).await
     }
 
     pub async fn create_profile(&self, name: String, base: ProfileBase) -> anyhow::Result<()> {
@@ -175,6 +212,18 @@ impl DaemonClient {
             .await
     }
 
+    pub async fn explain_profile_selection(&self) -> anyhow::Result<ProfileSelectionExplanation> {
+        self.make_request(Request::ExplainProfileSelection).await
+    }
+
+    pub async fn list_hook_results(&self) -> anyhow::Result<Vec<HookResult>> {
+        self.make_request(Request::ListHookResults).await
+    }
+
+    pub async fn list_profile_switches(&self) -> anyhow::Result<Vec<ProfileSwitch>> {
+        self.make_request(Request::ListProfileSwitches).await
+    }
+
     pub async fn get_gpu_config(&self, id: &str) -> anyhow::Result<Option<GpuConfig>> {
         self.make_request(Request::GetGpuConfig { id }).await
     }
@@ -197,10 +246,46 @@ impl DaemonClient {
         &self,
         name: String,
         rule: Option<ProfileRule>,
-        hooks: ProfileHooks,
+        hooks: Option<ProfileHooks>,
+        timing: Option<ProfileTiming>,
+        gpu_ids: Option<Vec<String>>,
     ) -> anyhow::Result<()> {
-        self.make_request(Request::SetProfileRule { name, rule, hooks })
-            .await
+        self.make_request(Request::SetProfileRule {
+            name,
+            rule,
+            hooks,
+            timing,
+            gpu_ids,
+        })
+        .await
+    }
+
+    pub async fn export_apply_script(
+        &self,
+        id: &str,
+        profile: Option<String>,
+        format: ApplyScriptFormat,
+    ) -> anyhow::Result<String> {
+        self.make_request(Request::ExportApplyScript {
+            id,
+            profile,
+            format,
+        })
+        .await
+    }
+
+    pub async fn import_corectrl_profile(
+        &self,
+        data: Vec<u8>,
+        name: Option<String>,
+        dry_run: bool,
+    ) -> anyhow::Result<ProfileImportResult> {
+        self.make_request(Request::ImportCorectrlProfile {
+            data,
+            name,
+            dry_run,
+        })
+        .await
     }
 
     pub async fn confirm_pending_config(&self, command: ConfirmCommand) -> anyhow::Result<()> {
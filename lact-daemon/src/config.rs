@@ -1,7 +1,11 @@
+mod document;
+
 use crate::server::gpu_controller::{GpuController, VENDOR_NVIDIA};
 use anyhow::Context;
 use indexmap::IndexMap;
-use lact_schema::config::{GpuConfig, Profile, ProfileHooks};
+use lact_schema::config::{
+    ClocksPreset, FanCurve, GpuConfig, Profile, ProfileHooks, ProfileTiming,
+};
 use nix::unistd::{getuid, Group};
 use notify::{RecommendedWatcher, Watcher};
 use serde::{Deserialize, Serialize};
@@ -35,8 +39,17 @@ pub struct Config {
     gpus: IndexMap<String, GpuConfig>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     pub profiles: IndexMap<Rc<str>, Profile>,
+    /// Named fan curves that can be referenced from GPU configs
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub fan_curves: IndexMap<String, FanCurve>,
+    /// Named clock and power presets that can be referenced from GPU configs
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub clocks_presets: IndexMap<String, ClocksPreset>,
     #[serde(default)]
     pub current_profile: Option<Rc<str>>,
+    /// Profiles that are active on specific GPUs instead of the current profile
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub current_gpu_profiles: IndexMap<String, Rc<str>>,
     #[serde(default)]
     pub auto_switch_profiles: bool,
 }
@@ -48,7 +61,10 @@ impl Default for Config {
             apply_settings_timer: default_apply_settings_timer(),
             gpus: IndexMap::new(),
             profiles: IndexMap::new(),
+            fan_curves: IndexMap::new(),
+            clocks_presets: IndexMap::new(),
             current_profile: None,
+            current_gpu_profiles: IndexMap::new(),
             auto_switch_profiles: false,
             version: 5,
         }
@@ -94,7 +110,7 @@ impl Config {
         let path = get_path(FILE_NAME);
         if path.exists() {
             let raw_config = fs::read_to_string(path).context("Could not open config file")?;
-            let config =
+            let config: Self =
                 serde_yml::from_str(&raw_config).context("Could not deserialize config")?;
             Ok(Some(config))
         } else {
@@ -119,7 +135,16 @@ impl Config {
 
         #[cfg(not(test))]
         {
-            let raw_config = serde_yml::to_string(self)?;
+            let mut raw_config = serde_yml::to_string(self)?;
+
+            // Keep comments and formatting in the existing file
+            if let Ok(original) = fs::read_to_string(&path) {
+                match document::update_in_place(&original, &raw_config) {
+                    Some(updated) => raw_config = updated,
+                    None => debug!("could not update config in place, rewriting the whole file"),
+                }
+            }
+
             fs::write(path, raw_config).context("Could not write config")?;
         }
 
@@ -226,7 +251,47 @@ impl Config {
 
     /// Gets the GPU configs according to the current profile. Returns an error if the current profile could not be found.
     pub fn gpus(&self) -> anyhow::Result<&IndexMap<String, GpuConfig>> {
-        match &self.current_profile {
+        self.profile_gpus(self.current_profile.as_deref())
+    }
+
+    /// Same as [`gpus`], but with a mutable reference
+    pub fn gpus_mut(&mut self) -> anyhow::Result<&mut IndexMap<String, GpuConfig>> {
+        let profile = self.current_profile.clone();
+        self.profile_gpus_mut(profile.as_deref())
+    }
+
+    /// Gets the profile that is in effect for the given GPU
+    pub fn gpu_profile(&self, id: &str) -> Option<&Rc<str>> {
+        self.current_gpu_profiles
+            .get(id)
+            .or(self.current_profile.as_ref())
+    }
+
+    /// Activates a profile for a single GPU, or makes it follow the current profile again when `None` is passed
+    pub fn set_gpu_profile(&mut self, id: &str, profile: Option<Rc<str>>) {
+        match profile {
+            Some(profile) => {
+                self.current_gpu_profiles.insert(id.to_owned(), profile);
+            }
+            None => {
+                self.current_gpu_profiles.shift_remove(id);
+            }
+        }
+    }
+
+    /// Gets the GPU configs of the profile that is in effect for the given GPU
+    pub fn gpus_for(&self, id: &str) -> anyhow::Result<&IndexMap<String, GpuConfig>> {
+        self.profile_gpus(self.gpu_profile(id).map(|profile| &**profile))
+    }
+
+    /// Same as [`gpus_for`], but with a mutable reference
+    pub fn gpus_for_mut(&mut self, id: &str) -> anyhow::Result<&mut IndexMap<String, GpuConfig>> {
+        let profile = self.gpu_profile(id).cloned();
+        self.profile_gpus_mut(profile.as_deref())
+    }
+
+    fn profile_gpus(&self, profile: Option<&str>) -> anyhow::Result<&IndexMap<String, GpuConfig>> {
+        match profile {
             Some(profile) => {
                 let profile = self
                     .profiles
@@ -238,9 +303,11 @@ impl Config {
         }
     }
 
-    /// Same as [`gpus`], but with a mutable reference
-    pub fn gpus_mut(&mut self) -> anyhow::Result<&mut IndexMap<String, GpuConfig>> {
-        match &self.current_profile {
+    fn profile_gpus_mut(
+        &mut self,
+        profile: Option<&str>,
+    ) -> anyhow::Result<&mut IndexMap<String, GpuConfig>> {
+        match profile {
             Some(profile) => {
                 let profile = self
                     .profiles
@@ -252,6 +319,57 @@ impl Config {
         }
     }
 
+    /// GPUs whose profile can differ from the current profile, either because a profile is limited to them or because one was activated for them specifically
+    pub fn scoped_gpu_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.current_gpu_profiles.keys().cloned().collect();
+        for profile in self.profiles.values() {
+            for id in &profile.gpu_ids {
+                if !ids.contains(id) {
+                    ids.push(id.clone());
+                }
+            }
+        }
+        ids
+    }
+
+    /// Gets the effective settings for a GPU, with all preset references resolved
+    pub fn resolve_gpu_config(&self, gpu_config: &GpuConfig) -> anyhow::Result<GpuConfig> {
+        let mut resolved = gpu_config.clone();
+
+        if let Some(preset_name) = &gpu_config.clocks_preset {
+            let preset = self
+                .clocks_presets
+                .get(preset_name)
+                .with_context(|| format!("Clocks preset '{preset_name}' not found"))?;
+
+            resolved
+                .clocks_configuration
+                .merge_base(&preset.clocks_configuration);
+            resolved.power_cap = resolved.power_cap.or(preset.power_cap);
+            resolved.performance_level = resolved.performance_level.or(preset.performance_level);
+        }
+
+        if let Some(fan_settings) = &mut resolved.fan_control_settings {
+            if let Some(curve_name) = &fan_settings.curve_preset {
+                let curve = self
+                    .fan_curves
+                    .get(curve_name)
+                    .with_context(|| format!("Fan curve '{curve_name}' not found"))?;
+                fan_settings.curve = curve.clone();
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    /// Gets the effective settings for a GPU in the profile that is active for it
+    pub fn resolved_gpu(&self, id: &str) -> anyhow::Result<Option<GpuConfig>> {
+        self.gpus_for(id)?
+            .get(id)
+            .map(|gpu_config| self.resolve_gpu_config(gpu_config))
+            .transpose()
+    }
+
     /// Get a specific profile
     pub fn profile(&self, profile: &str) -> anyhow::Result<&Profile> {
         self.profiles
@@ -265,13 +383,18 @@ impl Config {
             gpus: self.gpus.clone(),
             rule: None,
             hooks: ProfileHooks::default(),
+            timing: ProfileTiming::default(),
+            gpu_ids: Vec::new(),
         }
     }
 
     pub fn clear(&mut self) {
         self.gpus.clear();
         self.profiles.clear();
+        self.fan_curves.clear();
+        self.clocks_presets.clear();
         self.current_profile = None;
+        self.current_gpu_profiles.clear();
     }
 }
 
@@ -397,7 +520,10 @@ mod tests {
     use indexmap::IndexMap;
     use insta::assert_yaml_snapshot;
     use lact_schema::{
-        config::{ClocksConfiguration, FanControlSettings, FanCurve, GpuConfig},
+        config::{
+            ClocksConfiguration, ClocksPreset, FanControlSettings, FanCurve, FanCurveInterpolation,
+            FanSensorCurve, GpuConfig, Profile,
+        },
         FanControlMode, PmfwOptions,
     };
     use std::collections::BTreeMap;
@@ -412,13 +538,27 @@ mod tests {
                     fan_control_enabled: true,
                     fan_control_settings: Some(FanControlSettings {
                         curve: FanCurve::default(),
+                        interpolation: FanCurveInterpolation::MonotoneCubic,
                         temperature_key: "edge".to_owned(),
                         interval_ms: 500,
                         mode: FanControlMode::Curve,
                         static_speed: 0.5,
+                        curve_preset: None,
                         spindown_delay_ms: Some(5000),
                         change_threshold: Some(3),
                         auto_threshold: Some(40),
+                        falling_curve: None,
+                        hysteresis: Some(4),
+                        extra_sensors: vec![FanSensorCurve {
+                            temperature_key: "mem".to_owned(),
+                            hwmon: None,
+                            curve: None,
+                            offset: 10,
+                        }],
+                        pid: None,
+                        max_speed_increase: Some(0.1),
+                        max_speed_decrease: Some(0.02),
+                        temperature_smoothing_ms: Some(2000),
                     }),
                     ..Default::default()
                 },
@@ -457,6 +597,7 @@ mod tests {
             power_cap: None,
             performance_level: None,
             clocks_configuration: ClocksConfiguration::default(),
+            clocks_preset: None,
             power_profile_mode_index: None,
             custom_power_profile_mode_hueristics: vec![],
             power_states: IndexMap::new(),
@@ -498,7 +639,10 @@ mod tests {
                 ),
             ]),
             profiles: IndexMap::new(),
+            fan_curves: IndexMap::new(),
+            clocks_presets: IndexMap::new(),
             current_profile: None,
+            current_gpu_profiles: IndexMap::new(),
             auto_switch_profiles: false,
         };
 
@@ -532,4 +676,104 @@ mod tests {
             Some(920),
         );
     }
+
+    #[test]
+    fn resolve_presets() {
+        let config = Config {
+            fan_curves: IndexMap::from([(
+                "silent".to_owned(),
+                FanCurve(BTreeMap::from([(40, 0.2), (80, 0.6)])),
+            )]),
+            clocks_presets: IndexMap::from([(
+                "balanced".to_owned(),
+                ClocksPreset {
+                    clocks_configuration: ClocksConfiguration {
+                        max_core_clock: Some(2000),
+                        max_memory_clock: Some(1000),
+                        ..Default::default()
+                    },
+                    power_cap: Some(250.0),
+                    performance_level: None,
+                },
+            )]),
+            ..Default::default()
+        };
+
+        let gpu_config = GpuConfig {
+            fan_control_enabled: true,
+            fan_control_settings: Some(FanControlSettings {
+                curve_preset: Some("silent".to_owned()),
+                ..Default::default()
+            }),
+            clocks_configuration: ClocksConfiguration {
+                max_core_clock: Some(2100),
+                ..Default::default()
+            },
+            clocks_preset: Some("balanced".to_owned()),
+            ..Default::default()
+        };
+
+        let resolved = config.resolve_gpu_config(&gpu_config).unwrap();
+        assert_eq!(
+            resolved.fan_control_settings.unwrap().curve,
+            FanCurve(BTreeMap::from([(40, 0.2), (80, 0.6)]))
+        );
+        assert_eq!(resolved.clocks_configuration.max_core_clock, Some(2100));
+        assert_eq!(resolved.clocks_configuration.max_memory_clock, Some(1000));
+        assert_eq!(resolved.power_cap, Some(250.0));
+
+        let missing_preset = GpuConfig {
+            clocks_preset: Some("benchmark".to_owned()),
+            ..Default::default()
+        };
+        assert!(config.resolve_gpu_config(&missing_preset).is_err());
+    }
+
+    #[test]
+    fn gpu_profiles() {
+        let gpu_config = |power_cap| GpuConfig {
+            power_cap: Some(power_cap),
+            ..Default::default()
+        };
+
+        let mut config = Config::default();
+        config.profiles.insert(
+            "gaming".into(),
+            Profile {
+                gpus: IndexMap::from([
+                    ("display".to_owned(), gpu_config(200.0)),
+                    ("compute".to_owned(), gpu_config(200.0)),
+                ]),
+                ..Default::default()
+            },
+        );
+        config.profiles.insert(
+            "render".into(),
+            Profile {
+                gpus: IndexMap::from([("compute".to_owned(), gpu_config(300.0))]),
+                gpu_ids: vec!["compute".to_owned()],
+                ..Default::default()
+            },
+        );
+        config.current_profile = Some("gaming".into());
+        assert_eq!(vec!["compute".to_owned()], config.scoped_gpu_ids());
+
+        config.set_gpu_profile("compute", Some("render".into()));
+        assert_eq!(
+            Some("render"),
+            config.gpu_profile("compute").map(|profile| &**profile)
+        );
+        assert_eq!(
+            Some("gaming"),
+            config.gpu_profile("display").map(|profile| &**profile)
+        );
+
+        let power_cap = |config: &Config, id| config.resolved_gpu(id).unwrap().unwrap().power_cap;
+        assert_eq!(Some(300.0), power_cap(&config, "compute"));
+        assert_eq!(Some(200.0), power_cap(&config, "display"));
+
+        config.set_gpu_profile("compute", None);
+        assert_eq!(Some(200.0), power_cap(&config, "compute"));
+        assert!(config.current_gpu_profiles.is_empty());
+    }
 }
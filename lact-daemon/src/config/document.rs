@@ -0,0 +1,440 @@
+//! In-place updates of the YAML config file.
+//!
+//! Instead of re-serializing the whole config on every save, the existing document is walked
+//! alongside the new config, and only the entries that actually changed are rewritten.
+//! This keeps comments, blank lines and key ordering that were written by hand.
+use serde_yml::{Mapping, Value};
+
+/// Creates a document with the contents of `new_raw`, reusing the text of `original` for every entry that did not change.
+///
+/// Returns `None` if the original document uses a structure that cannot be safely updated in place
+/// (flow mappings at the top level, multiple documents, etc.), in which case the new document should be written as-is.
+pub fn update_in_place(original: &str, new_raw: &str) -> Option<String> {
+    let old_value: Value = serde_yml::from_str(original).ok()?;
+    let new_value: Value = serde_yml::from_str(new_raw).ok()?;
+
+    let (Value::Mapping(old_map), Value::Mapping(new_map)) = (&old_value, &new_value) else {
+        return None;
+    };
+
+    let lines: Vec<&str> = original.lines().collect();
+    let mut output = Vec::with_capacity(lines.len());
+    patch_mapping(&lines, old_map, new_map, &mut output)?;
+
+    let mut updated = output.join("\n");
+    updated.push('\n');
+
+    // The result must be exactly equivalent to the new config, otherwise it's not safe to use
+    let updated_value: Value = serde_yml::from_str(&updated).ok()?;
+    (updated_value == new_value).then_some(updated)
+}
+
+/// A single `key: value` entry of a block mapping
+struct Entry<'a> {
+    key: Value,
+    /// Index of the first comment or blank line right before the entry
+    leading_start: usize,
+    /// Index of the line with the key
+    start: usize,
+    /// Index after the last line of the entry's value
+    end: usize,
+    /// Value written on the same line as the key
+    inline_value: &'a str,
+    /// Comment on the same line as the key
+    comment: Option<&'a str>,
+}
+
+fn patch_mapping(
+    lines: &[&str],
+    old_map: &Mapping,
+    new_map: &Mapping,
+    output: &mut Vec<String>,
+) -> Option<()> {
+    let (indent, entries, trailing_start) = parse_entries(lines)?;
+    if entries.len() != old_map.len() {
+        return None;
+    }
+
+    // New keys are placed after the closest preceding key that already exists in the document
+    let new_keys: Vec<&Value> = new_map.iter().map(|(key, _)| key).collect();
+    let mut inserted: Vec<(Option<usize>, &Value, &Value)> = Vec::new();
+    for (position, (key, value)) in new_map.iter().enumerate() {
+        if entries.iter().any(|entry| entry.key == *key) {
+            continue;
+        }
+
+        let anchor = new_keys[..position]
+            .iter()
+            .rev()
+            .find_map(|previous_key| entries.iter().position(|entry| entry.key == **previous_key));
+        inserted.push((anchor, key, value));
+    }
+
+    for (_, key, value) in inserted.iter().filter(|(anchor, _, _)| anchor.is_none()) {
+        output.extend(render_entry(key, value, indent, None)?);
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        let old_value = lookup(old_map, &entry.key)?;
+
+        if let Some(new_value) = lookup(new_map, &entry.key) {
+            output.extend(
+                lines[entry.leading_start..entry.start]
+                    .iter()
+                    .map(|line| (*line).to_owned()),
+            );
+
+            if old_value == new_value {
+                output.extend(
+                    lines[entry.start..entry.end]
+                        .iter()
+                        .map(|line| (*line).to_owned()),
+                );
+            } else {
+                patch_entry(lines, entry, indent, old_value, new_value, output)?;
+            }
+        }
+
+        for (_, key, value) in inserted.iter().filter(|(anchor, _, _)| *anchor == Some(i)) {
+            output.extend(render_entry(key, value, indent, None)?);
+        }
+    }
+
+    output.extend(
+        lines[trailing_start..]
+            .iter()
+            .map(|line| (*line).to_owned()),
+    );
+
+    Some(())
+}
+
+fn patch_entry(
+    lines: &[&str],
+    entry: &Entry,
+    indent: usize,
+    old_value: &Value,
+    new_value: &Value,
+    output: &mut Vec<String>,
+) -> Option<()> {
+    if let (Value::Mapping(old_inner), Value::Mapping(new_inner)) = (old_value, new_value) {
+        if entry.inline_value.is_empty() && !new_inner.is_empty() {
+            let mut inner_output = Vec::new();
+            if patch_mapping(
+                &lines[entry.start + 1..entry.end],
+                old_inner,
+                new_inner,
+                &mut inner_output,
+            )
+            .is_some()
+            {
+                output.push(lines[entry.start].to_owned());
+                output.extend(inner_output);
+                return Some(());
+            }
+        }
+    }
+
+    output.extend(render_entry(&entry.key, new_value, indent, entry.comment)?);
+    Some(())
+}
+
+fn render_entry(
+    key: &Value,
+    value: &Value,
+    indent: usize,
+    comment: Option<&str>,
+) -> Option<Vec<String>> {
+    let mut mapping = Mapping::new();
+    mapping.insert(key.clone(), value.clone());
+    let rendered = serde_yml::to_string(&mapping).ok()?;
+
+    let padding = " ".repeat(indent);
+    let mut lines: Vec<String> = rendered
+        .lines()
+        .filter(|line| *line != "---")
+        .map(|line| format!("{padding}{line}"))
+        .collect();
+
+    if let (Some(comment), [line]) = (comment, lines.as_mut_slice()) {
+        line.push(' ');
+        line.push_str(comment);
+    }
+
+    Some(lines)
+}
+
+/// Parses the entries of a block mapping.
+/// Returns the indentation of the mapping, its entries and the index where the trailing comments start.
+fn parse_entries<'a>(lines: &[&'a str]) -> Option<(usize, Vec<Entry<'a>>, usize)> {
+    let mut entries: Vec<Entry<'a>> = Vec::new();
+    let mut mapping_indent = None;
+    let mut leading_start = 0;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        if is_trivia(line) {
+            i += 1;
+            continue;
+        }
+
+        let indent = indentation(line);
+        if indent != *mapping_indent.get_or_insert(indent) {
+            return None;
+        }
+
+        let (key_text, rest) = split_key(&line[indent..])?;
+        let key = parse_key(key_text)?;
+        if entries.iter().any(|entry| entry.key == key) {
+            return None;
+        }
+        let (inline_value, comment) = split_comment(rest);
+
+        let start = i;
+        let mut end = i + 1;
+        i += 1;
+
+        while i < lines.len() {
+            let line = lines[i];
+            if is_trivia(line) {
+                i += 1;
+                continue;
+            }
+
+            let line_indent = indentation(line);
+            // Block sequences are allowed to have the same indentation as their parent key
+            if line_indent > indent
+                || (line_indent == indent
+                    && inline_value.is_empty()
+                    && is_sequence_item(&line[indent..]))
+            {
+                i += 1;
+                end = i;
+            } else {
+                break;
+            }
+        }
+
+        entries.push(Entry {
+            key,
+            leading_start,
+            start,
+            end,
+            inline_value,
+            comment,
+        });
+
+        // Comments after an entry are attached to the next one
+        leading_start = end;
+        i = end;
+    }
+
+    Some((mapping_indent.unwrap_or(0), entries, leading_start))
+}
+
+fn lookup<'a>(map: &'a Mapping, key: &Value) -> Option<&'a Value> {
+    map.iter()
+        .find_map(|(map_key, value)| (map_key == key).then_some(value))
+}
+
+fn is_trivia(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+fn is_sequence_item(content: &str) -> bool {
+    content == "-" || content.starts_with("- ")
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Splits a `key: value` line into the key and the rest of the line
+fn split_key(content: &str) -> Option<(&str, &str)> {
+    let key_end = match content.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let mut escaped = false;
+            let closing = content.char_indices().skip(1).find(|(_, c)| {
+                let found = *c == quote && !escaped;
+                escaped = quote == '"' && *c == '\\' && !escaped;
+                found
+            })?;
+            let key_end = closing.0 + 1;
+            if !content[key_end..].starts_with(':') {
+                return None;
+            }
+            key_end
+        }
+        // Sequences, anchors, aliases, tags and complex keys are not supported
+        '-' | '&' | '*' | '!' | '?' | '{' | '[' | '|' | '>' => return None,
+        _ => {
+            content
+                .char_indices()
+                .find(|(i, c)| {
+                    *c == ':'
+                        && content[i + 1..]
+                            .chars()
+                            .next()
+                            .is_none_or(|next| next == ' ')
+                })?
+                .0
+        }
+    };
+
+    let key = &content[..key_end];
+    if key == "<<" {
+        return None;
+    }
+
+    Some((key, &content[key_end + 1..]))
+}
+
+fn parse_key(key_text: &str) -> Option<Value> {
+    match serde_yml::from_str(key_text).ok()? {
+        Value::Mapping(_) | Value::Sequence(_) | Value::Tagged(_) => None,
+        key => Some(key),
+    }
+}
+
+/// Splits the text after a key into the inline value and the comment
+fn split_comment(rest: &str) -> (&str, Option<&str>) {
+    let mut quote = None;
+    let mut previous = ' ';
+
+    for (i, c) in rest.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && (previous == ' ' || previous == '\t') => {
+                return (rest[..i].trim(), Some(&rest[i..]));
+            }
+            _ => (),
+        }
+        previous = c;
+    }
+
+    (rest.trim(), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::update_in_place;
+    use pretty_assertions::assert_eq;
+
+    const ORIGINAL: &str = r"# Managed by the infra team
+daemon:
+  log_level: info # change to debug when troubleshooting
+  admin_group: wheel
+
+# GPU settings
+gpus:
+  1002:687F-1043:0555-0000:0b:00.0:
+    # Keep this quiet
+    fan_control_enabled: true
+    power_cap: 200.0
+    custom_power_profile_mode_hueristics:
+    - - 0
+      - 5
+
+profiles:
+  game:
+    rule:
+      type: process
+      filter:
+        name: game
+";
+
+    #[test]
+    fn unchanged_document() {
+        assert_eq!(
+            ORIGINAL,
+            update_in_place(ORIGINAL, ORIGINAL).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn update_nested_value() {
+        let new = r"
+daemon:
+  log_level: info
+  admin_group: wheel
+gpus:
+  1002:687F-1043:0555-0000:0b:00.0:
+    fan_control_enabled: true
+    power_cap: 250.0
+    custom_power_profile_mode_hueristics:
+    - - 0
+      - 5
+profiles:
+  game:
+    rule:
+      type: process
+      filter:
+        name: game
+";
+        let expected = ORIGINAL.replace("power_cap: 200.0", "power_cap: 250.0");
+        assert_eq!(expected, update_in_place(ORIGINAL, new).unwrap());
+    }
+
+    #[test]
+    fn keep_inline_comment() {
+        let new = ORIGINAL.replace(
+            "log_level: info # change to debug when troubleshooting",
+            "log_level: debug",
+        );
+        let expected = ORIGINAL.replace("log_level: info", "log_level: debug");
+        assert_eq!(expected, update_in_place(ORIGINAL, &new).unwrap());
+    }
+
+    #[test]
+    fn add_and_remove_entries() {
+        let new = r"
+version: 5
+daemon:
+  log_level: info
+  admin_group: wheel
+gpus:
+  1002:687F-1043:0555-0000:0b:00.0:
+    fan_control_enabled: true
+    power_cap: 200.0
+    custom_power_profile_mode_hueristics:
+    - - 0
+      - 5
+profiles:
+  benchmark: {}
+current_profile: benchmark
+";
+        let expected = r"version: 5
+# Managed by the infra team
+daemon:
+  log_level: info # change to debug when troubleshooting
+  admin_group: wheel
+
+# GPU settings
+gpus:
+  1002:687F-1043:0555-0000:0b:00.0:
+    # Keep this quiet
+    fan_control_enabled: true
+    power_cap: 200.0
+    custom_power_profile_mode_hueristics:
+    - - 0
+      - 5
+
+profiles:
+  benchmark: {}
+current_profile: benchmark
+";
+        assert_eq!(expected, update_in_place(ORIGINAL, new).unwrap());
+    }
+
+    #[test]
+    fn unsupported_document() {
+        assert_eq!(
+            None,
+            update_in_place("{daemon: {log_level: info}}", "daemon: {}")
+        );
+        assert_eq!(None, update_in_place("", "daemon: {}"));
+    }
+}
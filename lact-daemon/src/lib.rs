@@ -122,6 +122,7 @@ async fn listen_config_changes(handler: Handler) {
     let mut rx = config::start_watcher(handler.config_last_saved.clone());
     while let Some(new_config) = rx.recv().await {
         info!("config file was changed, reloading");
+        handler.compile_profile_rules(&new_config);
         *handler.config.write().await = new_config;
         match handler.apply_current_config().await {
             Ok(()) => {
@@ -1,6 +1,9 @@
+mod corectrl;
 pub mod gpu_controller;
 pub mod handler;
+mod hooks;
 mod opencl;
+mod profile_leases;
 mod profiles;
 mod vulkan;
 
@@ -15,7 +18,7 @@ use tokio::{
     io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
     net::{TcpListener, UnixListener},
 };
-use tracing::{error, info, instrument, trace};
+use tracing::{debug, error, info, instrument, trace};
 
 pub struct Server {
     pub handler: Handler,
@@ -108,6 +111,25 @@ impl Server {
 pub async fn handle_stream<T: AsyncRead + AsyncWrite + Unpin>(
     stream: T,
     handler: Handler,
+) -> anyhow::Result<()> {
+    let mut leases = Vec::new();
+    let result = serve_requests(stream, &handler, &mut leases).await;
+
+    // Profile leases only last as long as the connection they were acquired over
+    for id in leases {
+        match handler.release_profile_lease(id).await {
+            Ok(released) => debug!("connection closed, released profile lease {id}: {released}"),
+            Err(err) => error!("could not release profile lease {id}: {err:#}"),
+        }
+    }
+
+    result
+}
+
+async fn serve_requests<T: AsyncRead + AsyncWrite + Unpin>(
+    stream: T,
+    handler: &Handler,
+    leases: &mut Vec<u64>,
 ) -> anyhow::Result<()> {
     let mut stream = BufReader::new(stream);
 
@@ -117,7 +139,7 @@ pub async fn handle_stream<T: AsyncRead + AsyncWrite + Unpin>(
 
         let maybe_request = serde_json::from_str(&buf);
         let response = match maybe_request {
-            Ok(request) => match handle_request(request, &handler).await {
+            Ok(request) => match handle_request(request, handler, leases).await {
                 Ok(response) => response,
                 Err(error) => serde_json::to_vec(&Response::<()>::from(error))?,
             },
@@ -136,7 +158,11 @@ pub async fn handle_stream<T: AsyncRead + AsyncWrite + Unpin>(
 }
 
 #[instrument(level = "debug", skip(handler))]
-async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyhow::Result<Vec<u8>> {
+async fn handle_request<'a>(
+    request: Request<'a>,
+    handler: &'a Handler,
+    leases: &mut Vec<u64>,
+) -> anyhow::Result<Vec<u8>> {
     match request {
         Request::Ping => ok_response(ping()),
         Request::SystemInfo => ok_response(system::info().await?),
@@ -180,11 +206,30 @@ async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyho
         Request::GetProfile { name } => {
             ok_response(handler.get_profile(name.map(Into::into)).await?)
         }
-        Request::SetProfile { name, auto_switch } => ok_response(
+        Request::SetProfile {
+            name,
+            auto_switch,
+            gpu_id,
+        } => ok_response(
             handler
-                .set_profile(name.map(Into::into), auto_switch)
+                .set_profile(name.map(Into::into), auto_switch, gpu_id)
                 .await?,
         ),
+        Request::AcquireProfileLease {
+            name,
+            gpu_id,
+            timeout_secs,
+        } => {
+            let id = handler
+                .acquire_profile_lease(name.map(Into::into), gpu_id, timeout_secs)
+                .await?;
+            leases.push(id);
+            ok_response(id)
+        }
+        Request::ReleaseProfileLease { id } => {
+            leases.retain(|lease| *lease != id);
+            ok_response(handler.release_profile_lease(id).await?)
+        }
         Request::CreateProfile { name, base } => {
             ok_response(handler.create_profile(name, base).await?)
         }
@@ -193,9 +238,34 @@ async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyho
             ok_response(handler.move_profile(&name, new_position).await?)
         }
         Request::EvaluateProfileRule { rule } => ok_response(handler.evaluate_profile_rule(&rule)?),
-        Request::SetProfileRule { name, rule, hooks } => {
-            ok_response(handler.set_profile_rule(&name, rule, hooks).await?)
-        }
+        Request::ExplainProfileSelection => ok_response(handler.explain_profile_selection().await?),
+        Request::ListHookResults => ok_response(handler.list_hook_results()),
+        Request::ListProfileSwitches => ok_response(handler.list_profile_switches()),
+        Request::SetProfileRule {
+            name,
+            rule,
+            hooks,
+            timing,
+            gpu_ids,
+        } => ok_response(
+            handler
+                .set_profile_rule(&name, rule, hooks, timing, gpu_ids)
+                .await?,
+        ),
+        Request::ImportCorectrlProfile {
+            data,
+            name,
+            dry_run,
+        } => ok_response(
+            handler
+                .import_corectrl_profile(&data, name, dry_run)
+                .await?,
+        ),
+        Request::ExportApplyScript {
+            id,
+            profile,
+            format,
+        } => ok_response(handler.export_apply_script(id, profile, format).await?),
         Request::GetGpuConfig { id } => ok_response(handler.get_gpu_config(id).await?),
         Request::SetGpuConfig { id, config } => {
             ok_response(handler.set_gpu_config(id, config).await?)
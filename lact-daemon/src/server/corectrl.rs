@@ -0,0 +1,684 @@
+//! Conversion of `CoreCtrl` profiles into LACT profiles.
+//!
+//! `CoreCtrl` stores each profile as a `.ccpro` zip archive containing the profile XML and an icon.
+//! Settings are stored as a tree of components with an `active` flag, where only AMD GPU components have an equivalent in LACT.
+//!
+//! Profiles can also be given as JSON with the same tree, which is converted to the XML layout before importing.
+use amdgpu_sysfs::gpu_handle::{PerformanceLevel, PowerLevelKind};
+use anyhow::{bail, ensure, Context};
+use lact_schema::{
+    config::{FanControlSettings, FanCurve, GpuConfig, Profile},
+    FanControlMode, ProcessProfileRule, ProfileImportResult, ProfileRule,
+};
+use libflate::deflate;
+use roxmltree::{Document, Node};
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, io::Read};
+
+const ZIP_LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+
+/// A GPU in the system that imported settings can be applied to
+pub struct ImportTarget {
+    pub id: String,
+    /// PCI device id in hex
+    pub device_id: String,
+    pub power_profile_modes: Vec<(u16, String)>,
+}
+
+pub fn import_profile(
+    data: &[u8],
+    targets: &[ImportTarget],
+) -> anyhow::Result<ProfileImportResult> {
+    let mut xml = if data.starts_with(&ZIP_LOCAL_HEADER_SIGNATURE.to_le_bytes()) {
+        extract_profile_xml(data)?
+    } else {
+        String::from_utf8(data.to_vec()).context("Profile is not valid UTF-8")?
+    };
+    if xml.trim_start().starts_with(['{', '[']) {
+        xml = json_profile_to_xml(&xml)?;
+    }
+
+    let document = Document::parse(&xml).context("Could not parse profile XML")?;
+    let root = document.root_element();
+    ensure!(
+        root.tag_name().name().eq_ignore_ascii_case("profile"),
+        "Unexpected root element '{}', expected a CoreCtrl profile",
+        root.tag_name().name()
+    );
+
+    let exe = root.attribute("exe").unwrap_or_default();
+    let name = root
+        .attribute("name")
+        .filter(|name| !name.is_empty())
+        .unwrap_or(exe)
+        .to_owned();
+    ensure!(!name.is_empty(), "Profile does not have a name");
+
+    let mut unmapped = Vec::new();
+
+    // Special CoreCtrl profiles such as `_global_` and `_manual_` don't have an executable trigger
+    let rule = if exe.is_empty() || (exe.starts_with('_') && exe.ends_with('_')) {
+        None
+    } else {
+        Some(ProfileRule::Process(ProcessProfileRule {
+            name: exe.into(),
+            ..Default::default()
+        }))
+    };
+
+    let mut profile = Profile {
+        rule,
+        ..Default::default()
+    };
+
+    let mut matched_targets = Vec::new();
+    for node in root.children().filter(Node::is_element) {
+        if !is_active(node) {
+            continue;
+        }
+
+        match node.tag_name().name() {
+            "GPU" => {
+                let index = node.attribute("index").unwrap_or("?");
+                let device_id = node.attribute("deviceid").unwrap_or_default();
+
+                let target = targets.iter().find(|target| {
+                    target.device_id.eq_ignore_ascii_case(device_id)
+                        && !matched_targets.contains(&target.id)
+                });
+                let Some(target) = target else {
+                    unmapped.push(format!(
+                        "GPU {index} (device id {device_id}): no matching GPU found in the system"
+                    ));
+                    continue;
+                };
+                matched_targets.push(target.id.clone());
+
+                let mut gpu_config = GpuConfig::default();
+                let mut gpu_unmapped = Vec::new();
+                for component in node.children().filter(Node::is_element) {
+                    import_component(component, target, &mut gpu_config, &mut gpu_unmapped);
+                }
+
+                unmapped.extend(
+                    gpu_unmapped
+                        .into_iter()
+                        .map(|description| format!("GPU {index}: {description}")),
+                );
+                profile.gpus.insert(target.id.clone(), gpu_config);
+            }
+            "CPU" => unmapped.push("CPU settings".to_owned()),
+            other => unmapped.push(format!("Unknown component {other}")),
+        }
+    }
+
+    Ok(ProfileImportResult {
+        name,
+        profile,
+        unmapped,
+    })
+}
+
+fn import_component(
+    node: Node,
+    target: &ImportTarget,
+    config: &mut GpuConfig,
+    unmapped: &mut Vec<String>,
+) {
+    if !is_active(node) {
+        return;
+    }
+
+    let tag = node.tag_name().name();
+    match tag {
+        // Mode selectors, only the selected mode should be imported
+        "AMD_PM_PERFMODE" | "AMD_FAN_MODE" | "AMD_OD_FAN_MODE" => {
+            let selected_mode = node.attribute("mode");
+            for child in node.children().filter(Node::is_element) {
+                if selected_mode.is_none_or(|mode| mode == child.tag_name().name()) {
+                    import_component(child, target, config, unmapped);
+                }
+            }
+        }
+        "AMD_PM_AUTO" => config.performance_level = Some(PerformanceLevel::Auto),
+        "AMD_PM_FIXED" => match node.attribute("mode") {
+            Some("low") => config.performance_level = Some(PerformanceLevel::Low),
+            Some("high") => config.performance_level = Some(PerformanceLevel::High),
+            mode => unmapped.push(format!("fixed performance mode {mode:?}")),
+        },
+        "AMD_PM_ADVANCED" => {
+            config.performance_level = Some(PerformanceLevel::Manual);
+            for child in node.children().filter(Node::is_element) {
+                import_component(child, target, config, unmapped);
+            }
+        }
+        "AMD_PM_POWERCAP" | "AMD_PM_POWER_CAP" => {
+            match node.attribute("value").map(str::parse::<f64>) {
+                Some(Ok(value)) => config.power_cap = Some(value),
+                _ => unmapped.push("power cap without a valid value".to_owned()),
+            }
+        }
+        "AMD_PM_POWER_PROFILE" => {
+            let mode = node.attribute("mode").unwrap_or_default();
+            let index = target
+                .power_profile_modes
+                .iter()
+                .find(|(_, name)| normalize_mode_name(name) == normalize_mode_name(mode))
+                .map(|(index, _)| *index);
+            match index {
+                Some(index) => config.power_profile_mode_index = Some(index),
+                None => unmapped.push(format!("power profile mode '{mode}'")),
+            }
+        }
+        "AMD_PM_FV_STATE" | "AMD_PM_FV_VOLTCURVE" | "AMD_PM_FREQ_RANGE" => {
+            import_clock_states(node, config, unmapped);
+        }
+        "AMD_PM_VOLT_OFFSET" => match node.attribute("value").map(str::parse::<i32>) {
+            Some(Ok(value)) => config.clocks_configuration.voltage_offset = Some(value),
+            _ => unmapped.push("voltage offset without a valid value".to_owned()),
+        },
+        // Default behaviour that does not need any settings
+        "AMD_PM_DYNAMIC_FREQ" => (),
+        "AMD_FAN_AUTO" | "AMD_OD_FAN_AUTO" => config.fan_control_enabled = false,
+        "AMD_FAN_FIXED" => match node.attribute("value").map(str::parse::<f32>) {
+            Some(Ok(value)) => {
+                config.fan_control_enabled = true;
+                config.fan_control_settings = Some(FanControlSettings {
+                    mode: FanControlMode::Static,
+                    static_speed: (value / 100.0).clamp(0.0, 1.0),
+                    ..Default::default()
+                });
+                report_fan_stop(node, unmapped);
+            }
+            _ => unmapped.push("fixed fan speed without a valid value".to_owned()),
+        },
+        "AMD_FAN_CURVE" | "AMD_OD_FAN_CURVE" => import_fan_curve(node, config, unmapped),
+        other => unmapped.push(format!("component {other}")),
+    }
+}
+
+fn import_fan_curve(node: Node, config: &mut GpuConfig, unmapped: &mut Vec<String>) {
+    let curve = node
+        .descendants()
+        .filter(|point| point.has_tag_name("POINT"))
+        .map(|point| {
+            let temp = point.attribute("temp")?.parse::<i32>().ok()?;
+            let speed = point
+                .attribute("pwm")
+                .or_else(|| point.attribute("speed"))?
+                .parse::<f32>()
+                .ok()?;
+            Some((temp, (speed / 100.0).clamp(0.0, 1.0)))
+        })
+        .collect::<Option<BTreeMap<i32, f32>>>();
+
+    match curve {
+        Some(curve) if !curve.is_empty() => {
+            config.fan_control_enabled = true;
+            config.fan_control_settings = Some(FanControlSettings {
+                mode: FanControlMode::Curve,
+                curve: FanCurve(curve),
+                ..Default::default()
+            });
+            report_fan_stop(node, unmapped);
+        }
+        _ => unmapped.push("fan curve with invalid points".to_owned()),
+    }
+}
+
+/// Imports clock/voltage states. For every clock type, the first and the last state are used as the min and max values.
+fn import_clock_states(node: Node, config: &mut GpuConfig, unmapped: &mut Vec<String>) {
+    let clocks = &mut config.clocks_configuration;
+
+    for child in node.children().filter(Node::is_element) {
+        let (power_level_kind, is_memory) = match child.tag_name().name() {
+            "GPU_STATES" => (PowerLevelKind::CoreClock, false),
+            "MEM_STATES" => (PowerLevelKind::MemoryClock, true),
+            // Ranges that list the states directly are handled below
+            "STATE" => continue,
+            "VOLT_CURVE" => {
+                unmapped.push("voltage curve".to_owned());
+                continue;
+            }
+            other => {
+                unmapped.push(format!("clock states {other}"));
+                continue;
+            }
+        };
+
+        let states = parse_states(child);
+        if states.is_empty() {
+            continue;
+        }
+
+        let (min_freq, max_freq) = (states[0].1, states[states.len() - 1].1);
+        let max_volt = states.iter().filter_map(|state| state.2).max();
+
+        if is_memory {
+            if states.len() > 1 && node.has_tag_name("AMD_PM_FREQ_RANGE") {
+                clocks.min_memory_clock = Some(min_freq);
+            }
+            clocks.max_memory_clock = Some(max_freq);
+        } else {
+            if states.len() > 1 && !node.has_tag_name("AMD_PM_FV_STATE") {
+                clocks.min_core_clock = Some(min_freq);
+            }
+            clocks.max_core_clock = Some(max_freq);
+            if node.has_tag_name("AMD_PM_FV_STATE") {
+                clocks.max_voltage = max_volt;
+            }
+        }
+
+        // Power states can only be toggled individually with the discrete states format
+        if node.has_tag_name("AMD_PM_FV_STATE") {
+            let enabled_states: Vec<u8> = child
+                .children()
+                .filter(|state| state.has_tag_name("STATE") && is_active(*state))
+                .filter_map(|state| state.attribute("index")?.parse().ok())
+                .collect();
+            if enabled_states.len() != states.len() {
+                config.power_states.insert(power_level_kind, enabled_states);
+            }
+        }
+    }
+
+    // Ranges may also list the states directly, with the clock type as an attribute
+    if node.has_tag_name("AMD_PM_FREQ_RANGE") {
+        let control = node.attribute("controlName").unwrap_or("SCLK");
+        let states = parse_states(node);
+        if let (Some(first), Some(last)) = (states.first(), states.last()) {
+            if control.eq_ignore_ascii_case("MCLK") {
+                clocks.max_memory_clock = Some(last.1);
+            } else {
+                if states.len() > 1 {
+                    clocks.min_core_clock = Some(first.1);
+                }
+                clocks.max_core_clock = Some(last.1);
+            }
+        }
+    }
+}
+
+/// Returns a list of `(index, frequency, voltage)` states, sorted by index
+fn parse_states(node: Node) -> Vec<(u8, i32, Option<i32>)> {
+    let mut states: Vec<_> = node
+        .children()
+        .filter(|state| state.has_tag_name("STATE"))
+        .filter_map(|state| {
+            let index = state.attribute("index")?.parse().ok()?;
+            let freq = state.attribute("freq")?.parse().ok()?;
+            let volt = state.attribute("volt").and_then(|volt| volt.parse().ok());
+            Some((index, freq, volt))
+        })
+        .collect();
+    states.sort_unstable_by_key(|state| state.0);
+    states
+}
+
+fn report_fan_stop(node: Node, unmapped: &mut Vec<String>) {
+    if node.attribute("fanStop") == Some("true") {
+        unmapped.push("fan stop".to_owned());
+    }
+}
+
+fn is_active(node: Node) -> bool {
+    node.attribute("active") != Some("false")
+}
+
+fn normalize_mode_name(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Converts a JSON profile into the XML layout.
+///
+/// Every object is a component, where scalar values are its attributes and objects (or lists of objects) are child components named after their key.
+/// The profile may either be the top level object or be wrapped in a `PROFILE` key.
+fn json_profile_to_xml(json: &str) -> anyhow::Result<String> {
+    let value: Value = serde_json::from_str(json).context("Could not parse profile JSON")?;
+    let Value::Object(root) = value else {
+        bail!("Expected the profile JSON to be an object");
+    };
+
+    let profile = match root.get("PROFILE") {
+        Some(Value::Object(profile)) if root.len() == 1 => profile,
+        _ => &root,
+    };
+
+    let mut xml = String::new();
+    write_json_component(&mut xml, "PROFILE", profile)?;
+    Ok(xml)
+}
+
+fn write_json_component(
+    xml: &mut String,
+    tag: &str,
+    component: &Map<String, Value>,
+) -> anyhow::Result<()> {
+    ensure!(
+        tag.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+        "Invalid component name '{tag}'"
+    );
+
+    xml.push('<');
+    xml.push_str(tag);
+
+    let mut children = Vec::new();
+    for (key, value) in component {
+        let attribute = match value {
+            Value::Null => continue,
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) => value.to_string(),
+            Value::String(value) => value.clone(),
+            Value::Object(child) => {
+                children.push((key, child));
+                continue;
+            }
+            Value::Array(items) => {
+                for item in items {
+                    let Value::Object(child) = item else {
+                        bail!("Expected '{key}' to be a list of components");
+                    };
+                    children.push((key, child));
+                }
+                continue;
+            }
+        };
+
+        ensure!(
+            key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "Invalid attribute name '{key}'"
+        );
+        xml.push(' ');
+        xml.push_str(key);
+        xml.push_str("=\"");
+        for c in attribute.chars() {
+            match c {
+                '&' => xml.push_str("&amp;"),
+                '<' => xml.push_str("&lt;"),
+                '>' => xml.push_str("&gt;"),
+                '"' => xml.push_str("&quot;"),
+                c => xml.push(c),
+            }
+        }
+        xml.push('"');
+    }
+    xml.push('>');
+
+    for (key, child) in children {
+        write_json_component(xml, key, child)?;
+    }
+
+    xml.push_str("</");
+    xml.push_str(tag);
+    xml.push('>');
+    Ok(())
+}
+
+/// Finds the profile XML in a `.ccpro` archive
+fn extract_profile_xml(archive: &[u8]) -> anyhow::Result<String> {
+    let end_of_directory = (0..archive.len().saturating_sub(21))
+        .rev()
+        .find(|offset| {
+            read_u32(archive, *offset).ok() == Some(ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE)
+        })
+        .context("Invalid archive: could not find the central directory")?;
+
+    let entry_count = read_u16(archive, end_of_directory + 10)?;
+    let mut offset = read_u32(archive, end_of_directory + 16)? as usize;
+
+    for _ in 0..entry_count {
+        ensure!(
+            read_u32(archive, offset)? == ZIP_CENTRAL_HEADER_SIGNATURE,
+            "Invalid archive: malformed central directory"
+        );
+        let compression_method = read_u16(archive, offset + 10)?;
+        let compressed_size = read_u32(archive, offset + 20)? as usize;
+        let name_len = read_u16(archive, offset + 28)? as usize;
+        let extra_len = read_u16(archive, offset + 30)? as usize;
+        let comment_len = read_u16(archive, offset + 32)? as usize;
+        let local_header_offset = read_u32(archive, offset + 42)? as usize;
+        let file_name = archive
+            .get(offset + 46..offset + 46 + name_len)
+            .context("Invalid archive: unexpected end of file")?;
+
+        offset += 46 + name_len + extra_len + comment_len;
+
+        if !file_name.ends_with(b".xml") {
+            continue;
+        }
+
+        ensure!(
+            read_u32(archive, local_header_offset)? == ZIP_LOCAL_HEADER_SIGNATURE,
+            "Invalid archive: malformed file header"
+        );
+        let data_start = local_header_offset
+            + 30
+            + read_u16(archive, local_header_offset + 26)? as usize
+            + read_u16(archive, local_header_offset + 28)? as usize;
+        let data = archive
+            .get(data_start..data_start + compressed_size)
+            .context("Invalid archive: unexpected end of file")?;
+
+        let mut contents = Vec::new();
+        match compression_method {
+            0 => contents.extend_from_slice(data),
+            8 => {
+                deflate::Decoder::new(data)
+                    .read_to_end(&mut contents)
+                    .context("Could not decompress profile")?;
+            }
+            other => bail!("Unsupported archive compression method {other}"),
+        }
+
+        return String::from_utf8(contents).context("Profile is not valid UTF-8");
+    }
+
+    bail!("Could not find the profile in the archive")
+}
+
+fn read_u16(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .context("Invalid archive: unexpected end of file")
+}
+
+fn read_u32(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .context("Invalid archive: unexpected end of file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_profile, ImportTarget};
+    use amdgpu_sysfs::gpu_handle::{PerformanceLevel, PowerLevelKind};
+    use lact_schema::{FanControlMode, ProcessProfileRule, ProfileRule};
+    use pretty_assertions::assert_eq;
+
+    const GPU_ID: &str = "1002:687F-1043:0555-0000:0b:00.0";
+
+    fn targets() -> Vec<ImportTarget> {
+        vec![ImportTarget {
+            id: GPU_ID.to_owned(),
+            device_id: "687F".to_owned(),
+            power_profile_modes: vec![
+                (0, "BOOTUP_DEFAULT".to_owned()),
+                (1, "3D_FULL_SCREEN".to_owned()),
+                (5, "COMPUTE".to_owned()),
+            ],
+        }]
+    }
+
+    #[test]
+    fn import_game_profile() {
+        let xml = r#"<?xml version="1.0"?>
+<PROFILE active="true" name="My Game" exe="game.exe">
+ <CPU id="0" active="true">
+  <CPU_CPUFREQ active="true" scalingGovernor="performance"/>
+ </CPU>
+ <GPU index="0" deviceid="687f" revision="c1" active="true">
+  <AMD_PM_PERFMODE active="true" mode="AMD_PM_ADVANCED">
+   <AMD_PM_AUTO active="false"/>
+   <AMD_PM_FIXED active="false" mode="low"/>
+   <AMD_PM_ADVANCED active="true">
+    <AMD_PM_POWERCAP active="true" value="180"/>
+    <AMD_PM_POWER_PROFILE active="true" mode="3D_FULL_SCREEN"/>
+    <AMD_PM_FV_STATE active="true" voltMode="manual">
+     <GPU_STATES>
+      <STATE active="true" index="0" freq="852" volt="800"/>
+      <STATE active="false" index="1" freq="991" volt="900"/>
+      <STATE active="true" index="2" freq="1630" volt="1200"/>
+     </GPU_STATES>
+     <MEM_STATES>
+      <STATE active="true" index="0" freq="167" volt="800"/>
+      <STATE active="true" index="1" freq="945" volt="950"/>
+     </MEM_STATES>
+    </AMD_PM_FV_STATE>
+   </AMD_PM_ADVANCED>
+  </AMD_PM_PERFMODE>
+  <AMD_FAN_MODE active="true" mode="AMD_FAN_CURVE">
+   <AMD_FAN_AUTO active="false"/>
+   <AMD_FAN_CURVE active="true" fanStop="true" fanStartValue="54">
+    <CURVE>
+     <POINT temp="35" pwm="20"/>
+     <POINT temp="60" pwm="50"/>
+     <POINT temp="90" pwm="100"/>
+    </CURVE>
+   </AMD_FAN_CURVE>
+  </AMD_FAN_MODE>
+ </GPU>
+</PROFILE>"#;
+
+        let result = import_profile(xml.as_bytes(), &targets()).unwrap();
+        assert_eq!("My Game", result.name);
+        assert_eq!(
+            Some(ProfileRule::Process(ProcessProfileRule {
+                name: "game.exe".into(),
+                ..Default::default()
+            })),
+            result.profile.rule
+        );
+
+        let gpu = result.profile.gpus.get(GPU_ID).unwrap();
+        assert_eq!(Some(PerformanceLevel::Manual), gpu.performance_level);
+        assert_eq!(Some(180.0), gpu.power_cap);
+        assert_eq!(Some(1), gpu.power_profile_mode_index);
+        assert_eq!(Some(1630), gpu.clocks_configuration.max_core_clock);
+        assert_eq!(Some(1200), gpu.clocks_configuration.max_voltage);
+        assert_eq!(Some(945), gpu.clocks_configuration.max_memory_clock);
+        assert_eq!(
+            Some(&vec![0, 2]),
+            gpu.power_states.get(&PowerLevelKind::CoreClock)
+        );
+        assert_eq!(None, gpu.power_states.get(&PowerLevelKind::MemoryClock));
+
+        assert!(gpu.fan_control_enabled);
+        let fan_settings = gpu.fan_control_settings.as_ref().unwrap();
+        assert_eq!(FanControlMode::Curve, fan_settings.mode);
+        assert_eq!(
+            vec![(35, 0.2), (60, 0.5), (90, 1.0)],
+            fan_settings
+                .curve
+                .0
+                .iter()
+                .map(|(temp, speed)| (*temp, *speed))
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(vec!["CPU settings", "GPU 0: fan stop"], result.unmapped);
+    }
+
+    #[test]
+    fn import_global_profile() {
+        let xml = r#"<PROFILE active="true" name="_global_" exe="_global_">
+ <GPU index="0" deviceid="73bf" active="true">
+  <AMD_PM_PERFMODE active="true" mode="AMD_PM_AUTO">
+   <AMD_PM_AUTO active="true"/>
+  </AMD_PM_PERFMODE>
+ </GPU>
+</PROFILE>"#;
+
+        let result = import_profile(xml.as_bytes(), &targets()).unwrap();
+        assert_eq!(None, result.profile.rule);
+        assert!(result.profile.gpus.is_empty());
+        assert_eq!(
+            vec!["GPU 0 (device id 73bf): no matching GPU found in the system"],
+            result.unmapped
+        );
+    }
+
+    #[test]
+    fn import_json_profile() {
+        let json = r#"{
+  "PROFILE": {
+    "active": true,
+    "name": "Q&A \"Game\"",
+    "exe": "game.exe",
+    "GPU": {
+      "index": 0,
+      "deviceid": "687f",
+      "active": true,
+      "AMD_PM_PERFMODE": {
+        "active": true,
+        "mode": "AMD_PM_ADVANCED",
+        "AMD_PM_ADVANCED": {
+          "active": true,
+          "AMD_PM_POWERCAP": { "active": true, "value": 150 },
+          "AMD_PM_FV_STATE": {
+            "active": true,
+            "GPU_STATES": {
+              "STATE": [
+                { "active": true, "index": 0, "freq": 852, "volt": 800 },
+                { "active": true, "index": 1, "freq": 1600, "volt": 1150 }
+              ]
+            }
+          }
+        }
+      },
+      "AMD_FAN_MODE": {
+        "active": true,
+        "mode": "AMD_FAN_FIXED",
+        "AMD_FAN_FIXED": { "active": true, "value": 40 }
+      }
+    }
+  }
+}"#;
+
+        let result = import_profile(json.as_bytes(), &targets()).unwrap();
+        assert_eq!("Q&A \"Game\"", result.name);
+        assert_eq!(
+            Some(ProfileRule::Process(ProcessProfileRule {
+                name: "game.exe".into(),
+                ..Default::default()
+            })),
+            result.profile.rule
+        );
+
+        let gpu = result.profile.gpus.get(GPU_ID).unwrap();
+        assert_eq!(Some(PerformanceLevel::Manual), gpu.performance_level);
+        assert_eq!(Some(150.0), gpu.power_cap);
+        assert_eq!(Some(1600), gpu.clocks_configuration.max_core_clock);
+        assert_eq!(Some(1150), gpu.clocks_configuration.max_voltage);
+
+        let fan_settings = gpu.fan_control_settings.as_ref().unwrap();
+        assert_eq!(FanControlMode::Static, fan_settings.mode);
+        assert_eq!(0.4, fan_settings.static_speed);
+        assert!(result.unmapped.is_empty());
+    }
+
+    #[test]
+    fn invalid_profile() {
+        assert!(import_profile(b"<foo/>", &targets()).is_err());
+        assert!(import_profile(b"not xml", &targets()).is_err());
+        assert!(import_profile(b"[1, 2]", &targets()).is_err());
+        assert!(import_profile(br#"{"name": "game", "GPU": ["x"]}"#, &targets()).is_err());
+    }
+}
@@ -6,6 +6,7 @@ mod intel;
 mod nvidia;
 
 use amd::AmdGpuController;
+use common::apply_script::ApplyStep;
 use intel::IntelGpuController;
 use lact_schema::DeviceType;
 use lact_schema::ProcessList;
@@ -46,6 +47,13 @@ pub trait GpuController {
 
     fn apply_config<'a>(&'a self, config: &'a GpuConfig) -> LocalBoxFuture<'a, anyhow::Result<()>>;
 
+    /// Lists the sysfs writes which are equivalent to applying the given config
+    fn apply_config_steps(&self, _config: &GpuConfig) -> anyhow::Result<Vec<ApplyStep>> {
+        Err(anyhow!(
+            "Exporting settings as a script is not supported on this GPU"
+        ))
+    }
+
     fn get_stats(&self, gpu_config: Option<&GpuConfig>) -> DeviceStats;
 
     fn get_clocks_info(&self, gpu_config: Option<&GpuConfig>) -> anyhow::Result<ClocksInfo>;
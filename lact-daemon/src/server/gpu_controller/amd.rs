@@ -1,8 +1,10 @@
 use super::{CommonControllerInfo, FanControlHandle, GpuController, VENDOR_AMD};
 use crate::server::{
     gpu_controller::common::{
-        fan_control::FanCurveExt,
+        apply_script::ApplyStep,
+        fan_control::{self, FanCurveExt, PidController, TemperatureSmoothing},
         fdinfo::{self, DrmUtilMap},
+        system_sensors::SystemSensors,
     },
     opencl::get_opencl_info,
     vulkan::get_vulkan_info,
@@ -18,7 +20,7 @@ use amdgpu_sysfs::{
     hw_mon::{FanControlMethod, HwMon},
     sysfs::SysFS,
 };
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use futures::{future::LocalBoxFuture, FutureExt};
 use lact_schema::{
     config::{ClocksConfiguration, FanControlSettings, FanCurve, GpuConfig},
@@ -36,7 +38,7 @@ use std::{
     rc::Rc,
     time::Duration,
 };
-use std::{collections::BTreeMap, fs, time::Instant};
+use std::{collections::BTreeMap, fs, mem, time::Instant};
 use tokio::{select, sync::Notify, time::sleep};
 use tracing::{debug, error, info, trace, warn};
 
@@ -124,25 +126,7 @@ impl AmdGpuController {
                 }
             }
 
-            let allowed_ranges = current_curve.allowed_ranges.clone().ok_or_else(|| {
-                anyhow!("The GPU does not allow setting custom fan values (is overdrive enabled?)")
-            })?;
-            let min_temperature = allowed_ranges.temperature_range.start();
-            let max_temperature = allowed_ranges.temperature_range.end();
-
-            #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-            let custom_pwm = (f32::from(*allowed_ranges.speed_range.end()) * static_speed) as u8;
-            let static_pwm = cmp::max(*allowed_ranges.speed_range.start(), custom_pwm);
-
-            let mut points = vec![(*min_temperature, static_pwm)];
-            for _ in 1..current_curve.points.len() {
-                points.push((*max_temperature, static_pwm));
-            }
-
-            let new_curve = PmfwCurve {
-                points: points.into_boxed_slice(),
-                allowed_ranges: Some(allowed_ranges),
-            };
+            let new_curve = static_pmfw_curve(&current_curve, static_speed)?;
 
             debug!("setting static curve {new_curve:?}");
 
@@ -186,8 +170,20 @@ impl AmdGpuController {
         // Use the PMFW curve functionality when it is available
         // Otherwise, fall back to manual fan control via a task
         if let Ok(current_curve) = self.handle.get_fan_curve() {
+            if settings.falling_curve.is_some()
+                || settings.hysteresis.is_some_and(|value| value > 0)
+            {
+                warn!("the firmware fan curve does not support hysteresis, only the regular curve will be used");
+            }
+            if settings.max_speed_increase.is_some()
+                || settings.max_speed_decrease.is_some()
+                || settings.temperature_smoothing_ms.is_some()
+            {
+                warn!("fan speed ramping and temperature smoothing are not applied to the firmware fan curve");
+            }
+
             let new_curve = curve
-                .into_pmfw_curve(current_curve.clone())
+                .into_pmfw_curve(current_curve.clone(), settings.interpolation)
                 .context("Invalid fan curve")?;
 
             debug!("setting pmfw curve {new_curve:?}");
@@ -209,6 +205,11 @@ impl AmdGpuController {
         curve: FanCurve,
         settings: FanControlSettings,
     ) -> anyhow::Result<()> {
+        if settings.mode == lact_schema::FanControlMode::TargetTemperature {
+            PidController::validate_settings(&settings.pid.unwrap_or_default())
+                .context("Invalid target temperature settings")?;
+        }
+
         // Stop existing task to re-apply new curve
         self.stop_fan_control(false).await?;
 
@@ -240,6 +241,47 @@ impl AmdGpuController {
             }
         }
 
+        for sensor in &settings.extra_sensors {
+            if sensor.hwmon.is_some() {
+                continue;
+            }
+            if sensor.temperature_key == settings.temperature_key {
+                bail!(
+                    "Sensor {} is already used as the main sensor",
+                    sensor.temperature_key
+                );
+            }
+            if !temps.contains_key(&sensor.temperature_key) {
+                bail!(
+                    "Extra sensor with name {} not found, available sensors: {}",
+                    sensor.temperature_key,
+                    temps
+                        .keys()
+                        .map(String::as_str)
+                        .collect::<Vec<&str>>()
+                        .join(",")
+                );
+            }
+        }
+
+        let mut system_sensors =
+            SystemSensors::new(&settings.extra_sensors).context("Could not read extra sensor")?;
+
+        let mut pid = if settings.mode == lact_schema::FanControlMode::TargetTemperature {
+            let max_pwm = f32::from(u8::MAX);
+            let min_speed = f32::from(hw_mon.get_fan_min_pwm().unwrap_or(0)) / max_pwm;
+            let max_speed = f32::from(hw_mon.get_fan_max_pwm().unwrap_or(u8::MAX)) / max_pwm;
+            let current_speed = f32::from(hw_mon.get_fan_pwm().unwrap_or(0)) / max_pwm;
+
+            Some(PidController::new(
+                settings.pid.unwrap_or_default(),
+                min_speed..=max_speed,
+                current_speed,
+            ))
+        } else {
+            None
+        };
+
         hw_mon
             .set_fan_control_method(FanControlMethod::Manual)
             .context("Could not set fan control method")?;
@@ -255,7 +297,13 @@ impl AmdGpuController {
         debug!("spawning new fan control task");
         let handle = tokio::task::spawn_local(async move {
             let mut last_pwm = (None, Instant::now());
-            let mut last_temp = 0.0;
+            // Differs from the last target while the speed is being ramped up or down
+            let mut applied_pwm = None;
+            let mut last_temps = HashMap::new();
+            let mut last_tick = Instant::now();
+            let mut smoothing = settings
+                .temperature_smoothing_ms
+                .map(TemperatureSmoothing::new);
 
             // If the fan speed could was able to be set at least once
             let mut control_available = false;
@@ -274,9 +322,10 @@ impl AmdGpuController {
                     () = task_notify.notified() => break,
                 }
 
+                // Whatever is left after taking out the main sensor is used for the extra sensors
                 let mut temps = hw_mon.get_temps();
-                let temp = if temps.len() == 1 {
-                    temps.into_values().next().unwrap()
+                let mut temp = if temps.len() == 1 {
+                    mem::take(&mut temps).into_values().next().unwrap()
                 } else if let Some(value) = temps.remove(&temp_key) {
                     value
                 } else {
@@ -290,29 +339,73 @@ impl AmdGpuController {
                     continue;
                 };
 
-                let current_temp = temp.current.expect("Missing temp");
-
-                if (last_temp - current_temp).abs() < change_threshold {
-                    trace!("temperature changed from {last_temp}°C to {current_temp}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment");
-                    continue;
-                }
+                system_sensors.read(&mut temps);
 
-                let target_pwm = curve.pwm_at_temp(temp);
                 let now = Instant::now();
+                let elapsed = (now - last_tick).as_secs_f32();
+                last_tick = now;
 
-                if let (Some(previous_pwm), previous_timestamp) = last_pwm {
-                    let diff = now - previous_timestamp;
-                    if target_pwm < previous_pwm && diff < spindown_delay {
-                        trace!(
-                            "delaying fan spindown ({}ms left)",
-                            (spindown_delay - diff).as_millis()
-                        );
-                        continue;
+                if let Some(smoothing) = &mut smoothing {
+                    smoothing.apply(&temp_key, &mut temp, elapsed);
+                    for (key, value) in &mut temps {
+                        smoothing.apply(key, value, elapsed);
                     }
                 }
 
-                last_pwm = (Some(target_pwm), now);
-                last_temp = current_temp;
+                // Set while the fan is still being ramped towards the last target
+                let ramp_target = last_pwm.0.filter(|pwm| applied_pwm != Some(*pwm));
+
+                let target_pwm = if let Some(pid) = &mut pid {
+                    let speed = pid.update(temp.current.expect("Missing temp"), elapsed);
+                    let target_pwm = fan_control::speed_to_pwm(speed);
+                    last_pwm = (Some(target_pwm), now);
+                    target_pwm
+                } else {
+                    let mut current_temps = settings
+                        .extra_sensors
+                        .iter()
+                        .filter_map(|sensor| {
+                            let sensor_key = sensor.sensor_key();
+                            let current = temps.get(&sensor_key)?.current?;
+                            Some((sensor_key, current))
+                        })
+                        .collect::<HashMap<_, _>>();
+                    current_temps.insert(temp_key.clone(), temp.current.expect("Missing temp"));
+
+                    let temp_change = fan_control::max_temp_change(&last_temps, &current_temps);
+                    let target_pwm =
+                        fan_control::target_pwm(&curve, &settings, temp, &temps, last_pwm.0);
+
+                    let mut delayed = false;
+                    if temp_change < change_threshold {
+                        trace!("temperature changed by {temp_change}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment");
+                        delayed = true;
+                    } else if let (Some(previous_pwm), previous_timestamp) = last_pwm {
+                        let diff = now - previous_timestamp;
+                        if target_pwm < previous_pwm && diff < spindown_delay {
+                            trace!(
+                                "delaying fan spindown ({}ms left)",
+                                (spindown_delay - diff).as_millis()
+                            );
+                            delayed = true;
+                        }
+                    }
+
+                    if delayed {
+                        match ramp_target {
+                            Some(ramp_target) => ramp_target,
+                            None => continue,
+                        }
+                    } else {
+                        last_temps = current_temps;
+                        last_pwm = (Some(target_pwm), now);
+                        target_pwm
+                    }
+                };
+
+                let target_pwm =
+                    fan_control::limit_pwm_change(&settings, applied_pwm, target_pwm, elapsed);
+                applied_pwm = Some(target_pwm);
 
                 trace!("fan control tick: setting pwm to {target_pwm}");
 
@@ -629,6 +722,386 @@ impl AmdGpuController {
         self.common.pci_info.device_pci_info.vendor_id == VENDOR_AMD
             && STEAM_DECK_IDS.contains(&self.common.pci_info.device_pci_info.model_id.as_str())
     }
+
+    /// Performs a single operation of applying a config through the GPU handle.
+    /// Firmware settings are only committed once the `Commit` operation is reached.
+    #[allow(clippy::too_many_lines)]
+    async fn apply_config_op(
+        &self,
+        op: ConfigOp<'_>,
+        commit_handles: &mut VecDeque<CommitHandle>,
+    ) -> anyhow::Result<()> {
+        match op {
+            ConfigOp::ResetClocksTable => {
+                self.handle.reset_clocks_table().ok();
+            }
+            ConfigOp::ResetFanControl => {
+                self.stop_fan_control(true)
+                    .await
+                    .context("Failed to stop fan control")?;
+            }
+            ConfigOp::ManualPerformanceLevel => {
+                self.handle
+                    .set_power_force_performance_level(PerformanceLevel::Manual)
+                    .ok();
+            }
+            ConfigOp::ClocksTable(clocks_configuration) => match self.handle.get_clocks_table() {
+                Ok(original_table) => {
+                    let mut table = original_table.clone();
+                    apply_clocks_config_to_table(clocks_configuration, &mut table)
+                        .context("Failed to apply clocks configuration to table")?;
+
+                    debug!(
+                        "writing clocks commands: {:#?}",
+                        table
+                            .get_commands(&original_table)
+                            .context("Failed to get table commands")?
+                    );
+
+                    let handle = self
+                        .handle
+                        .set_clocks_table(&table)
+                        .context("Could not write clocks table")
+                        .with_context(|| {
+                            format!(
+                                "Clocks table commands: {:?}",
+                                table.get_commands(&original_table)
+                            )
+                        })?;
+                    commit_handles.push_back(handle);
+                }
+                Err(err) => {
+                    error!("custom clock settings are present but will be ignored, could not get clocks table: {err}");
+                }
+            },
+            ConfigOp::PerformanceLevel(performance_level) => {
+                match self.handle.get_power_force_performance_level() {
+                    Ok(_) => {
+                        self.handle
+                            .set_power_force_performance_level(performance_level)
+                            .context("Failed to set power performance level")?;
+                    }
+                    Err(err) => {
+                        error!("could not get current performance level: {err}");
+                    }
+                }
+            }
+            ConfigOp::PowerProfileMode(mode_index) => {
+                self.handle
+                    .set_active_power_profile_mode(mode_index)
+                    .context("Failed to set active power profile mode")?;
+            }
+            ConfigOp::PowerProfileHeuristics(heuristics) => {
+                self.handle
+                    .set_custom_power_profile_mode_heuristics(heuristics)
+                    .context("Failed to set custom power profile mode heuristics")?;
+            }
+            ConfigOp::FanControl(settings) => match settings.mode {
+                lact_schema::FanControlMode::Static => {
+                    let fan_handles = self
+                        .set_static_fan_control(settings.static_speed)
+                        .await
+                        .context("Failed to set static fan control")?;
+
+                    for handle in fan_handles {
+                        commit_handles.push_front(handle);
+                    }
+                }
+                lact_schema::FanControlMode::Curve => {
+                    if let Some(commit_handle) = self
+                        .start_curve_fan_control(settings.curve.clone(), settings.clone())
+                        .await
+                        .context("Failed to set curve fan control")?
+                    {
+                        commit_handles.push_front(commit_handle);
+                    }
+                }
+                lact_schema::FanControlMode::TargetTemperature => {
+                    if self.handle.get_fan_curve().is_ok() {
+                        return Err(anyhow!("This GPU does not support manual fan control, use the firmware target temperature option instead"));
+                    }
+
+                    self.start_curve_fan_control_task(settings.curve.clone(), settings.clone())
+                        .await
+                        .context("Failed to set target temperature fan control")?;
+                }
+            },
+            ConfigOp::PmfwOption(option, value) => {
+                let current_value = match option {
+                    PmfwOption::AcousticLimit => self.handle.get_fan_acoustic_limit(),
+                    PmfwOption::AcousticTarget => self.handle.get_fan_acoustic_target(),
+                    PmfwOption::TargetTemperature => self.handle.get_fan_target_temperature(),
+                    PmfwOption::MinimumPwm => self.handle.get_fan_minimum_pwm(),
+                }
+                .with_context(|| format!("Could not get {}", option.description()))?
+                .current;
+
+                if current_value != value {
+                    let commit_handle = match option {
+                        PmfwOption::AcousticLimit => self.handle.set_fan_acoustic_limit(value),
+                        PmfwOption::AcousticTarget => self.handle.set_fan_acoustic_target(value),
+                        PmfwOption::TargetTemperature => {
+                            self.handle.set_fan_target_temperature(value)
+                        }
+                        PmfwOption::MinimumPwm => self.handle.set_fan_minimum_pwm(value),
+                    }
+                    .with_context(|| format!("Could not set {}", option.description()))?;
+                    commit_handles.push_front(commit_handle);
+                }
+            }
+            ConfigOp::ZeroRpm(zero_rpm) => match self.handle.get_fan_zero_rpm_enable() {
+                Ok(current_zero_rpm) => {
+                    if current_zero_rpm != zero_rpm {
+                        let commit_handle = self
+                            .handle
+                            .set_fan_zero_rpm_enable(zero_rpm)
+                            .context("Could not set zero RPM mode")?;
+                        commit_handles.push_front(commit_handle);
+                    }
+                }
+                Err(err) => {
+                    error!(
+                        "zero RPM is present in the config, but not available on the GPU: {err}"
+                    );
+                }
+            },
+            ConfigOp::ZeroRpmThreshold(zero_rpm_threshold) => {
+                match self.handle.get_fan_zero_rpm_stop_temperature() {
+                    Ok(current_threshold) => {
+                        if current_threshold.current != zero_rpm_threshold {
+                            let commit_handle = self
+                                .handle
+                                .set_fan_zero_rpm_stop_temperature(zero_rpm_threshold)
+                                .context("Could not set zero RPM temperature")?;
+                            commit_handles.push_front(commit_handle);
+                        }
+                    }
+                    Err(err) => {
+                        error!("zero RPM threshold is present in the config, but not available on the GPU: {err}");
+                    }
+                }
+            }
+            ConfigOp::PowerCap(Some(configured_cap)) => {
+                let hw_mon = self.first_hw_mon()?;
+
+                hw_mon
+                    .set_power_cap(configured_cap)
+                    .with_context(|| format!("Failed to set power cap: {configured_cap}"))?;
+            }
+            ConfigOp::PowerCap(None) => {
+                if let Ok(hw_mon) = self.first_hw_mon() {
+                    if let Ok(default_cap) = hw_mon.get_power_cap_default() {
+                        if Ok(default_cap) != hw_mon.get_power_cap() {
+                            hw_mon.set_power_cap(default_cap).with_context(|| {
+                                format!("Failed to set power cap to default cap: {default_cap}")
+                            })?;
+                        }
+                    }
+                }
+            }
+            ConfigOp::Commit => {
+                for handle in commit_handles.drain(..) {
+                    handle.commit()?;
+                }
+            }
+            ConfigOp::PowerStates(kind, states) => {
+                self.handle
+                    .set_enabled_power_levels(kind, states)
+                    .with_context(|| format!("Could not set {kind:?} power states"))?;
+            }
+            ConfigOp::Invalid(message) => bail!(message),
+        }
+
+        Ok(())
+    }
+
+    /// Lists the sysfs writes which `apply_config_op` does for an operation.
+    /// Firmware commits are collected in `commits` until the `Commit` operation is reached, in the same order as the commit handles.
+    #[allow(clippy::too_many_lines)]
+    fn config_op_steps(
+        &self,
+        op: ConfigOp<'_>,
+        steps: &mut Vec<ApplyStep>,
+        commits: &mut VecDeque<ApplyStep>,
+    ) -> anyhow::Result<()> {
+        let device_path = self.handle.get_path();
+        let od_path = device_path.join("pp_od_clk_voltage");
+        let fan_ctrl_path = device_path.join("gpu_od/fan_ctrl");
+        let performance_level_path = device_path.join("power_dpm_force_performance_level");
+
+        match op {
+            ConfigOp::ResetClocksTable => {
+                if self.handle.get_clocks_table().is_ok() {
+                    steps.push(ApplyStep::command(&od_path, "r"));
+                    steps.push(ApplyStep::command(&od_path, "c"));
+                }
+            }
+            ConfigOp::ResetFanControl => {
+                if self.handle.get_fan_curve().is_ok() {
+                    let curve_path = fan_ctrl_path.join("fan_curve");
+                    steps.push(ApplyStep::command(&curve_path, "r"));
+                    steps.push(ApplyStep::command(&curve_path, "c"));
+                }
+                if let Ok(hw_mon) = self.first_hw_mon() {
+                    steps.push(ApplyStep::write(hw_mon.get_path().join("pwm1_enable"), 2));
+                }
+            }
+            ConfigOp::ManualPerformanceLevel => {
+                steps.push(ApplyStep::write(
+                    &performance_level_path,
+                    PerformanceLevel::Manual,
+                ));
+            }
+            ConfigOp::ClocksTable(clocks_configuration) => match self.handle.get_clocks_table() {
+                Ok(original_table) => {
+                    let mut table = original_table.clone();
+                    apply_clocks_config_to_table(clocks_configuration, &mut table)
+                        .context("Failed to apply clocks configuration to table")?;
+
+                    for command in table
+                        .get_commands(&original_table)
+                        .context("Failed to get table commands")?
+                    {
+                        steps.push(ApplyStep::command(&od_path, command));
+                    }
+                    commits.push_back(ApplyStep::command(&od_path, "c"));
+                }
+                Err(_) => steps.push(ApplyStep::Unsupported(
+                    "Clock settings, the clocks table is not available".to_owned(),
+                )),
+            },
+            ConfigOp::PerformanceLevel(performance_level) => {
+                if self.handle.get_power_force_performance_level().is_ok() {
+                    steps.push(ApplyStep::write(&performance_level_path, performance_level));
+                }
+            }
+            ConfigOp::PowerProfileMode(mode_index) => {
+                steps.push(ApplyStep::command(
+                    device_path.join("pp_power_profile_mode"),
+                    mode_index,
+                ));
+            }
+            ConfigOp::PowerProfileHeuristics(_) => {
+                steps.push(ApplyStep::Unsupported(
+                    "Custom power profile mode heuristics".to_owned(),
+                ));
+            }
+            ConfigOp::FanControl(settings) => {
+                let pmfw_curve = match settings.mode {
+                    lact_schema::FanControlMode::Static => match self.handle.get_fan_curve() {
+                        Ok(current_curve) => {
+                            if let Ok(true) = self.handle.get_fan_zero_rpm_enable() {
+                                let zero_rpm_path = fan_ctrl_path.join("fan_zero_rpm_enable");
+                                steps.push(ApplyStep::command(&zero_rpm_path, 0));
+                                commits.push_front(ApplyStep::command(&zero_rpm_path, "c"));
+                            }
+                            Some(static_pmfw_curve(&current_curve, settings.static_speed)?)
+                        }
+                        Err(_) => {
+                            let hw_mon = self.first_hw_mon()?;
+
+                            #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+                            let static_pwm = (f32::from(u8::MAX) * settings.static_speed) as u8;
+
+                            steps.push(ApplyStep::write(hw_mon.get_path().join("pwm1_enable"), 1));
+                            steps
+                                .push(ApplyStep::write(hw_mon.get_path().join("pwm1"), static_pwm));
+                            None
+                        }
+                    },
+                    lact_schema::FanControlMode::Curve => match self.handle.get_fan_curve() {
+                        Ok(current_curve) => Some(
+                            settings
+                                .curve
+                                .clone()
+                                .into_pmfw_curve(current_curve, settings.interpolation)
+                                .context("Invalid fan curve")?,
+                        ),
+                        Err(_) => {
+                            steps.push(ApplyStep::Unsupported(
+                                "Fan curve, it is only available with the LACT daemon on this GPU"
+                                    .to_owned(),
+                            ));
+                            None
+                        }
+                    },
+                    lact_schema::FanControlMode::TargetTemperature => {
+                        steps.push(ApplyStep::Unsupported(
+                            "Target temperature fan control, it is only available with the LACT daemon"
+                                .to_owned(),
+                        ));
+                        None
+                    }
+                };
+
+                if let Some(pmfw_curve) = pmfw_curve {
+                    let curve_path = fan_ctrl_path.join("fan_curve");
+                    for (i, (temperature, speed)) in pmfw_curve.points.iter().enumerate() {
+                        steps.push(ApplyStep::command(
+                            &curve_path,
+                            format!("{i} {temperature} {speed}"),
+                        ));
+                    }
+                    commits.push_front(ApplyStep::command(&curve_path, "c"));
+                }
+            }
+            ConfigOp::PmfwOption(option, value) => {
+                let path = fan_ctrl_path.join(option.file_name());
+                steps.push(ApplyStep::command(&path, value));
+                commits.push_front(ApplyStep::command(&path, "c"));
+            }
+            ConfigOp::ZeroRpm(zero_rpm) => {
+                if self.handle.get_fan_zero_rpm_enable().is_ok() {
+                    let path = fan_ctrl_path.join("fan_zero_rpm_enable");
+                    steps.push(ApplyStep::command(&path, u8::from(zero_rpm)));
+                    commits.push_front(ApplyStep::command(&path, "c"));
+                } else {
+                    steps.push(ApplyStep::Unsupported(
+                        "Zero RPM mode, it is not available on this GPU".to_owned(),
+                    ));
+                }
+            }
+            ConfigOp::ZeroRpmThreshold(zero_rpm_threshold) => {
+                if self.handle.get_fan_zero_rpm_stop_temperature().is_ok() {
+                    let path = fan_ctrl_path.join("fan_zero_rpm_stop_temperature");
+                    steps.push(ApplyStep::command(&path, zero_rpm_threshold));
+                    commits.push_front(ApplyStep::command(&path, "c"));
+                } else {
+                    steps.push(ApplyStep::Unsupported(
+                        "Zero RPM threshold, it is not available on this GPU".to_owned(),
+                    ));
+                }
+            }
+            ConfigOp::PowerCap(configured_cap) => {
+                let power_cap = match configured_cap {
+                    Some(configured_cap) => Some(configured_cap),
+                    None => self.hw_mon_and_then(HwMon::get_power_cap_default),
+                };
+                if let Some(cap) = power_cap {
+                    let hw_mon = self.first_hw_mon()?;
+
+                    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+                    let cap_microwatts = (cap * 1_000_000.0) as u64;
+                    steps.push(ApplyStep::write(
+                        hw_mon.get_path().join("power1_cap"),
+                        cap_microwatts,
+                    ));
+                }
+            }
+            ConfigOp::Commit => steps.extend(commits.drain(..)),
+            ConfigOp::PowerStates(kind, states) => {
+                let value = states
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                steps.push(ApplyStep::write(device_path.join(kind.filename()), value));
+            }
+            ConfigOp::Invalid(message) => bail!(message),
+        }
+
+        Ok(())
+    }
 }
 
 impl GpuController for AmdGpuController {
@@ -701,8 +1174,14 @@ impl GpuController for AmdGpuController {
                 control_mode: fan_settings.map(|settings| settings.mode),
                 static_speed: fan_settings.map(|settings| settings.static_speed),
                 curve: fan_settings.map(|settings| settings.curve.0.clone()),
+                interpolation: fan_settings.map(|settings| settings.interpolation),
                 spindown_delay_ms: fan_settings.and_then(|settings| settings.spindown_delay_ms),
                 change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
+                falling_curve: fan_settings
+                    .and_then(|settings| settings.falling_curve.as_ref())
+                    .map(|curve| curve.0.clone()),
+                hysteresis: fan_settings.and_then(|settings| settings.hysteresis),
+                pid: fan_settings.and_then(|settings| settings.pid),
                 temperature_key: fan_settings.map(|settings| settings.temperature_key.clone()),
                 auto_threshold: None,
                 speed_current: self.hw_mon_and_then(HwMon::get_fan_current).or_else(|| {
@@ -824,260 +1303,25 @@ impl GpuController for AmdGpuController {
         fs::read(debugfs.join("amdgpu_vbios")).context("Could not read VBIOS file")
     }
 
-    #[allow(clippy::too_many_lines)]
     fn apply_config<'a>(&'a self, config: &'a GpuConfig) -> LocalBoxFuture<'a, anyhow::Result<()>> {
         Box::pin(async {
             let mut commit_handles = VecDeque::new();
-
-            // Reset the clocks table in case the settings get reverted back to not having a clocks value configured
-            self.handle.reset_clocks_table().ok();
-
-            if !config.fan_control_enabled {
-                self.stop_fan_control(true)
-                    .await
-                    .context("Failed to stop fan control")?;
-            }
-
-            if self.is_steam_deck() {
-                // Van Gogh/Sephiroth only allow clock settings to be used with manual performance mode
-                self.handle
-                    .set_power_force_performance_level(PerformanceLevel::Manual)
-                    .ok();
-            }
-
-            if config.is_core_clocks_used() {
-                match self.handle.get_clocks_table() {
-                    Ok(original_table) => {
-                        let mut table = original_table.clone();
-                        apply_clocks_config_to_table(&config.clocks_configuration, &mut table)
-                            .context("Failed to apply clocks configuration to table")?;
-
-                        debug!(
-                            "writing clocks commands: {:#?}",
-                            table
-                                .get_commands(&original_table)
-                                .context("Failed to get table commands")?
-                        );
-
-                        let handle = self
-                            .handle
-                            .set_clocks_table(&table)
-                            .context("Could not write clocks table")
-                            .with_context(|| {
-                                format!(
-                                    "Clocks table commands: {:?}",
-                                    table.get_commands(&original_table)
-                                )
-                            })?;
-                        commit_handles.push_back(handle);
-                    }
-                    Err(err) => {
-                        error!("custom clock settings are present but will be ignored, could not get clocks table: {err}");
-                    }
-                }
-            }
-
-            match self.handle.get_power_force_performance_level() {
-                Ok(_) => {
-                    let performance_level =
-                        config.performance_level.unwrap_or(PerformanceLevel::Auto);
-
-                    self.handle
-                        .set_power_force_performance_level(performance_level)
-                        .context("Failed to set power performance level")?;
-                }
-                Err(err) => {
-                    error!("could not get current performance level: {err}");
-                }
+            for op in config_ops(config, self.is_steam_deck()) {
+                self.apply_config_op(op, &mut commit_handles).await?;
             }
-
-            if let Some(mode_index) = config.power_profile_mode_index {
-                if config.performance_level != Some(PerformanceLevel::Manual) {
-                    return Err(anyhow!(
-                        "Performance level has to be set to `manual` to use power profile modes"
-                    ));
-                }
-
-                if config.custom_power_profile_mode_hueristics.is_empty() {
-                    self.handle
-                        .set_active_power_profile_mode(mode_index)
-                        .context("Failed to set active power profile mode")?;
-                } else {
-                    self.handle
-                        .set_custom_power_profile_mode_heuristics(
-                            &config.custom_power_profile_mode_hueristics,
-                        )
-                        .context("Failed to set custom power profile mode heuristics")?;
-                }
-            }
-
-            if config.fan_control_enabled {
-                if let Some(ref settings) = config.fan_control_settings {
-                    match settings.mode {
-                        lact_schema::FanControlMode::Static => {
-                            let fan_handles = self
-                                .set_static_fan_control(settings.static_speed)
-                                .await
-                                .context("Failed to set static fan control")?;
-
-                            for handle in fan_handles {
-                                commit_handles.push_front(handle);
-                            }
-                        }
-                        lact_schema::FanControlMode::Curve => {
-                            if settings.curve.0.is_empty() {
-                                return Err(anyhow!("Cannot use empty fan curve"));
-                            }
-
-                            if let Some(commit_handle) = self
-                                .start_curve_fan_control(settings.curve.clone(), settings.clone())
-                                .await
-                                .context("Failed to set curve fan control")?
-                            {
-                                commit_handles.push_front(commit_handle);
-                            }
-                        }
-                    }
-                } else {
-                    return Err(anyhow!(
-                        "Trying to enable fan control with no settings provided"
-                    ));
-                }
-            } else {
-                let pmfw = &config.pmfw_options;
-                if let Some(acoustic_limit) = pmfw.acoustic_limit {
-                    if self
-                        .handle
-                        .get_fan_acoustic_limit()
-                        .context("Could not get acoustic limit")?
-                        .current
-                        != acoustic_limit
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_acoustic_limit(acoustic_limit)
-                            .context("Could not set acoustic limit")?;
-                        commit_handles.push_front(commit_handle);
-                    }
-                }
-                if let Some(acoustic_target) = pmfw.acoustic_target {
-                    if self
-                        .handle
-                        .get_fan_acoustic_target()
-                        .context("Could not get acoustic target")?
-                        .current
-                        != acoustic_target
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_acoustic_target(acoustic_target)
-                            .context("Could not set acoustic target")?;
-                        commit_handles.push_front(commit_handle);
-                    }
-                }
-                if let Some(target_temperature) = pmfw.target_temperature {
-                    if self
-                        .handle
-                        .get_fan_target_temperature()
-                        .context("Could not get target temperature")?
-                        .current
-                        != target_temperature
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_target_temperature(target_temperature)
-                            .context("Could not set target temperature")?;
-                        commit_handles.push_front(commit_handle);
-                    }
-                }
-                if let Some(minimum_pwm) = pmfw.minimum_pwm {
-                    if self
-                        .handle
-                        .get_fan_minimum_pwm()
-                        .context("Could not get minimum pwm")?
-                        .current
-                        != minimum_pwm
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_minimum_pwm(minimum_pwm)
-                            .context("Could not set minimum pwm")?;
-                        commit_handles.push_front(commit_handle);
-                    }
-                }
-            }
-
-            // Unlike the other PMFW options, zero rpm should be functional with a custom curve
-            if let Some(zero_rpm) = config.pmfw_options.zero_rpm {
-                match self.handle.get_fan_zero_rpm_enable() {
-                    Ok(current_zero_rpm) => {
-                        if current_zero_rpm != zero_rpm {
-                            let commit_handle = self
-                                .handle
-                                .set_fan_zero_rpm_enable(zero_rpm)
-                                .context("Could not set zero RPM mode")?;
-                            commit_handles.push_front(commit_handle);
-                        }
-                    }
-                    Err(err) => {
-                        error!("zero RPM is present in the config, but not available on the GPU: {err}");
-                    }
-                }
-            }
-
-            if let Some(zero_rpm_threshold) = config.pmfw_options.zero_rpm_threshold {
-                match self.handle.get_fan_zero_rpm_stop_temperature() {
-                    Ok(current_threshold) => {
-                        if current_threshold.current != zero_rpm_threshold {
-                            let commit_handle = self
-                                .handle
-                                .set_fan_zero_rpm_stop_temperature(zero_rpm_threshold)
-                                .context("Could not set zero RPM temperature")?;
-                            commit_handles.push_front(commit_handle);
-                        }
-                    }
-                    Err(err) => {
-                        error!("zero RPM threshold is present in the config, but not available on the GPU: {err}");
-                    }
-                }
-            }
-
-            if let Some(configured_cap) = config.power_cap {
-                let hw_mon = self.first_hw_mon()?;
-
-                hw_mon
-                    .set_power_cap(configured_cap)
-                    .with_context(|| format!("Failed to set power cap: {configured_cap}"))?;
-            } else if let Ok(hw_mon) = self.first_hw_mon() {
-                if let Ok(default_cap) = hw_mon.get_power_cap_default() {
-                    if Ok(default_cap) != hw_mon.get_power_cap() {
-                        hw_mon.set_power_cap(default_cap).with_context(|| {
-                            format!("Failed to set power cap to default cap: {default_cap}")
-                        })?;
-                    }
-                }
-            }
-
-            for handle in commit_handles {
-                handle.commit()?;
-            }
-
-            for (kind, states) in &config.power_states {
-                if config.performance_level != Some(PerformanceLevel::Manual) {
-                    return Err(anyhow!(
-                        "Performance level has to be set to `manual` to configure power states"
-                    ));
-                }
-
-                self.handle
-                    .set_enabled_power_levels(*kind, states)
-                    .with_context(|| format!("Could not set {kind:?} power states"))?;
-            }
-
             Ok(())
         })
     }
 
+    fn apply_config_steps(&self, config: &GpuConfig) -> anyhow::Result<Vec<ApplyStep>> {
+        let mut steps = Vec::new();
+        let mut commits = VecDeque::new();
+        for op in config_ops(config, self.is_steam_deck()) {
+            self.config_op_steps(op, &mut steps, &mut commits)?;
+        }
+        Ok(steps)
+    }
+
     fn reset_clocks(&self) -> anyhow::Result<()> {
         if self.handle.get_clocks_table().is_err() {
             return Ok(());
@@ -1111,6 +1355,158 @@ impl GpuController for AmdGpuController {
     }
 }
 
+/// A single operation of applying a config.
+///
+/// The daemon performs each operation through the GPU handle, while the exported scripts write the sysfs values that the handle would.
+/// Both go through the same list of operations from `config_ops`, so they apply settings in the same order.
+#[derive(Clone, Copy)]
+enum ConfigOp<'a> {
+    /// Resets the clocks table in case the settings get reverted back to not having a clocks value configured
+    ResetClocksTable,
+    /// Stops custom fan control and puts the fan back into automatic mode
+    ResetFanControl,
+    /// Van Gogh/Sephiroth only allow clock settings to be used with manual performance mode
+    ManualPerformanceLevel,
+    ClocksTable(&'a ClocksConfiguration),
+    PerformanceLevel(PerformanceLevel),
+    PowerProfileMode(u16),
+    PowerProfileHeuristics(&'a Vec<Vec<Option<i32>>>),
+    FanControl(&'a FanControlSettings),
+    PmfwOption(PmfwOption, u32),
+    ZeroRpm(bool),
+    ZeroRpmThreshold(u32),
+    /// The default power cap is restored when not set
+    PowerCap(Option<f64>),
+    /// Commits the firmware settings written by the previous operations
+    Commit,
+    PowerStates(PowerLevelKind, &'a Vec<u8>),
+    /// Applying stops with this error once it is reached
+    Invalid(&'static str),
+}
+
+#[derive(Clone, Copy)]
+enum PmfwOption {
+    AcousticLimit,
+    AcousticTarget,
+    TargetTemperature,
+    MinimumPwm,
+}
+
+impl PmfwOption {
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::AcousticLimit => "acoustic_limit_rpm_threshold",
+            Self::AcousticTarget => "acoustic_target_rpm_threshold",
+            Self::TargetTemperature => "fan_target_temperature",
+            Self::MinimumPwm => "fan_minimum_pwm",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::AcousticLimit => "acoustic limit",
+            Self::AcousticTarget => "acoustic target",
+            Self::TargetTemperature => "target temperature",
+            Self::MinimumPwm => "minimum pwm",
+        }
+    }
+}
+
+/// Lists the operations of applying the given config, in order
+fn config_ops(config: &GpuConfig, is_steam_deck: bool) -> Vec<ConfigOp<'_>> {
+    let mut ops = vec![ConfigOp::ResetClocksTable];
+
+    if !config.fan_control_enabled {
+        ops.push(ConfigOp::ResetFanControl);
+    }
+
+    if is_steam_deck {
+        ops.push(ConfigOp::ManualPerformanceLevel);
+    }
+
+    if config.is_core_clocks_used() {
+        ops.push(ConfigOp::ClocksTable(&config.clocks_configuration));
+    }
+
+    ops.push(ConfigOp::PerformanceLevel(
+        config.performance_level.unwrap_or(PerformanceLevel::Auto),
+    ));
+
+    if let Some(mode_index) = config.power_profile_mode_index {
+        if config.performance_level != Some(PerformanceLevel::Manual) {
+            ops.push(ConfigOp::Invalid(
+                "Performance level has to be set to `manual` to use power profile modes",
+            ));
+            return ops;
+        }
+
+        if config.custom_power_profile_mode_hueristics.is_empty() {
+            ops.push(ConfigOp::PowerProfileMode(mode_index));
+        } else {
+            ops.push(ConfigOp::PowerProfileHeuristics(
+                &config.custom_power_profile_mode_hueristics,
+            ));
+        }
+    }
+
+    if config.fan_control_enabled {
+        match &config.fan_control_settings {
+            Some(settings) => {
+                if settings.mode == lact_schema::FanControlMode::Curve
+                    && settings.curve.0.is_empty()
+                {
+                    ops.push(ConfigOp::Invalid("Cannot use empty fan curve"));
+                    return ops;
+                }
+                ops.push(ConfigOp::FanControl(settings));
+            }
+            None => {
+                ops.push(ConfigOp::Invalid(
+                    "Trying to enable fan control with no settings provided",
+                ));
+                return ops;
+            }
+        }
+    } else {
+        let pmfw = &config.pmfw_options;
+        let pmfw_values = [
+            (PmfwOption::AcousticLimit, pmfw.acoustic_limit),
+            (PmfwOption::AcousticTarget, pmfw.acoustic_target),
+            (PmfwOption::TargetTemperature, pmfw.target_temperature),
+            (PmfwOption::MinimumPwm, pmfw.minimum_pwm),
+        ];
+        for (option, value) in pmfw_values {
+            if let Some(value) = value {
+                ops.push(ConfigOp::PmfwOption(option, value));
+            }
+        }
+    }
+
+    // Unlike the other PMFW options, zero rpm should be functional with a custom curve
+    if let Some(zero_rpm) = config.pmfw_options.zero_rpm {
+        ops.push(ConfigOp::ZeroRpm(zero_rpm));
+    }
+    if let Some(zero_rpm_threshold) = config.pmfw_options.zero_rpm_threshold {
+        ops.push(ConfigOp::ZeroRpmThreshold(zero_rpm_threshold));
+    }
+
+    ops.push(ConfigOp::PowerCap(config.power_cap));
+    ops.push(ConfigOp::Commit);
+
+    for (kind, states) in &config.power_states {
+        if config.performance_level != Some(PerformanceLevel::Manual) {
+            ops.push(ConfigOp::Invalid(
+                "Performance level has to be set to `manual` to configure power states",
+            ));
+            return ops;
+        }
+
+        ops.push(ConfigOp::PowerStates(*kind, states));
+    }
+
+    ops
+}
+
 #[cfg(not(test))]
 fn get_drm_handle(
     common: &CommonControllerInfo,
@@ -1130,6 +1526,29 @@ fn get_drm_handle(
     Ok(handle)
 }
 
+/// Builds a PMFW fan curve which keeps the fan at a constant speed
+fn static_pmfw_curve(current_curve: &PmfwCurve, static_speed: f32) -> anyhow::Result<PmfwCurve> {
+    let allowed_ranges = current_curve.allowed_ranges.clone().ok_or_else(|| {
+        anyhow!("The GPU does not allow setting custom fan values (is overdrive enabled?)")
+    })?;
+    let min_temperature = allowed_ranges.temperature_range.start();
+    let max_temperature = allowed_ranges.temperature_range.end();
+
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    let custom_pwm = (f32::from(*allowed_ranges.speed_range.end()) * static_speed) as u8;
+    let static_pwm = cmp::max(*allowed_ranges.speed_range.start(), custom_pwm);
+
+    let mut points = vec![(*min_temperature, static_pwm)];
+    for _ in 1..current_curve.points.len() {
+        points.push((*max_temperature, static_pwm));
+    }
+
+    Ok(PmfwCurve {
+        points: points.into_boxed_slice(),
+        allowed_ranges: Some(allowed_ranges),
+    })
+}
+
 fn apply_clocks_config_to_table(
     config: &ClocksConfiguration,
     table: &mut ClocksTableGen,
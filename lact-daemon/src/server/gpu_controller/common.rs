@@ -1,5 +1,7 @@
+pub mod apply_script;
 pub mod fan_control;
 pub mod fdinfo;
+pub mod system_sensors;
 
 use libcopes::PID;
 use std::io;
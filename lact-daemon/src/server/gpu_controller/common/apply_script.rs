@@ -0,0 +1,188 @@
+//! Conversion of GPU settings into a standalone script, which applies them without the daemon.
+use anyhow::Context;
+use lact_schema::ApplyScriptFormat;
+use std::{fmt::Write, fs, path::PathBuf};
+
+/// A single step of applying a GPU config
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApplyStep {
+    /// Write a value to a sysfs file
+    Write { path: PathBuf, value: String },
+    /// A setting which can only be applied by the daemon
+    Unsupported(String),
+}
+
+impl ApplyStep {
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn write(path: impl Into<PathBuf>, value: impl ToString) -> Self {
+        Self::Write {
+            path: path.into(),
+            value: value.to_string(),
+        }
+    }
+
+    /// A write to a PowerPlay table file, the kernel expects these commands to end with a newline
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn command(path: impl Into<PathBuf>, value: impl ToString) -> Self {
+        let mut value = value.to_string();
+        if !value.ends_with('\n') {
+            value.push('\n');
+        }
+        Self::Write {
+            path: path.into(),
+            value,
+        }
+    }
+
+    /// Performs the write. Unsupported steps are skipped.
+    pub fn apply(&self) -> anyhow::Result<()> {
+        if let Self::Write { path, value } = self {
+            fs::write(path, value)
+                .with_context(|| format!("Could not write '{value}' to '{}'", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn render(steps: &[ApplyStep], format: ApplyScriptFormat, description: &str) -> String {
+    let mut out = String::new();
+
+    match format {
+        ApplyScriptFormat::Shell => {
+            writeln!(out, "#!/bin/sh").unwrap();
+            writeln!(out, "# {description}").unwrap();
+            writeln!(out, "# Generated by LACT, the script needs to be generated again if the hardware or kernel changes").unwrap();
+            writeln!(out, "set -e").unwrap();
+            writeln!(out).unwrap();
+
+            for step in steps {
+                match step {
+                    ApplyStep::Write { .. } => writeln!(out, "{}", shell_command(step)),
+                    ApplyStep::Unsupported(text) => writeln!(out, "# Not applied: {text}"),
+                }
+                .unwrap();
+            }
+        }
+        ApplyScriptFormat::Systemd => {
+            writeln!(out, "# Generated by LACT, the unit needs to be generated again if the hardware or kernel changes").unwrap();
+            writeln!(out, "[Unit]").unwrap();
+            writeln!(out, "Description={}", escape_systemd(description)).unwrap();
+            writeln!(out, "After=systemd-modules-load.service").unwrap();
+            writeln!(out, "Conflicts=lactd.service").unwrap();
+            writeln!(out).unwrap();
+            writeln!(out, "[Service]").unwrap();
+            writeln!(out, "Type=oneshot").unwrap();
+            writeln!(out, "RemainAfterExit=yes").unwrap();
+
+            for step in steps {
+                match step {
+                    ApplyStep::Write { .. } => {
+                        let command = shell_command(step)
+                            .replace('\\', "\\\\")
+                            .replace('"', "\\\"");
+                        writeln!(out, "ExecStart=/bin/sh -c \"{}\"", escape_systemd(&command))
+                    }
+                    ApplyStep::Unsupported(text) => writeln!(out, "# Not applied: {text}"),
+                }
+                .unwrap();
+            }
+
+            writeln!(out).unwrap();
+            writeln!(out, "[Install]").unwrap();
+            writeln!(out, "WantedBy=multi-user.target").unwrap();
+        }
+    }
+
+    out
+}
+
+fn shell_command(step: &ApplyStep) -> String {
+    match step {
+        // `echo` already terminates the value with a newline
+        ApplyStep::Write { path, value } => format!(
+            "echo {} > {}",
+            quote_shell(value.strip_suffix('\n').unwrap_or(value)),
+            quote_shell(&path.to_string_lossy())
+        ),
+        ApplyStep::Unsupported(_) => String::new(),
+    }
+}
+
+fn quote_shell(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Escapes the characters which systemd expands in unit files
+fn escape_systemd(value: &str) -> String {
+    value.replace('%', "%%").replace('$', "$$")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, ApplyStep};
+    use lact_schema::ApplyScriptFormat;
+    use pretty_assertions::assert_eq;
+
+    fn steps() -> Vec<ApplyStep> {
+        vec![
+            ApplyStep::write(
+                "/sys/devices/pci0000:00/0000:00:01.0/0000:03:00.0/power_dpm_force_performance_level",
+                "manual",
+            ),
+            ApplyStep::command("/sys/class/drm/card0/device/pp_od_clk_voltage", "s 1 2000"),
+            ApplyStep::Unsupported("Fan curve".to_owned()),
+            ApplyStep::write("/tmp/it's", "100%"),
+        ]
+    }
+
+    #[test]
+    fn render_shell() {
+        let expected = r"#!/bin/sh
+# Settings for GPU 1002:73BF
+# Generated by LACT, the script needs to be generated again if the hardware or kernel changes
+set -e
+
+echo 'manual' > '/sys/devices/pci0000:00/0000:00:01.0/0000:03:00.0/power_dpm_force_performance_level'
+echo 's 1 2000' > '/sys/class/drm/card0/device/pp_od_clk_voltage'
+# Not applied: Fan curve
+echo '100%' > '/tmp/it'\''s'
+";
+        assert_eq!(
+            expected,
+            render(
+                &steps(),
+                ApplyScriptFormat::Shell,
+                "Settings for GPU 1002:73BF"
+            )
+        );
+    }
+
+    #[test]
+    fn render_systemd() {
+        let expected = r#"# Generated by LACT, the unit needs to be generated again if the hardware or kernel changes
+[Unit]
+Description=Settings for GPU 1002:73BF
+After=systemd-modules-load.service
+Conflicts=lactd.service
+
+[Service]
+Type=oneshot
+RemainAfterExit=yes
+ExecStart=/bin/sh -c "echo 'manual' > '/sys/devices/pci0000:00/0000:00:01.0/0000:03:00.0/power_dpm_force_performance_level'"
+ExecStart=/bin/sh -c "echo 's 1 2000' > '/sys/class/drm/card0/device/pp_od_clk_voltage'"
+# Not applied: Fan curve
+ExecStart=/bin/sh -c "echo '100%%' > '/tmp/it'\\''s'"
+
+[Install]
+WantedBy=multi-user.target
+"#;
+        assert_eq!(
+            expected,
+            render(
+                &steps(),
+                ApplyScriptFormat::Systemd,
+                "Settings for GPU 1002:73BF"
+            )
+        );
+    }
+}
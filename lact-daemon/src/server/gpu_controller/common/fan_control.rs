@@ -1,12 +1,31 @@
 use amdgpu_sysfs::{gpu_handle::fan_control::FanCurve as PmfwCurve, hw_mon::Temperature};
 use anyhow::{anyhow, bail, Context};
-use lact_schema::config::FanCurve;
-use tracing::warn;
+use lact_schema::{
+    config::{FanControlSettings, FanCurve, FanCurveInterpolation, FanPidSettings},
+    FanCurveMap,
+};
+use std::{collections::HashMap, ops::RangeInclusive};
+use tracing::{trace, warn};
 
 pub trait FanCurveExt {
-    fn pwm_at_temp(&self, temp: Temperature) -> u8;
+    fn pwm_at_temp(&self, temp: Temperature, interpolation: FanCurveInterpolation) -> u8;
 
-    fn into_pmfw_curve(self, current_pmfw_curve: PmfwCurve) -> anyhow::Result<PmfwCurve>;
+    /// Speeds for a rising and a falling temperature, based on the falling curve or the hysteresis band
+    fn pwm_bounds(
+        &self,
+        temp: Temperature,
+        falling_curve: Option<&FanCurve>,
+        hysteresis: Option<u64>,
+        interpolation: FanCurveInterpolation,
+    ) -> (u8, u8);
+
+    /// The firmware always interpolates linearly between its points,
+    /// so with other interpolation modes the curve is resampled to the number of points that the firmware uses.
+    fn into_pmfw_curve(
+        self,
+        current_pmfw_curve: PmfwCurve,
+        interpolation: FanCurveInterpolation,
+    ) -> anyhow::Result<PmfwCurve>;
 
     fn validate(&self) -> anyhow::Result<()>;
 }
@@ -17,7 +36,7 @@ pub trait FanCurveExt {
     clippy::cast_sign_loss
 )]
 impl FanCurveExt for FanCurve {
-    fn pwm_at_temp(&self, temp: Temperature) -> u8 {
+    fn pwm_at_temp(&self, temp: Temperature, interpolation: FanCurveInterpolation) -> u8 {
         let current = temp.current.expect("No current temp");
 
         // This scenario is most likely unreachable as the kernel shuts down the GPU when it reaches critical temperature
@@ -26,28 +45,54 @@ impl FanCurveExt for FanCurve {
             return u8::MAX;
         }
 
-        let current = current as i32;
-        let maybe_lower = self.0.range(..current).next_back();
-        let maybe_higher = self.0.range(current..).next();
+        let percentage = interpolation.speed_at(&self.0, current as i32);
+        (f32::from(u8::MAX) * percentage) as u8
+    }
+
+    fn pwm_bounds(
+        &self,
+        temp: Temperature,
+        falling_curve: Option<&FanCurve>,
+        hysteresis: Option<u64>,
+        interpolation: FanCurveInterpolation,
+    ) -> (u8, u8) {
+        let rising_pwm = self.pwm_at_temp(
+            Temperature {
+                current: temp.current,
+                crit: temp.crit,
+                crit_hyst: temp.crit_hyst,
+            },
+            interpolation,
+        );
 
-        let percentage = match (maybe_lower, maybe_higher) {
-            (Some((lower_temp, lower_speed)), Some((higher_temp, higher_speed))) => {
-                let speed_ratio = (current - lower_temp) as f32 / (higher_temp - lower_temp) as f32;
-                lower_speed + (higher_speed - lower_speed) * speed_ratio
+        let falling_pwm = match falling_curve {
+            Some(falling_curve) => falling_curve.pwm_at_temp(temp, interpolation),
+            None => {
+                let hysteresis = hysteresis.unwrap_or(0) as f32;
+                // Evaluating the curve at a higher temperature shifts it to the left
+                self.pwm_at_temp(
+                    Temperature {
+                        current: temp.current.map(|current| current + hysteresis),
+                        ..temp
+                    },
+                    interpolation,
+                )
             }
-            (Some((_, lower_speed)), None) => *lower_speed,
-            (None, Some((_, higher_speed))) => *higher_speed,
-            (None, None) => panic!("Could not find fan speed on the curve! This is a bug."),
         };
 
-        (f32::from(u8::MAX) * percentage) as u8
+        (rising_pwm, falling_pwm)
     }
 
-    fn into_pmfw_curve(self, current_pmfw_curve: PmfwCurve) -> anyhow::Result<PmfwCurve> {
-        if current_pmfw_curve.points.len() != self.0.len() {
+    fn into_pmfw_curve(
+        self,
+        current_pmfw_curve: PmfwCurve,
+        interpolation: FanCurveInterpolation,
+    ) -> anyhow::Result<PmfwCurve> {
+        let points_count = current_pmfw_curve.points.len();
+        if interpolation == FanCurveInterpolation::Linear && points_count != self.0.len() {
             return Err(anyhow!(
                 "The GPU only supports {} curve points, given {}",
-                current_pmfw_curve.points.len(),
+                points_count,
                 self.0.len()
             ));
         }
@@ -59,8 +104,12 @@ impl FanCurveExt for FanCurve {
         let min_temp = *allowed_ranges.temperature_range.start();
         let max_temp = *allowed_ranges.temperature_range.end();
 
-        let points = self
-            .0
+        let curve = match interpolation {
+            FanCurveInterpolation::Linear => self.0,
+            _ => resample_curve(&self.0, interpolation, points_count, min_temp..=max_temp)?,
+        };
+
+        let points = curve
             .into_iter()
             .map(|(temp, ratio)| {
                 let custom_percent = (ratio * 100.0) as u8;
@@ -93,12 +142,275 @@ impl FanCurveExt for FanCurve {
     }
 }
 
+/// Samples the curve at evenly spaced temperatures between its first and last points,
+/// limited to the allowed temperature range
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn resample_curve(
+    curve: &FanCurveMap,
+    interpolation: FanCurveInterpolation,
+    points_count: usize,
+    temperature_range: RangeInclusive<i32>,
+) -> anyhow::Result<FanCurveMap> {
+    let (Some(first_temp), Some(last_temp)) = (curve.keys().next(), curve.keys().next_back())
+    else {
+        bail!("Cannot use empty fan curve");
+    };
+    let start = (*first_temp).clamp(*temperature_range.start(), *temperature_range.end());
+    let end = (*last_temp).clamp(*temperature_range.start(), *temperature_range.end());
+
+    let steps = points_count.saturating_sub(1) as i32;
+    if steps == 0 || end - start < steps {
+        bail!("The fan curve needs to span at least {steps}℃ to be used with the GPU firmware");
+    }
+
+    Ok((0..=steps)
+        .map(|step| {
+            let temp = start + (end - start) * step / steps;
+            (temp, interpolation.speed_at(curve, temp))
+        })
+        .collect())
+}
+
+/// Target speed over the main sensor and the extra sensors from the settings, whichever asks for the highest speed.
+///
+/// The speed only goes up once a regular curve is above the previous speed,
+/// and only goes down once the falling curves are below it.
+/// Extra sensors that are missing from `extra_temps` are skipped.
+pub fn target_pwm(
+    curve: &FanCurve,
+    settings: &FanControlSettings,
+    main_temp: Temperature,
+    extra_temps: &HashMap<String, Temperature>,
+    previous_pwm: Option<u8>,
+) -> u8 {
+    let (mut rising_pwm, mut falling_pwm) = curve.pwm_bounds(
+        main_temp,
+        settings.falling_curve.as_ref(),
+        settings.hysteresis,
+        settings.interpolation,
+    );
+
+    for sensor in &settings.extra_sensors {
+        let sensor_key = sensor.sensor_key();
+        let Some(temp) = extra_temps.get(&sensor_key) else {
+            trace!("sensor {sensor_key} is not available");
+            continue;
+        };
+
+        #[allow(clippy::cast_precision_loss)]
+        let offset = sensor.offset as f32;
+        let temp = Temperature {
+            current: temp.current.map(|current| current + offset),
+            crit: temp.crit.map(|crit| crit + offset),
+            crit_hyst: temp.crit_hyst,
+        };
+
+        let (sensor_rising, sensor_falling) = match &sensor.curve {
+            Some(sensor_curve) => {
+                sensor_curve.pwm_bounds(temp, None, settings.hysteresis, settings.interpolation)
+            }
+            None => curve.pwm_bounds(
+                temp,
+                settings.falling_curve.as_ref(),
+                settings.hysteresis,
+                settings.interpolation,
+            ),
+        };
+        rising_pwm = rising_pwm.max(sensor_rising);
+        falling_pwm = falling_pwm.max(sensor_falling);
+    }
+
+    match previous_pwm {
+        Some(previous_pwm) => {
+            previous_pwm.clamp(rising_pwm.min(falling_pwm), rising_pwm.max(falling_pwm))
+        }
+        None => rising_pwm,
+    }
+}
+
+/// The largest change of any sensor since the last readings. Sensors without a previous reading count as changed.
+pub fn max_temp_change(last_temps: &HashMap<String, f32>, temps: &HashMap<String, f32>) -> f32 {
+    temps
+        .iter()
+        .map(|(key, current)| match last_temps.get(key) {
+            Some(last) => (last - current).abs(),
+            None => f32::INFINITY,
+        })
+        .fold(0.0, f32::max)
+}
+
+/// Limits how quickly the fan speed changes according to `max_speed_increase` and `max_speed_decrease`.
+/// `elapsed` is the time since the speed was last checked in seconds.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn limit_pwm_change(
+    settings: &FanControlSettings,
+    current_pwm: Option<u8>,
+    target_pwm: u8,
+    elapsed: f32,
+) -> u8 {
+    let Some(current_pwm) = current_pwm else {
+        return target_pwm;
+    };
+    // Always allow a change of at least one step so that slow rates still make progress
+    let max_step = |rate: f32| (rate.abs() * f32::from(u8::MAX) * elapsed).round().max(1.0) as u8;
+
+    if target_pwm > current_pwm {
+        match settings.max_speed_increase {
+            Some(rate) => target_pwm.min(current_pwm.saturating_add(max_step(rate))),
+            None => target_pwm,
+        }
+    } else {
+        match settings.max_speed_decrease {
+            Some(rate) => target_pwm.max(current_pwm.saturating_sub(max_step(rate))),
+            None => target_pwm,
+        }
+    }
+}
+
+/// Exponential moving average of the sensor readings
+pub struct TemperatureSmoothing {
+    time_constant: f32,
+    values: HashMap<String, f32>,
+}
+
+impl TemperatureSmoothing {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(time_constant_ms: u64) -> Self {
+        Self {
+            time_constant: time_constant_ms as f32 / 1000.0,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Replaces the current value of the sensor with the smoothed one.
+    /// `elapsed` is the time since the last reading in seconds.
+    pub fn apply(&mut self, key: &str, temp: &mut Temperature, elapsed: f32) {
+        let Some(current) = temp.current else {
+            return;
+        };
+
+        let smoothed = match self.values.get(key) {
+            Some(last) if self.time_constant > 0.0 => {
+                let weight = 1.0 - (-elapsed / self.time_constant).exp();
+                last + (current - last) * weight
+            }
+            _ => current,
+        };
+        self.values.insert(key.to_owned(), smoothed);
+        temp.current = Some(smoothed);
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn speed_to_pwm(speed: f32) -> u8 {
+    (f32::from(u8::MAX) * speed).round() as u8
+}
+
+/// Target temperatures that are accepted for the `target_temperature` mode, in °C
+const PID_TARGET_TEMPERATURE_RANGE: RangeInclusive<f32> = 30.0..=100.0;
+
+/// Adjusts the fan speed to hold a temperature at the target
+pub struct PidController {
+    settings: FanPidSettings,
+    speed_range: RangeInclusive<f32>,
+    integral: f32,
+    last_error: Option<f32>,
+    speed: f32,
+}
+
+impl PidController {
+    /// `speed` is the current fan speed, which the controller starts from
+    pub fn new(settings: FanPidSettings, speed_range: RangeInclusive<f32>, speed: f32) -> Self {
+        let speed = speed.clamp(*speed_range.start(), *speed_range.end());
+        // Starting with the integral at the current speed avoids a jump on the first update
+        let integral = if settings.ki > 0.0 {
+            speed / settings.ki
+        } else {
+            0.0
+        };
+
+        Self {
+            settings,
+            speed_range,
+            integral,
+            last_error: None,
+            speed,
+        }
+    }
+
+    pub fn validate_settings(settings: &FanPidSettings) -> anyhow::Result<()> {
+        if !PID_TARGET_TEMPERATURE_RANGE.contains(&settings.target_temperature) {
+            bail!(
+                "Target temperature must be between {}°C and {}°C",
+                PID_TARGET_TEMPERATURE_RANGE.start(),
+                PID_TARGET_TEMPERATURE_RANGE.end()
+            );
+        }
+
+        for (name, gain) in [
+            ("kp", settings.kp),
+            ("ki", settings.ki),
+            ("kd", settings.kd),
+        ] {
+            if gain.is_nan() || gain < 0.0 {
+                bail!("PID gain {name} must not be negative");
+            }
+        }
+
+        if let Some(max_speed_change) = settings.max_speed_change {
+            if max_speed_change.is_nan() || max_speed_change <= 0.0 {
+                bail!("Maximum speed change must be above 0");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the new fan speed from 0 to 1. `elapsed` is the time since the last update in seconds.
+    pub fn update(&mut self, temp: f32, elapsed: f32) -> f32 {
+        let settings = &self.settings;
+        let (min_speed, max_speed) = (*self.speed_range.start(), *self.speed_range.end());
+
+        let error = temp - settings.target_temperature;
+        let derivative = match self.last_error {
+            Some(last_error) if elapsed > 0.0 => (error - last_error) / elapsed,
+            _ => 0.0,
+        };
+        let integral = self.integral + error * elapsed;
+
+        let output = settings.kp * error + settings.ki * integral + settings.kd * derivative;
+        let clamped_output = output.clamp(min_speed, max_speed);
+
+        // Stop integrating while the output is saturated, otherwise the controller would
+        // overshoot for a long time once the temperature goes the other way
+        let saturated = (output > max_speed && error > 0.0) || (output < min_speed && error < 0.0);
+        if !saturated {
+            self.integral = integral;
+        }
+        self.last_error = Some(error);
+
+        self.speed = match settings.max_speed_change {
+            Some(max_change) => {
+                let max_step = max_change.abs() * elapsed;
+                clamped_output.clamp(self.speed - max_step, self.speed + max_step)
+            }
+            None => clamped_output,
+        };
+        self.speed
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{FanCurve, PmfwCurve};
+    use super::{
+        limit_pwm_change, max_temp_change, target_pwm, FanControlSettings, FanCurve,
+        FanCurveInterpolation, PidController, PmfwCurve, TemperatureSmoothing,
+    };
     use crate::server::gpu_controller::common::fan_control::FanCurveExt;
     use amdgpu_sysfs::{gpu_handle::fan_control::FanCurveRanges, hw_mon::Temperature};
     use anyhow::anyhow;
+    use lact_schema::config::{FanPidSettings, FanSensorCurve};
+    use std::collections::HashMap;
 
     fn simple_pwm(temp: f32) -> u8 {
         let curve = FanCurve([(0, 0.0), (100, 1.0)].into());
@@ -107,7 +419,7 @@ mod tests {
             crit: Some(150.0),
             crit_hyst: Some(-100.0),
         };
-        curve.pwm_at_temp(temp)
+        curve.pwm_at_temp(temp, FanCurveInterpolation::Linear)
     }
 
     #[test]
@@ -148,7 +460,7 @@ mod tests {
             crit: Some(90.0),
             crit_hyst: Some(0.0),
         };
-        let pwm = curve.pwm_at_temp(temp);
+        let pwm = curve.pwm_at_temp(temp, FanCurveInterpolation::Linear);
         assert_eq!(pwm, 255);
     }
 
@@ -161,7 +473,7 @@ mod tests {
                 crit: Some(90.0),
                 crit_hyst: Some(0.0),
             };
-            curve.pwm_at_temp(temp)
+            curve.pwm_at_temp(temp, FanCurveInterpolation::Linear)
         };
 
         assert_eq!(pwm_at_temp(30.0), 0);
@@ -182,7 +494,7 @@ mod tests {
                 crit: Some(90.0),
                 crit_hyst: Some(0.0),
             };
-            curve.pwm_at_temp(temp)
+            curve.pwm_at_temp(temp, FanCurveInterpolation::Linear)
         };
         assert_eq!(pwm_at_temp(40.0), 76);
         assert_eq!(pwm_at_temp(60.0), 127);
@@ -193,6 +505,236 @@ mod tests {
         assert_eq!(pwm_at_temp(100.0), 255);
     }
 
+    #[test]
+    fn curve_interpolation() {
+        let curve = FanCurve([(40, 0.2), (50, 0.3), (60, 0.8), (70, 0.9)].into());
+        let pwm_at_temp = |current: f32, interpolation| {
+            let temp = Temperature {
+                current: Some(current),
+                crit: Some(90.0),
+                crit_hyst: Some(0.0),
+            };
+            curve.pwm_at_temp(temp, interpolation)
+        };
+
+        assert_eq!(pwm_at_temp(55.0, FanCurveInterpolation::Step), 76);
+        assert_eq!(pwm_at_temp(60.0, FanCurveInterpolation::Step), 204);
+        assert_eq!(pwm_at_temp(55.0, FanCurveInterpolation::Linear), 140);
+
+        // Goes through the points without overshooting them
+        let mut previous = 0;
+        for temp in 30..=80 {
+            let pwm = pwm_at_temp(temp as f32, FanCurveInterpolation::MonotoneCubic);
+            assert!(pwm >= previous, "speed drops at {temp}°C");
+            assert!((51..=229).contains(&pwm));
+            previous = pwm;
+        }
+        assert_eq!(pwm_at_temp(50.0, FanCurveInterpolation::MonotoneCubic), 76);
+        assert_eq!(pwm_at_temp(55.0, FanCurveInterpolation::MonotoneCubic), 140);
+        assert_eq!(pwm_at_temp(65.0, FanCurveInterpolation::MonotoneCubic), 218);
+    }
+
+    #[test]
+    fn curve_hysteresis() {
+        let curve = FanCurve([(40, 0.2), (80, 1.0)].into());
+        let pwm_at_temp = |settings: &FanControlSettings, current: f32, previous: Option<u8>| {
+            let temp = Temperature {
+                current: Some(current),
+                crit: Some(90.0),
+                crit_hyst: Some(0.0),
+            };
+            target_pwm(&curve, settings, temp, &HashMap::new(), previous)
+        };
+
+        let settings = FanControlSettings {
+            hysteresis: Some(5),
+            ..Default::default()
+        };
+        let rising = pwm_at_temp(&settings, 60.0, None);
+        assert_eq!(rising, 153);
+        // Heating up follows the regular curve
+        assert_eq!(pwm_at_temp(&settings, 62.0, Some(rising)), 163);
+        // Cooling down within the band keeps the speed
+        assert_eq!(pwm_at_temp(&settings, 56.0, Some(rising)), rising);
+        // Below the band, the curve shifted by 5°C is used
+        assert_eq!(pwm_at_temp(&settings, 50.0, Some(rising)), 127);
+
+        let settings = FanControlSettings {
+            falling_curve: Some(FanCurve([(30, 0.2), (70, 1.0)].into())),
+            ..Default::default()
+        };
+        assert_eq!(pwm_at_temp(&settings, 52.0, Some(rising)), rising);
+        assert_eq!(pwm_at_temp(&settings, 40.0, Some(rising)), 102);
+        assert_eq!(pwm_at_temp(&settings, 70.0, Some(rising)), 204);
+
+        // Without any hysteresis settings, the curve is followed in both directions
+        let settings = FanControlSettings::default();
+        assert_eq!(pwm_at_temp(&settings, 50.0, Some(rising)), 102);
+    }
+
+    #[test]
+    fn multi_sensor_curve() {
+        let curve = FanCurve([(40, 0.2), (80, 1.0)].into());
+        let settings = FanControlSettings {
+            extra_sensors: vec![
+                FanSensorCurve {
+                    temperature_key: "junction".to_owned(),
+                    hwmon: None,
+                    curve: None,
+                    offset: -10,
+                },
+                FanSensorCurve {
+                    temperature_key: "mem".to_owned(),
+                    hwmon: None,
+                    curve: Some(FanCurve([(60, 0.0), (100, 1.0)].into())),
+                    offset: 0,
+                },
+            ],
+            ..Default::default()
+        };
+        let temp = |current: f32| Temperature {
+            current: Some(current),
+            crit: None,
+            crit_hyst: None,
+        };
+        let pwm_at_temps = |edge: f32, junction: f32, mem: f32| {
+            let extra_temps = HashMap::from([
+                ("junction".to_owned(), temp(junction)),
+                ("mem".to_owned(), temp(mem)),
+            ]);
+            target_pwm(&curve, &settings, temp(edge), &extra_temps, None)
+        };
+
+        assert_eq!(pwm_at_temps(60.0, 65.0, 70.0), 153);
+        // Junction at 80°C is treated as 70°C
+        assert_eq!(pwm_at_temps(60.0, 80.0, 70.0), 204);
+        assert_eq!(pwm_at_temps(60.0, 65.0, 90.0), 191);
+
+        // A missing sensor does not affect the speed
+        let pwm = target_pwm(&curve, &settings, temp(60.0), &HashMap::new(), None);
+        assert_eq!(pwm, 153);
+    }
+
+    #[test]
+    fn temp_change() {
+        let last_temps = HashMap::from([("edge".to_owned(), 50.0), ("mem".to_owned(), 70.0)]);
+        let temps = HashMap::from([("edge".to_owned(), 51.0), ("mem".to_owned(), 67.0)]);
+        assert_eq!(max_temp_change(&last_temps, &temps), 3.0);
+
+        let temps = HashMap::from([("junction".to_owned(), 60.0)]);
+        assert_eq!(max_temp_change(&last_temps, &temps), f32::INFINITY);
+    }
+
+    #[test]
+    fn pid_target_temperature() {
+        let settings = FanPidSettings {
+            target_temperature: 70.0,
+            kp: 0.05,
+            ki: 0.01,
+            kd: 0.0,
+            max_speed_change: Some(0.1),
+        };
+        let mut pid = PidController::new(settings, 0.2..=1.0, 0.4);
+
+        // The speed only goes up by 10% per second
+        assert!((pid.update(80.0, 1.0) - 0.5).abs() < 0.001);
+        assert!((pid.update(80.0, 1.0) - 0.6).abs() < 0.001);
+
+        // Holding at the target keeps the speed
+        let mut speed = 0.0;
+        for _ in 0..100 {
+            speed = pid.update(70.0, 1.0);
+        }
+        assert!((pid.update(70.0, 1.0) - speed).abs() < 0.001);
+
+        // Never goes outside of the allowed range
+        for _ in 0..100 {
+            speed = pid.update(40.0, 1.0);
+        }
+        assert_eq!(speed, 0.2);
+        for _ in 0..100 {
+            speed = pid.update(100.0, 1.0);
+        }
+        assert_eq!(speed, 1.0);
+
+        // No windup from being stuck at the maximum speed
+        let speed = pid.update(69.0, 1.0);
+        assert!(speed < 1.0);
+    }
+
+    #[test]
+    fn pid_settings_validation() {
+        assert!(PidController::validate_settings(&FanPidSettings::default()).is_ok());
+
+        let invalid = [
+            FanPidSettings {
+                target_temperature: 150.0,
+                ..Default::default()
+            },
+            FanPidSettings {
+                target_temperature: f32::NAN,
+                ..Default::default()
+            },
+            FanPidSettings {
+                kp: -0.1,
+                ..Default::default()
+            },
+            FanPidSettings {
+                ki: f32::NAN,
+                ..Default::default()
+            },
+            FanPidSettings {
+                max_speed_change: Some(0.0),
+                ..Default::default()
+            },
+        ];
+        for settings in invalid {
+            assert!(
+                PidController::validate_settings(&settings).is_err(),
+                "{settings:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn pwm_ramp_rate() {
+        let settings = FanControlSettings {
+            max_speed_increase: Some(0.2),
+            max_speed_decrease: Some(0.1),
+            ..Default::default()
+        };
+
+        assert_eq!(limit_pwm_change(&settings, None, 200, 0.5), 200);
+        assert_eq!(limit_pwm_change(&settings, Some(100), 200, 0.5), 126);
+        assert_eq!(limit_pwm_change(&settings, Some(100), 110, 0.5), 110);
+        assert_eq!(limit_pwm_change(&settings, Some(100), 0, 0.5), 87);
+        // Very slow rates still move by at least one step
+        assert_eq!(limit_pwm_change(&settings, Some(100), 0, 0.01), 99);
+
+        let settings = FanControlSettings::default();
+        assert_eq!(limit_pwm_change(&settings, Some(100), 0, 0.5), 0);
+    }
+
+    #[test]
+    fn temperature_smoothing() {
+        let mut smoothing = TemperatureSmoothing::new(1000);
+        let mut smooth = |current: f32| {
+            let mut temp = Temperature {
+                current: Some(current),
+                crit: None,
+                crit_hyst: None,
+            };
+            smoothing.apply("edge", &mut temp, 1.0);
+            temp.current.unwrap()
+        };
+
+        assert_eq!(smooth(50.0), 50.0);
+        // A spike only moves the value part of the way
+        let spike = smooth(80.0);
+        assert!((spike - 68.96).abs() < 0.01);
+        assert!(smooth(50.0) < spike);
+    }
+
     #[test]
     fn default_curve_to_pmfw() {
         let curve = FanCurve::default();
@@ -203,11 +745,36 @@ mod tests {
                 speed_range: 30..=100,
             }),
         };
-        let pmfw_curve = curve.into_pmfw_curve(current_pmfw_curve).unwrap();
+        let pmfw_curve = curve
+            .into_pmfw_curve(current_pmfw_curve, FanCurveInterpolation::Linear)
+            .unwrap();
         let expected_points = [(40, 30), (50, 35), (60, 50), (70, 75), (80, 100)];
         assert_eq!(&expected_points, pmfw_curve.points.as_ref());
     }
 
+    #[test]
+    fn resampled_curve_to_pmfw() {
+        let curve = FanCurve([(30, 0.3), (60, 0.5), (90, 1.0)].into());
+        let current_pmfw_curve = PmfwCurve {
+            points: Box::new([(0, 0); 5]),
+            allowed_ranges: Some(FanCurveRanges {
+                temperature_range: 25..=100,
+                speed_range: 30..=100,
+            }),
+        };
+        let pmfw_curve = curve
+            .clone()
+            .into_pmfw_curve(current_pmfw_curve.clone(), FanCurveInterpolation::Step)
+            .unwrap();
+        let expected_points = [(30, 30), (45, 30), (60, 50), (75, 50), (90, 100)];
+        assert_eq!(&expected_points, pmfw_curve.points.as_ref());
+
+        // Linear curves have to match the point count exactly
+        assert!(curve
+            .into_pmfw_curve(current_pmfw_curve, FanCurveInterpolation::Linear)
+            .is_err());
+    }
+
     #[test]
     fn curve_outside_of_limits_to_pmfw() {
         let curve_invalid_temp =
@@ -225,14 +792,14 @@ mod tests {
         assert_eq!(
             anyhow!("Temperature 20℃ is outside of the allowed range 25℃ to 100℃").to_string(),
             curve_invalid_temp
-                .into_pmfw_curve(current_pmfw_curve.clone())
+                .into_pmfw_curve(current_pmfw_curve.clone(), FanCurveInterpolation::Linear)
                 .unwrap_err()
                 .to_string()
         );
         assert_eq!(
             anyhow!("Speed 10% is outside of the allowed range 30% to 100%").to_string(),
             curve_invalid_speed
-                .into_pmfw_curve(current_pmfw_curve)
+                .into_pmfw_curve(current_pmfw_curve, FanCurveInterpolation::Linear)
                 .unwrap_err()
                 .to_string()
         );
@@ -0,0 +1,323 @@
+//! Temperature sensors of hwmon devices outside of the GPU, such as the CPU, NVMe drives or AIO coolers.
+use amdgpu_sysfs::hw_mon::Temperature;
+use anyhow::{bail, Context};
+use lact_schema::config::FanSensorCurve;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::{error, info};
+
+fn hwmon_base_path() -> PathBuf {
+    match env::var("_LACT_HWMON_SYSFS_PATH") {
+        Ok(custom_path) => PathBuf::from(custom_path),
+        Err(_) => PathBuf::from("/sys/class/hwmon"),
+    }
+}
+
+/// The extra sensors of a fan curve which come from system hwmon devices.
+///
+/// A sensor is referenced by the hwmon device name (e.g. `k10temp`) and the sensor label (e.g. `Tctl`).
+/// Sensors without a label can be referenced by their file name, such as `temp1`.
+/// When there are multiple devices with the same name, the first one is used.
+///
+/// The device and the sensor file are looked up when fan control starts,
+/// and only looked up again after reading the sensor fails, as hwmon devices can be renumbered when they are re-created.
+pub struct SystemSensors {
+    base_path: PathBuf,
+    sensors: Vec<SystemSensor>,
+    /// Sensors that could not be read, so that the error is only logged when they first disappear
+    missing_sensors: HashSet<String>,
+}
+
+struct SystemSensor {
+    key: String,
+    hwmon_name: String,
+    label: String,
+    location: Option<SensorLocation>,
+}
+
+struct SensorLocation {
+    device_path: PathBuf,
+    /// File name prefix of the sensor, such as `temp1`
+    prefix: String,
+}
+
+impl SystemSensors {
+    /// Fails if any of the sensors can't be read
+    pub fn new(sensors: &[FanSensorCurve]) -> anyhow::Result<Self> {
+        Self::with_base_path(hwmon_base_path(), sensors)
+    }
+
+    fn with_base_path(base_path: PathBuf, sensors: &[FanSensorCurve]) -> anyhow::Result<Self> {
+        let mut system_sensors = Self {
+            base_path,
+            sensors: Vec::new(),
+            missing_sensors: HashSet::new(),
+        };
+
+        for sensor in sensors {
+            let Some(hwmon_name) = &sensor.hwmon else {
+                continue;
+            };
+            let mut system_sensor = SystemSensor {
+                key: sensor.sensor_key(),
+                hwmon_name: hwmon_name.clone(),
+                label: sensor.temperature_key.clone(),
+                location: None,
+            };
+            system_sensor.read(&system_sensors.base_path)?;
+            system_sensors.sensors.push(system_sensor);
+        }
+
+        Ok(system_sensors)
+    }
+
+    /// Adds the current values of the sensors to `temps`, keyed by [`FanSensorCurve::sensor_key`].
+    /// A sensor that can't be read is skipped until it is available again.
+    pub fn read(&mut self, temps: &mut HashMap<String, Temperature>) {
+        for sensor in &mut self.sensors {
+            match sensor.read(&self.base_path) {
+                Ok(temp) => {
+                    if self.missing_sensors.remove(&sensor.key) {
+                        info!("sensor {} is available again", sensor.key);
+                    }
+                    temps.insert(sensor.key.clone(), temp);
+                }
+                Err(err) => {
+                    if self.missing_sensors.insert(sensor.key.clone()) {
+                        error!(
+                            "could not read sensor {}, ignoring it until it is available again: {err:#}",
+                            sensor.key
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl SystemSensor {
+    fn read(&mut self, base_path: &Path) -> anyhow::Result<Temperature> {
+        if let Some(location) = &self.location {
+            if let Ok(temp) = location.read() {
+                return Ok(temp);
+            }
+            self.location = None;
+        }
+
+        let location = find_sensor(base_path, &self.hwmon_name, &self.label)?;
+        let temp = location.read().with_context(|| {
+            format!(
+                "Could not read sensor {} on {}",
+                self.label, self.hwmon_name
+            )
+        })?;
+        self.location = Some(location);
+        Ok(temp)
+    }
+}
+
+impl SensorLocation {
+    fn read(&self) -> anyhow::Result<Temperature> {
+        let current = read_millidegrees(&self.device_path, &self.prefix, "input")?;
+        Ok(Temperature {
+            current: Some(current),
+            crit: read_millidegrees(&self.device_path, &self.prefix, "crit").ok(),
+            crit_hyst: read_millidegrees(&self.device_path, &self.prefix, "crit_hyst").ok(),
+        })
+    }
+}
+
+fn find_sensor(base_path: &Path, hwmon_name: &str, label: &str) -> anyhow::Result<SensorLocation> {
+    let mut devices = fs::read_dir(base_path)
+        .with_context(|| format!("Could not read {}", base_path.display()))?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = fs::read_to_string(path.join("name")).ok()?;
+            Some((name.trim().to_owned(), path))
+        })
+        .collect::<Vec<_>>();
+    devices.sort_by_cached_key(|(_, path)| {
+        let file_name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+        (name_index(file_name), path.clone())
+    });
+
+    let Some((_, device_path)) = devices.iter().find(|(name, _)| name == hwmon_name) else {
+        bail!(
+            "hwmon device {hwmon_name} not found, available devices: {}",
+            devices
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<&str>>()
+                .join(",")
+        );
+    };
+
+    let sensors = device_sensors(device_path)?;
+    let Some((_, prefix)) = sensors
+        .iter()
+        .find(|(sensor_label, prefix)| sensor_label == label || prefix == label)
+    else {
+        bail!(
+            "Sensor {label} not found on {hwmon_name}, available sensors: {}",
+            sensors
+                .iter()
+                .map(|(sensor_label, _)| sensor_label.as_str())
+                .collect::<Vec<&str>>()
+                .join(",")
+        );
+    };
+
+    Ok(SensorLocation {
+        device_path: device_path.clone(),
+        prefix: prefix.clone(),
+    })
+}
+
+/// Labels of the temperature sensors with their file prefix (e.g. `temp1`)
+fn device_sensors(device_path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let mut sensors = fs::read_dir(device_path)
+        .with_context(|| format!("Could not read {}", device_path.display()))?
+        .filter_map(|entry| {
+            let file_name = entry.ok()?.file_name();
+            let prefix = file_name.to_str()?.strip_suffix("_input")?;
+            if !prefix.starts_with("temp") {
+                return None;
+            }
+
+            let label = fs::read_to_string(device_path.join(format!("{prefix}_label")))
+                .map_or_else(|_| prefix.to_owned(), |label| label.trim().to_owned());
+            Some((label, prefix.to_owned()))
+        })
+        .collect::<Vec<_>>();
+    sensors.sort_by_cached_key(|(_, prefix)| (name_index(prefix), prefix.clone()));
+    Ok(sensors)
+}
+
+/// The number at the end of names like `hwmon10` or `temp2`, so that they are sorted numerically
+fn name_index(name: &str) -> Option<u32> {
+    name.trim_start_matches(|c: char| !c.is_ascii_digit())
+        .parse()
+        .ok()
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn read_millidegrees(device_path: &Path, prefix: &str, suffix: &str) -> anyhow::Result<f32> {
+    let raw = fs::read_to_string(device_path.join(format!("{prefix}_{suffix}")))?;
+    let value: i64 = raw.trim().parse().context("Invalid temperature value")?;
+    Ok(value as f32 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_sensor, SystemSensors};
+    use amdgpu_sysfs::hw_mon::Temperature;
+    use lact_schema::config::FanSensorCurve;
+    use std::{collections::HashMap, fs, path::Path, slice};
+
+    fn read_temperature_from(
+        base_path: &Path,
+        hwmon_name: &str,
+        label: &str,
+    ) -> anyhow::Result<Temperature> {
+        find_sensor(base_path, hwmon_name, label)?.read()
+    }
+
+    fn write_device(
+        base_path: &Path,
+        index: u32,
+        name: &str,
+        sensors: &[(u32, Option<&str>, i64)],
+    ) {
+        let device_path = base_path.join(format!("hwmon{index}"));
+        fs::create_dir_all(&device_path).unwrap();
+        fs::write(device_path.join("name"), format!("{name}\n")).unwrap();
+
+        for (sensor, label, value) in sensors {
+            fs::write(
+                device_path.join(format!("temp{sensor}_input")),
+                format!("{value}\n"),
+            )
+            .unwrap();
+            if let Some(label) = label {
+                fs::write(
+                    device_path.join(format!("temp{sensor}_label")),
+                    format!("{label}\n"),
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn read_system_sensor() {
+        let dir = tempfile::tempdir().unwrap();
+        write_device(
+            dir.path(),
+            0,
+            "k10temp",
+            &[(1, Some("Tctl"), 54_750), (3, Some("Tccd1"), 51_000)],
+        );
+        write_device(dir.path(), 1, "nvme", &[(1, None, 38_850)]);
+
+        let temp = read_temperature_from(dir.path(), "k10temp", "Tccd1").unwrap();
+        assert_eq!(temp.current, Some(51.0));
+        assert_eq!(temp.crit, None);
+
+        let temp = read_temperature_from(dir.path(), "nvme", "temp1").unwrap();
+        assert_eq!(temp.current, Some(38.85));
+
+        let err = read_temperature_from(dir.path(), "coretemp", "Package id 0").unwrap_err();
+        assert_eq!(
+            "hwmon device coretemp not found, available devices: k10temp,nvme",
+            err.to_string()
+        );
+        let err = read_temperature_from(dir.path(), "k10temp", "Tdie").unwrap_err();
+        assert_eq!(
+            "Sensor Tdie not found on k10temp, available sensors: Tctl,Tccd1",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn devices_sorted_numerically() {
+        let dir = tempfile::tempdir().unwrap();
+        write_device(dir.path(), 10, "nvme", &[(1, None, 45_000)]);
+        write_device(dir.path(), 2, "nvme", &[(1, None, 38_000)]);
+
+        let temp = read_temperature_from(dir.path(), "nvme", "temp1").unwrap();
+        assert_eq!(temp.current, Some(38.0));
+    }
+
+    #[test]
+    fn sensor_found_again_after_renumbering() {
+        let dir = tempfile::tempdir().unwrap();
+        write_device(dir.path(), 3, "nvme", &[(1, None, 38_000)]);
+
+        let curve = FanSensorCurve {
+            hwmon: Some("nvme".to_owned()),
+            temperature_key: "temp1".to_owned(),
+            curve: None,
+            offset: 0,
+        };
+        let mut sensors =
+            SystemSensors::with_base_path(dir.path().to_owned(), slice::from_ref(&curve)).unwrap();
+
+        let mut temps = HashMap::new();
+        sensors.read(&mut temps);
+        assert_eq!(temps[&curve.sensor_key()].current, Some(38.0));
+
+        fs::remove_dir_all(dir.path().join("hwmon3")).unwrap();
+        temps.clear();
+        sensors.read(&mut temps);
+        assert!(temps.is_empty());
+
+        write_device(dir.path(), 5, "nvme", &[(1, None, 40_000)]);
+        sensors.read(&mut temps);
+        assert_eq!(temps[&curve.sensor_key()].current, Some(40.0));
+    }
+}
@@ -7,7 +7,10 @@ use crate::{
         drm_xe_memory_class_DRM_XE_MEM_REGION_CLASS_VRAM, IntelDrm,
     },
     server::{
-        gpu_controller::common::fdinfo::{self, DrmUtilMap},
+        gpu_controller::common::{
+            apply_script::ApplyStep,
+            fdinfo::{self, DrmUtilMap},
+        },
         opencl::get_opencl_info,
         vulkan::get_vulkan_info,
     },
@@ -241,42 +244,29 @@ impl IntelGpuController {
             .map(|(contents, _)| contents)
     }
 
-    fn write_hwmon_file(
-        &self,
-        file_prefix: &str,
-        file_suffix: &str,
-        contents: &str,
-    ) -> anyhow::Result<()> {
-        debug!("writing value '{contents}' to '{file_prefix}*{file_suffix}'");
-
-        if let Some(hwmon_path) = &self.hwmon_path {
-            let mut files = Vec::with_capacity(1);
+    fn hwmon_file_path(&self, file_prefix: &str, file_suffix: &str) -> anyhow::Result<PathBuf> {
+        let hwmon_path = self.hwmon_path.as_ref().context("No hwmon available")?;
+        let mut files = Vec::with_capacity(1);
 
-            let entries = fs::read_dir(hwmon_path)?;
-            for entry in entries.flatten() {
-                if let Some(name) = entry.file_name().to_str() {
-                    if name.starts_with(file_prefix) && name.ends_with(file_suffix) {
-                        if let Some(infix) = name
-                            .strip_prefix(file_prefix)
-                            .and_then(|name| name.strip_suffix(file_suffix))
-                        {
-                            if !infix.contains('_') {
-                                files.push(entry.path());
-                            }
-                        }
+        let entries = fs::read_dir(hwmon_path)?;
+        for entry in entries.flatten() {
+            if let Some(name) = entry.file_name().to_str() {
+                if let Some(infix) = name
+                    .strip_prefix(file_prefix)
+                    .and_then(|name| name.strip_suffix(file_suffix))
+                {
+                    if !infix.contains('_') {
+                        files.push(entry.path());
                     }
                 }
             }
-            files.sort_unstable();
-
-            if let Some(entry) = files.first() {
-                self.write_file(entry, contents)
-            } else {
-                Err(anyhow!("File not found"))
-            }
-        } else {
-            Err(anyhow!("No hwmon available"))
         }
+        files.sort_unstable();
+
+        files
+            .into_iter()
+            .next()
+            .with_context(|| format!("File '{file_prefix}*{file_suffix}' not found"))
     }
 
     fn get_drm_info_i915(&self) -> IntelDrmInfo {
@@ -616,28 +606,45 @@ impl GpuController for IntelGpuController {
         })
     }
 
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
     fn apply_config<'a>(&'a self, config: &'a GpuConfig) -> LocalBoxFuture<'a, anyhow::Result<()>> {
         Box::pin(async {
-            if let Some(max_clock) = config.clocks_configuration.max_core_clock {
-                self.write_freq(FrequencyType::Max, max_clock)
-                    .context("Could not set max clock")?;
-            }
-
-            if let Some(min_clock) = config.clocks_configuration.min_core_clock {
-                self.write_freq(FrequencyType::Min, min_clock)
-                    .context("Could not set min clock")?;
-            }
-
-            if let Some(cap) = config.power_cap {
-                self.write_hwmon_file("power", "_max", &((cap * 1_000_000.0) as u64).to_string())
-                    .context("Could not set power cap")?;
+            for step in self.apply_config_steps(config)? {
+                debug!("applying {step:?}");
+                step.apply()?;
             }
 
             Ok(())
         })
     }
 
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn apply_config_steps(&self, config: &GpuConfig) -> anyhow::Result<Vec<ApplyStep>> {
+        let mut steps = Vec::new();
+
+        if let Some(max_clock) = config.clocks_configuration.max_core_clock {
+            let path = self
+                .freq_path(FrequencyType::Max)
+                .context("Could not set max clock: frequency info not found")?;
+            steps.push(ApplyStep::write(path, max_clock));
+        }
+
+        if let Some(min_clock) = config.clocks_configuration.min_core_clock {
+            let path = self
+                .freq_path(FrequencyType::Min)
+                .context("Could not set min clock: frequency info not found")?;
+            steps.push(ApplyStep::write(path, min_clock));
+        }
+
+        if let Some(cap) = config.power_cap {
+            let path = self
+                .hwmon_file_path("power", "_max")
+                .context("Could not set power cap")?;
+            steps.push(ApplyStep::write(path, (cap * 1_000_000.0) as u64));
+        }
+
+        Ok(steps)
+    }
+
     fn get_stats(&self, _gpu_config: Option<&GpuConfig>) -> DeviceStats {
         let current_gfxclk = self.read_freq(FrequencyType::Cur);
         let gpu_clockspeed = self
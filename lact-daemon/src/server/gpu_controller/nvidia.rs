@@ -5,7 +5,11 @@ use super::{CommonControllerInfo, FanControlHandle, GpuController};
 use crate::{
     bindings::nvidia::NvPhysicalGpuHandle,
     server::{
-        gpu_controller::{common::fan_control::FanCurveExt, common::resolve_process_name, NvApi},
+        gpu_controller::{
+            common::fan_control::{self, PidController, TemperatureSmoothing},
+            common::{resolve_process_name, system_sensors::SystemSensors},
+            NvApi,
+        },
         opencl::get_opencl_info,
         vulkan::get_vulkan_info,
     },
@@ -145,6 +149,11 @@ impl NvidiaGpuController {
         curve: FanCurve,
         settings: FanControlSettings,
     ) -> anyhow::Result<()> {
+        if settings.mode == FanControlMode::TargetTemperature {
+            PidController::validate_settings(&settings.pid.unwrap_or_default())
+                .context("Invalid target temperature settings")?;
+        }
+
         // Stop existing task to re-apply new curve
         self.stop_fan_control().await?;
 
@@ -158,6 +167,30 @@ impl NvidiaGpuController {
             return Err(anyhow!("Device has no fans"));
         }
 
+        if !settings.extra_sensors.is_empty() {
+            let temps = nvapi_temps(
+                self.nvapi.as_ref().as_ref(),
+                self.nvapi_handle,
+                self.nvapi_thermals_mask,
+            );
+            for sensor in &settings.extra_sensors {
+                if sensor.hwmon.is_none() && !temps.contains_key(&sensor.temperature_key) {
+                    bail!(
+                        "Extra sensor with name {} not found, available sensors: {}",
+                        sensor.temperature_key,
+                        temps
+                            .keys()
+                            .map(String::as_str)
+                            .collect::<Vec<&str>>()
+                            .join(",")
+                    );
+                }
+            }
+        }
+
+        let mut system_sensors =
+            SystemSensors::new(&settings.extra_sensors).context("Could not read extra sensor")?;
+
         let mut notify_guard = self
             .fan_control_handle
             .try_borrow_mut()
@@ -166,7 +199,27 @@ impl NvidiaGpuController {
         let notify = Rc::new(Notify::new());
         let task_notify = notify.clone();
 
+        let mut pid = if settings.mode == FanControlMode::TargetTemperature {
+            let (min_speed, max_speed) = device
+                .min_max_fan_speed()
+                .context("Could not get fan speed range")?;
+            let current_speed = device.fan_speed(0).unwrap_or(min_speed);
+            #[allow(clippy::cast_precision_loss)]
+            let percent_to_speed = |percent: u32| percent as f32 / 100.0;
+
+            Some(PidController::new(
+                settings.pid.unwrap_or_default(),
+                percent_to_speed(min_speed)..=percent_to_speed(max_speed),
+                percent_to_speed(current_speed),
+            ))
+        } else {
+            None
+        };
+
         let nvml = self.nvml.clone();
+        let nvapi = self.nvapi.clone();
+        let nvapi_handle = self.nvapi_handle;
+        let nvapi_thermals_mask = self.nvapi_thermals_mask;
         let pci_slot_id = self.common.pci_slot_name.clone();
         debug!("spawning new fan control task");
 
@@ -176,14 +229,20 @@ impl NvidiaGpuController {
                 .expect("Can no longer get device");
 
             let mut last_pwm = (None, Instant::now());
-            let mut last_temp = 0;
+            // Differs from the last target while the speed is being ramped up or down
+            let mut applied_pwm = None;
+            let mut last_temps = HashMap::new();
+            let mut last_tick = Instant::now();
+            let mut smoothing = settings
+                .temperature_smoothing_ms
+                .map(TemperatureSmoothing::new);
 
             let interval = Duration::from_millis(settings.interval_ms);
             let spindown_delay = Duration::from_millis(settings.spindown_delay_ms.unwrap_or(0));
-            #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
-            let change_threshold = settings.change_threshold.unwrap_or(0) as i32;
-            #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
-            let auto_threshold = settings.auto_threshold.unwrap_or(0) as i32;
+            #[allow(clippy::cast_precision_loss)]
+            let change_threshold = settings.change_threshold.unwrap_or(0) as f32;
+            #[allow(clippy::cast_precision_loss)]
+            let auto_threshold = settings.auto_threshold.unwrap_or(0) as f32;
 
             let mut manual_mode = true;
 
@@ -193,56 +252,117 @@ impl NvidiaGpuController {
                     () = task_notify.notified() => break,
                 }
 
-                #[allow(clippy::cast_possible_wrap)]
-                let current_temp = device
-                    .temperature(TemperatureSensor::Gpu)
-                    .expect("Could not read temperature") as i32;
+                let mut main_temp = Temperature {
+                    #[allow(clippy::cast_precision_loss)]
+                    current: Some(
+                        device
+                            .temperature(TemperatureSensor::Gpu)
+                            .expect("Could not read temperature") as f32,
+                    ),
+                    crit: None,
+                    crit_hyst: None,
+                };
+
+                let now = Instant::now();
+                let elapsed = (now - last_tick).as_secs_f32();
+                last_tick = now;
 
-                if (last_temp - current_temp).abs() < change_threshold {
-                    trace!("temperature changed from {last_temp}°C to {current_temp}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment");
-                    continue;
+                if let Some(smoothing) = &mut smoothing {
+                    smoothing.apply("GPU", &mut main_temp, elapsed);
                 }
+                let current_temp = main_temp.current.expect("Missing temp");
+
+                // Set while the fan is still being ramped towards the last target
+                let ramp_target = last_pwm.0.filter(|pwm| applied_pwm != Some(*pwm));
+
+                let target_pwm = if let Some(pid) = &mut pid {
+                    let speed = pid.update(current_temp, elapsed);
+                    let target_pwm = fan_control::speed_to_pwm(speed);
+                    last_pwm = (Some(target_pwm), now);
+                    target_pwm
+                } else {
+                    let mut extra_temps = if settings.extra_sensors.is_empty() {
+                        HashMap::new()
+                    } else {
+                        nvapi_temps(nvapi.as_ref().as_ref(), nvapi_handle, nvapi_thermals_mask)
+                    };
+                    system_sensors.read(&mut extra_temps);
+                    if let Some(smoothing) = &mut smoothing {
+                        for (key, value) in &mut extra_temps {
+                            smoothing.apply(key, value, elapsed);
+                        }
+                    }
 
-                if current_temp < auto_threshold {
-                    if manual_mode {
-                        trace!("temperature below auto threshold, setting fan policy to auto");
-                        for fan in 0..fan_count {
-                            if let Err(err) = device.set_default_fan_speed(fan) {
-                                error!(
-                                    "could not set fan speed to auto: {err}, disabling fan control"
-                                );
-                                break;
+                    let mut current_temps = extra_temps
+                        .iter()
+                        .filter_map(|(key, temp)| Some((key.clone(), temp.current?)))
+                        .collect::<HashMap<_, _>>();
+                    current_temps.insert("GPU".to_owned(), current_temp);
+
+                    let temp_change = fan_control::max_temp_change(&last_temps, &current_temps);
+                    let mut delayed = false;
+
+                    if temp_change < change_threshold {
+                        trace!("temperature changed by {temp_change}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment");
+                        delayed = true;
+                    } else if current_temp < auto_threshold {
+                        if manual_mode {
+                            trace!("temperature below auto threshold, setting fan policy to auto");
+                            for fan in 0..fan_count {
+                                if let Err(err) = device.set_default_fan_speed(fan) {
+                                    error!(
+                                        "could not set fan speed to auto: {err}, disabling fan control"
+                                    );
+                                    break;
+                                }
                             }
-                        }
 
-                        manual_mode = false;
-                    } else {
-                        trace!("temperature below auto threshold, skipping control");
+                            manual_mode = false;
+                            // The speed set by the firmware is unknown, so the curve starts over
+                            last_pwm.0 = None;
+                            applied_pwm = None;
+                        } else {
+                            trace!("temperature below auto threshold, skipping control");
+                        }
+                        continue;
                     }
-                    continue;
-                }
 
-                let target_pwm = curve.pwm_at_temp(Temperature {
-                    #[allow(clippy::cast_precision_loss)]
-                    current: Some(current_temp as f32),
-                    crit: None,
-                    crit_hyst: None,
-                });
-                let now = Instant::now();
+                    let target_pwm = fan_control::target_pwm(
+                        &curve,
+                        &settings,
+                        main_temp,
+                        &extra_temps,
+                        last_pwm.0,
+                    );
+
+                    if !delayed {
+                        if let (Some(previous_pwm), previous_timestamp) = last_pwm {
+                            let diff = now - previous_timestamp;
+                            if target_pwm < previous_pwm && diff < spindown_delay {
+                                trace!(
+                                    "delaying fan spindown ({}ms left)",
+                                    (spindown_delay - diff).as_millis()
+                                );
+                                delayed = true;
+                            }
+                        }
+                    }
 
-                if let (Some(previous_pwm), previous_timestamp) = last_pwm {
-                    let diff = now - previous_timestamp;
-                    if target_pwm < previous_pwm && diff < spindown_delay {
-                        trace!(
-                            "delaying fan spindown ({}ms left)",
-                            (spindown_delay - diff).as_millis()
-                        );
-                        continue;
+                    if delayed {
+                        match ramp_target {
+                            Some(ramp_target) => ramp_target,
+                            None => continue,
+                        }
+                    } else {
+                        last_temps = current_temps;
+                        last_pwm = (Some(target_pwm), now);
+                        target_pwm
                     }
-                }
+                };
 
-                last_pwm = (Some(target_pwm), now);
-                last_temp = current_temp;
+                let target_pwm =
+                    fan_control::limit_pwm_change(&settings, applied_pwm, target_pwm, elapsed);
+                applied_pwm = Some(target_pwm);
 
                 trace!("fan control tick: setting pwm to {target_pwm}");
 
@@ -477,34 +597,14 @@ impl GpuController for NvidiaGpuController {
 
         let mut voltage = None;
 
+        temps.extend(nvapi_temps(
+            self.nvapi.as_ref().as_ref(),
+            self.nvapi_handle,
+            self.nvapi_thermals_mask,
+        ));
+
         if let (Some(nvapi), Some(handle)) = (self.nvapi.as_ref(), self.nvapi_handle.as_ref()) {
             unsafe {
-                if let Some(mask) = self.nvapi_thermals_mask {
-                    if let Ok(thermals) = nvapi.get_thermals(*handle, mask) {
-                        if let Some(hotspot) = thermals.hotspot() {
-                            temps.insert(
-                                "GPU Hotspot".to_owned(),
-                                Temperature {
-                                    current: Some(hotspot as f32),
-                                    crit: None,
-                                    crit_hyst: None,
-                                },
-                            );
-                        }
-
-                        if let Some(vram) = thermals.vram() {
-                            temps.insert(
-                                "VRAM".to_owned(),
-                                Temperature {
-                                    current: Some(vram as f32),
-                                    crit: None,
-                                    crit_hyst: None,
-                                },
-                            );
-                        }
-                    }
-                }
-
                 if let Ok(value) = nvapi.get_voltage(*handle) {
                     voltage = Some(u64::from(value) / 1000);
                 }
@@ -564,8 +664,14 @@ impl GpuController for NvidiaGpuController {
                 control_mode: fan_settings.map(|settings| settings.mode),
                 static_speed: fan_settings.map(|settings| settings.static_speed),
                 curve: fan_settings.map(|settings| settings.curve.0.clone()),
+                interpolation: fan_settings.map(|settings| settings.interpolation),
                 spindown_delay_ms: fan_settings.and_then(|settings| settings.spindown_delay_ms),
                 change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
+                falling_curve: fan_settings
+                    .and_then(|settings| settings.falling_curve.as_ref())
+                    .map(|curve| curve.0.clone()),
+                hysteresis: fan_settings.and_then(|settings| settings.hysteresis),
+                pid: fan_settings.and_then(|settings| settings.pid),
                 auto_threshold: fan_settings.and_then(|settings| settings.auto_threshold),
                 temperature_key: None,
                 speed_current,
@@ -855,6 +961,12 @@ impl GpuController for NvidiaGpuController {
                         self.start_curve_fan_control_task(settings.curve.clone(), settings.clone())
                             .await?;
                     }
+
+                    FanControlMode::TargetTemperature => {
+                        self.start_curve_fan_control_task(settings.curve.clone(), settings.clone())
+                            .await
+                            .context("Could not start target temperature fan control")?;
+                    }
                 }
             } else {
                 self.stop_fan_control()
@@ -1004,3 +1116,41 @@ impl GpuController for NvidiaGpuController {
         })
     }
 }
+
+/// Sensors which are only available through NvAPI
+#[allow(clippy::cast_precision_loss)]
+fn nvapi_temps(
+    nvapi: Option<&NvApi>,
+    handle: Option<NvPhysicalGpuHandle>,
+    thermals_mask: Option<i32>,
+) -> HashMap<String, Temperature> {
+    let mut temps = HashMap::new();
+
+    if let (Some(nvapi), Some(handle), Some(mask)) = (nvapi, handle, thermals_mask) {
+        if let Ok(thermals) = unsafe { nvapi.get_thermals(handle, mask) } {
+            if let Some(hotspot) = thermals.hotspot() {
+                temps.insert(
+                    "GPU Hotspot".to_owned(),
+                    Temperature {
+                        current: Some(hotspot as f32),
+                        crit: None,
+                        crit_hyst: None,
+                    },
+                );
+            }
+
+            if let Some(vram) = thermals.vram() {
+                temps.insert(
+                    "VRAM".to_owned(),
+                    Temperature {
+                        current: Some(vram as f32),
+                        crit: None,
+                        crit_hyst: None,
+                    },
+                );
+            }
+        }
+    }
+
+    temps
+}
@@ -1,12 +1,22 @@
 use super::{
-    gpu_controller::{common::fan_control::FanCurveExt, DynGpuController, GpuController},
-    profiles::ProfileWatcherCommand,
+    gpu_controller::{
+        common::{apply_script, fan_control::FanCurveExt},
+        DynGpuController, GpuController,
+    },
+    hooks::{self, HookContext},
+    profile_leases::{ActiveProfiles, LeaseRelease, ProfileLease, ProfileLeases, RestoreState},
+    profiles::{CompiledRules, ProfileWatcherCommand},
     system::{self, detect_initramfs_type},
 };
 use crate::{
     bindings::intel::IntelDrm,
     config::Config,
-    server::{gpu_controller::init_controller, profiles, system::DAEMON_VERSION},
+    server::{
+        corectrl::{self, ImportTarget},
+        gpu_controller::init_controller,
+        profiles,
+        system::DAEMON_VERSION,
+    },
     system::get_os_release,
 };
 use crate::{server::gpu_controller::NvidiaLibs, system::run_command};
@@ -16,12 +26,15 @@ use amdgpu_sysfs::gpu_handle::{
 use anyhow::{anyhow, bail, Context};
 use lact_schema::{
     config::{
-        default_fan_static_speed, FanControlSettings, FanCurve, GpuConfig, Profile, ProfileHooks,
+        default_fan_static_speed, FanControlSettings, FanCurve, FanSensorCurve, GpuConfig, Profile,
+        ProfileHooks, ProfileTiming,
     },
     default_fan_curve,
     request::{ClockspeedType, ConfirmCommand, ProfileBase, SetClocksCommand},
-    ClocksInfo, DeviceInfo, DeviceListEntry, DeviceStats, FanControlMode, FanOptions, PmfwOptions,
-    PowerStates, ProcessList, ProfileRule, ProfileWatcherState, ProfilesInfo,
+    ApplyScriptFormat, ClocksInfo, DeviceInfo, DeviceListEntry, DeviceStats, FanControlMode,
+    FanCurveMap, FanOptions, HookKind, HookResult, PmfwOptions, PowerStates, ProcessList,
+    ProfileImportResult, ProfileRule, ProfileSelectionExplanation, ProfileSwitch,
+    ProfileSwitchTrigger, ProfileWatcherState, ProfilesInfo,
 };
 use libdrm_amdgpu_sys::LibDrmAmdgpu;
 use libflate::gzip;
@@ -34,17 +47,16 @@ use serde_json::json;
 use std::collections::HashMap;
 use std::{
     cell::{Cell, LazyCell, RefCell},
-    collections::BTreeMap,
+    collections::{BTreeMap, VecDeque},
     env,
     fs::{self, File, Permissions},
     io::{BufWriter, Cursor, Write},
     os::unix::fs::{MetadataExt, PermissionsExt},
     path::{Path, PathBuf},
     rc::Rc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 use tokio::{
-    process::Command,
     sync::{mpsc, oneshot, RwLock, RwLockReadGuard},
     time::sleep,
 };
@@ -52,6 +64,8 @@ use tracing::{debug, error, info, trace, warn};
 
 const CONTROLLERS_LOAD_RETRY_ATTEMPTS: u8 = 5;
 const CONTROLLERS_LOAD_RETRY_INTERVAL: u64 = 3;
+const MAX_HOOK_RESULTS: usize = 32;
+const MAX_PROFILE_SWITCHES: usize = 64;
 
 const SNAPSHOT_GLOBAL_PATHS: &[&str] = &[
     "/sys/module/amdgpu/parameters",
@@ -87,6 +101,11 @@ pub struct Handler {
     pub config_last_saved: Rc<Cell<Instant>>,
     profile_watcher_tx: Rc<RefCell<Option<mpsc::Sender<ProfileWatcherCommand>>>>,
     pub profile_watcher_state: Rc<RefCell<Option<ProfileWatcherState>>>,
+    /// Patterns of the profile rules in the config, compiled whenever the rules are loaded or changed
+    pub profile_rules: Rc<RefCell<CompiledRules>>,
+    hook_results: Rc<RefCell<VecDeque<HookResult>>>,
+    profile_switches: Rc<RefCell<VecDeque<ProfileSwitch>>>,
+    profile_leases: Rc<RefCell<ProfileLeases>>,
 }
 
 impl<'a> Handler {
@@ -183,6 +202,7 @@ impl<'a> Handler {
             config.save(&Cell::new(Instant::now()))?;
         }
 
+        let profile_rules = CompiledRules::new(&config.profiles);
         let handler = Self {
             gpu_controllers: Rc::new(RwLock::new(controllers)),
             config: Rc::new(RwLock::new(config)),
@@ -190,6 +210,10 @@ impl<'a> Handler {
             config_last_saved: Rc::new(Cell::new(Instant::now())),
             profile_watcher_tx: Rc::new(RefCell::new(None)),
             profile_watcher_state: Rc::new(RefCell::new(None)),
+            profile_rules: Rc::new(RefCell::new(profile_rules)),
+            hook_results: Rc::new(RefCell::new(VecDeque::new())),
+            profile_switches: Rc::new(RefCell::new(VecDeque::new())),
+            profile_leases: Rc::new(RefCell::new(ProfileLeases::default())),
         };
         if let Err(err) = handler.apply_current_config().await {
             error!("could not apply config: {err:#}");
@@ -287,32 +311,52 @@ impl<'a> Handler {
             ));
         }
 
-        let (previous_config, apply_timer) = {
+        let (
+            previous_config,
+            new_config,
+            resolved_previous_config,
+            resolved_new_config,
+            apply_timer,
+        ) = {
             let config = self.config.read().await;
             let apply_timer = config.apply_settings_timer;
-            let gpu_config = config.gpus()?.get(&id).cloned().unwrap_or_default();
-            (gpu_config, apply_timer)
+            let gpu_config = config.gpus_for(&id)?.get(&id).cloned().unwrap_or_default();
+
+            let mut new_config = gpu_config.clone();
+            f(&mut new_config);
+
+            let resolved_previous_config = config.resolve_gpu_config(&gpu_config)?;
+            let resolved_new_config = config.resolve_gpu_config(&new_config)?;
+            (
+                gpu_config,
+                new_config,
+                resolved_previous_config,
+                resolved_new_config,
+                apply_timer,
+            )
         };
 
-        let mut new_config = previous_config.clone();
-        f(&mut new_config);
-
         let controller = self.controller_by_id(&id).await?;
 
-        match controller.apply_config(&new_config).await {
+        match controller.apply_config(&resolved_new_config).await {
             Ok(()) => {
                 self.config
                     .write()
                     .await
-                    .gpus_mut()?
+                    .gpus_for_mut(&id)?
                     .insert(id.clone(), new_config);
-                self.wait_config_confirm(id, previous_config, apply_timer)?;
+                self.wait_config_confirm(
+                    id,
+                    previous_config,
+                    resolved_previous_config,
+                    apply_timer,
+                )?;
 
                 Ok(apply_timer)
             }
             Err(apply_err) => {
                 error!("could not apply settings: {apply_err:?}");
-                match controller.apply_config(&previous_config).await {
+                match controller.apply_config(&resolved_previous_config).await {
                     Ok(()) => Err(apply_err.context("Could not apply settings")),
                     Err(err) => Err(apply_err.context(err.context(
                         "Could not apply settings, and could not reset to previous settings",
@@ -327,6 +371,7 @@ impl<'a> Handler {
         &self,
         id: String,
         previous_config: GpuConfig,
+        resolved_previous_config: GpuConfig,
         apply_timer: u64,
     ) -> anyhow::Result<()> {
         let (tx, rx) = oneshot::channel();
@@ -347,7 +392,7 @@ impl<'a> Handler {
                 () = tokio::time::sleep(Duration::from_secs(apply_timer)) => {
                     info!("no confirmation received, reverting settings");
 
-                    if let Err(err) = controller.apply_config(&previous_config).await {
+                    if let Err(err) = controller.apply_config(&resolved_previous_config).await {
                         error!("could not revert settings: {err:#}");
                     }
                 }
@@ -362,16 +407,16 @@ impl<'a> Handler {
                         }
                         Ok(ConfirmCommand::Revert) | Err(_) => {
                             let mut config_guard = handler.config.write().await;
-                            match config_guard.gpus_mut() {
+                            match config_guard.gpus_for_mut(&id) {
                                 Ok(gpus) => {
-                                    gpus.insert(id, previous_config.clone());
+                                    gpus.insert(id, previous_config);
                                 }
                                 Err(err) => {
                                     error!("could not revert config: {err}") ;
                                 }
                             }
 
-                            if let Err(err) = controller.apply_config(&previous_config).await {
+                            if let Err(err) = controller.apply_config(&resolved_previous_config).await {
                                 error!("could not revert settings: {err:#}");
                             }
                         }
@@ -397,6 +442,10 @@ impl<'a> Handler {
             .map_err(|_| anyhow!("Controller '{id}' not found"))
     }
 
+    pub(crate) async fn gpu_ids(&self) -> Vec<String> {
+        self.gpu_controllers.read().await.keys().cloned().collect()
+    }
+
     pub async fn list_devices(&'a self) -> Vec<DeviceListEntry> {
         self.gpu_controllers
             .read()
@@ -425,22 +474,26 @@ impl<'a> Handler {
     }
 
     pub async fn get_gpu_stats(&'a self, id: &str) -> anyhow::Result<DeviceStats> {
-        let config = self.config.read().await;
-        let gpu_config = config.gpus()?.get(id);
-        Ok(self.controller_by_id(id).await?.get_stats(gpu_config))
+        let gpu_config = self.config.read().await.resolved_gpu(id)?;
+        Ok(self
+            .controller_by_id(id)
+            .await?
+            .get_stats(gpu_config.as_ref()))
     }
 
     pub async fn get_clocks_info(&'a self, id: &str) -> anyhow::Result<ClocksInfo> {
-        let config = self.config.read().await;
-        let gpu_config = config.gpus()?.get(id);
-        self.controller_by_id(id).await?.get_clocks_info(gpu_config)
+        let gpu_config = self.config.read().await.resolved_gpu(id)?;
+        self.controller_by_id(id)
+            .await?
+            .get_clocks_info(gpu_config.as_ref())
     }
 
     pub async fn set_fan_control(&'a self, opts: FanOptions<'_>) -> anyhow::Result<u64> {
         let settings = {
             let mut config_guard = self.config.write().await;
+            let fan_curves = config_guard.fan_curves.clone();
             let gpu_config = config_guard
-                .gpus_mut()?
+                .gpus_for_mut(opts.id)?
                 .entry(opts.id.to_owned())
                 .or_default();
 
@@ -479,25 +532,89 @@ impl<'a> Handler {
                             if let Some(spindown_delay) = opts.spindown_delay_ms {
                                 existing_settings.spindown_delay_ms = Some(spindown_delay);
                             }
+                            if let Some(hysteresis) = opts.hysteresis {
+                                existing_settings.hysteresis = Some(hysteresis);
+                            }
+                            if let Some(interpolation) = opts.interpolation {
+                                existing_settings.interpolation = interpolation;
+                            }
+                            if let Some(raw_curve) = opts.falling_curve {
+                                existing_settings.falling_curve = falling_fan_curve(raw_curve)?;
+                            }
+                            if let Some(max_increase) = opts.max_speed_increase {
+                                existing_settings.max_speed_increase = Some(max_increase);
+                            }
+                            if let Some(max_decrease) = opts.max_speed_decrease {
+                                existing_settings.max_speed_decrease = Some(max_decrease);
+                            }
+                            if let Some(smoothing) = opts.temperature_smoothing_ms {
+                                existing_settings.temperature_smoothing_ms = Some(smoothing);
+                            }
+                            if let Some(extra_sensors) = opts.extra_sensors {
+                                validate_extra_sensors(&extra_sensors)?;
+                                existing_settings.extra_sensors = extra_sensors;
+                            }
 
                             if let Some(raw_curve) = opts.curve {
                                 let curve = FanCurve(raw_curve);
                                 curve.validate()?;
+
+                                // A curve that was edited directly no longer follows the named preset
+                                let preset_curve = existing_settings
+                                    .curve_preset
+                                    .as_ref()
+                                    .and_then(|name| fan_curves.get(name));
+                                if preset_curve != Some(&curve) {
+                                    existing_settings.curve_preset = None;
+                                }
+
                                 existing_settings.curve = curve;
                             }
                             Some(existing_settings)
                         } else {
                             let curve = FanCurve(opts.curve.unwrap_or_else(default_fan_curve));
                             curve.validate()?;
+                            let falling_curve = match opts.falling_curve {
+                                Some(raw_curve) => falling_fan_curve(raw_curve)?,
+                                None => None,
+                            };
+                            let extra_sensors = opts.extra_sensors.unwrap_or_default();
+                            validate_extra_sensors(&extra_sensors)?;
                             Some(FanControlSettings {
                                 mode,
                                 curve,
+                                interpolation: opts.interpolation.unwrap_or_default(),
                                 change_threshold: opts.change_threshold,
                                 spindown_delay_ms: opts.spindown_delay_ms,
+                                falling_curve,
+                                hysteresis: opts.hysteresis,
+                                extra_sensors,
+                                max_speed_increase: opts.max_speed_increase,
+                                max_speed_decrease: opts.max_speed_decrease,
+                                temperature_smoothing_ms: opts.temperature_smoothing_ms,
                                 ..Default::default()
                             })
                         }
                     }
+                    FanControlMode::TargetTemperature => {
+                        // These only affect curve based control, so they are not silently stored with the wrong mode
+                        if opts.extra_sensors.is_some() {
+                            bail!(
+                                "Extra sensors are not supported with the target temperature mode"
+                            );
+                        }
+                        if opts.hysteresis.is_some() || opts.falling_curve.is_some() {
+                            bail!("Spindown hysteresis and falling curves are not supported with the target temperature mode");
+                        }
+
+                        let mut settings =
+                            gpu_config.fan_control_settings.clone().unwrap_or_default();
+                        settings.mode = mode;
+                        if let Some(pid) = opts.pid {
+                            settings.pid = Some(pid);
+                        }
+                        Some(settings)
+                    }
                 },
                 None => None,
             }
@@ -534,13 +651,12 @@ impl<'a> Handler {
     }
 
     pub async fn get_power_states(&self, id: &str) -> anyhow::Result<PowerStates> {
-        let config = self.config.read().await;
-        let gpu_config = config.gpus()?.get(id);
+        let gpu_config = self.config.read().await.resolved_gpu(id)?;
 
         let states = self
             .controller_by_id(id)
             .await?
-            .get_power_states(gpu_config);
+            .get_power_states(gpu_config.as_ref());
         Ok(states)
     }
 
@@ -746,15 +862,15 @@ impl<'a> Handler {
         let mut map = BTreeMap::new();
 
         for (id, controller) in controllers.iter() {
-            let gpu_config = config.gpus().ok().and_then(|gpus| gpus.get(id));
+            let gpu_config = config.resolved_gpu(id).ok().flatten();
 
             let data = json!({
                 "pci_info": controller.controller_info().pci_info.clone(),
                 "info": controller.get_info().await,
-                "stats": controller.get_stats(gpu_config),
-                "clocks_info": controller.get_clocks_info(gpu_config).ok(),
+                "stats": controller.get_stats(gpu_config.as_ref()),
+                "clocks_info": controller.get_clocks_info(gpu_config.as_ref()).ok(),
                 "power_profile_modes": controller.get_power_profile_modes().ok(),
-                "power_states": controller.get_power_states(gpu_config),
+                "power_states": controller.get_power_states(gpu_config.as_ref()),
             });
 
             map.insert(id.clone(), data);
@@ -770,6 +886,7 @@ impl<'a> Handler {
             None
         };
 
+        let gpu_ids = self.gpu_ids().await;
         let config = self.config.read().await;
         ProfilesInfo {
             profiles: config
@@ -782,7 +899,25 @@ impl<'a> Handler {
                 .iter()
                 .map(|(name, profile)| (name.to_string(), profile.hooks.clone()))
                 .collect(),
+            profile_timing: config
+                .profiles
+                .iter()
+                .map(|(name, profile)| (name.to_string(), profile.timing))
+                .collect(),
+            profile_gpu_ids: config
+                .profiles
+                .iter()
+                .filter(|(_, profile)| profile.is_scoped())
+                .map(|(name, profile)| (name.to_string(), profile.gpu_ids.clone()))
+                .collect(),
             current_profile: config.current_profile.as_ref().map(Rc::to_string),
+            gpu_profiles: gpu_ids
+                .into_iter()
+                .map(|id| {
+                    let profile = config.gpu_profile(&id).map(Rc::to_string);
+                    (id, profile)
+                })
+                .collect(),
             auto_switch: config.auto_switch_profiles,
             watcher_state,
         }
@@ -802,12 +937,17 @@ impl<'a> Handler {
         &self,
         name: Option<Rc<str>>,
         auto_switch: bool,
+        gpu_id: Option<String>,
     ) -> anyhow::Result<()> {
+        // Selecting a profile manually takes over from any temporary profiles
+        self.profile_leases.borrow_mut().clear();
+
         if auto_switch {
             self.start_profile_watcher().await;
         } else {
             self.stop_profile_watcher().await;
-            self.set_current_profile(name).await?;
+            self.activate_profile(name, gpu_id.as_deref(), &ProfileSwitchTrigger::Manual)
+                .await?;
         }
 
         let mut config = self.config.write().await;
@@ -817,39 +957,382 @@ impl<'a> Handler {
         Ok(())
     }
 
-    pub(super) async fn set_current_profile(&self, name: Option<Rc<str>>) -> anyhow::Result<()> {
-        let mut activation_hook = None;
-        let mut deactivation_hook = None;
-        {
+    /// Activates a profile either for a single GPU, or as the current profile for all of them.
+    /// A profile that is limited to some GPUs only gets activated on those.
+    async fn activate_profile(
+        &self,
+        name: Option<Rc<str>>,
+        gpu_id: Option<&str>,
+        trigger: &ProfileSwitchTrigger,
+    ) -> anyhow::Result<()> {
+        if let Some(gpu_id) = gpu_id {
+            return self.set_gpu_profile(gpu_id, name, trigger.clone()).await;
+        }
+
+        let available_gpu_ids = self.gpu_ids().await;
+        let scoped_gpu_ids: Vec<String> = {
             let config = self.config.read().await;
-            // Make sure the profile exists
-            if let Some(name) = &name {
-                let new_profile = config.profile(name)?;
-                activation_hook.clone_from(&new_profile.hooks.activated);
+            match &name {
+                Some(name) => config.profile(name)?.gpu_ids.clone(),
+                None => Vec::new(),
+            }
+        };
+
+        if scoped_gpu_ids.is_empty() {
+            let overridden_gpu_ids: Vec<String> = self
+                .config
+                .read()
+                .await
+                .current_gpu_profiles
+                .keys()
+                .cloned()
+                .collect();
+            for gpu_id in overridden_gpu_ids {
+                self.set_gpu_profile(&gpu_id, None, trigger.clone()).await?;
+            }
+
+            self.set_current_profile(name, trigger.clone()).await
+        } else {
+            for gpu_id in scoped_gpu_ids
+                .into_iter()
+                .filter(|id| available_gpu_ids.contains(id))
+            {
+                self.set_gpu_profile(&gpu_id, name.clone(), trigger.clone())
+                    .await?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Activates a profile temporarily. Automatic profile switching is paused while there are active leases.
+    ///
+    /// The lease is also released after `timeout_secs`, or when the connection it was acquired over is closed.
+    pub async fn acquire_profile_lease(
+        &self,
+        name: Option<Rc<str>>,
+        gpu_id: Option<String>,
+        timeout_secs: Option<u64>,
+    ) -> anyhow::Result<u64> {
+        if let Some(name) = &name {
+            self.config.read().await.profile(name)?;
+        }
+        if let Some(gpu_id) = &gpu_id {
+            self.controller_by_id(gpu_id).await?;
+        }
+
+        let first_lease = self.profile_leases.borrow().is_empty();
+        let restore = if first_lease {
+            self.stop_profile_watcher().await;
+            let config = self.config.read().await;
+            RestoreState {
+                current_profile: config.current_profile.clone(),
+                gpu_profiles: config.current_gpu_profiles.clone(),
+            }
+        } else {
+            RestoreState::default()
+        };
+
+        let id = self.profile_leases.borrow_mut().next_id();
+        let trigger = ProfileSwitchTrigger::Lease { id };
+        let lease = ProfileLease {
+            id,
+            profile: name,
+            gpu_id,
+        };
+        self.profile_leases.borrow_mut().acquire(lease, || restore);
+
+        // Leases of other scopes stay in effect, so everything is activated from the full set of leases
+        let active = self.profile_leases.borrow().active_profiles();
+        if let Err(err) = self.apply_active_profiles(active, &trigger).await {
+            self.release_profile_lease(id).await?;
+            return Err(err);
+        }
+        info!("acquired profile lease {id}");
+
+        if let Some(timeout_secs) = timeout_secs {
+            let handler = self.clone();
+            tokio::task::spawn_local(async move {
+                sleep(Duration::from_secs(timeout_secs)).await;
+                match handler.release_profile_lease(id).await {
+                    Ok(true) => info!("profile lease {id} expired"),
+                    Ok(false) => (),
+                    Err(err) => error!("could not release expired profile lease {id}: {err:#}"),
+                }
+            });
+        }
+
+        Ok(id)
+    }
+
+    /// Returns `false` if the lease was already released
+    pub async fn release_profile_lease(&self, id: u64) -> anyhow::Result<bool> {
+        let release = self.profile_leases.borrow_mut().release(id);
+        let trigger = ProfileSwitchTrigger::Lease { id };
+        match release {
+            None => return Ok(false),
+            Some(LeaseRelease::Keep) => (),
+            Some(LeaseRelease::Activate(active)) => {
+                self.apply_active_profiles(active, &trigger).await?;
+            }
+            Some(LeaseRelease::Restore(restore)) => {
+                self.restore_profiles(restore, &trigger).await?;
             }
+        }
+
+        info!("released profile lease {id}");
+        Ok(true)
+    }
+
+    /// Goes back to the profiles from before the leases, or resumes automatic switching if it is enabled
+    async fn restore_profiles(
+        &self,
+        restore: RestoreState,
+        trigger: &ProfileSwitchTrigger,
+    ) -> anyhow::Result<()> {
+        if self.config.read().await.auto_switch_profiles {
+            self.start_profile_watcher().await;
+            return Ok(());
+        }
 
-            if let Some(old_profile) = &config.current_profile {
-                if let Some(old_profile) = config.profiles.get(old_profile) {
-                    deactivation_hook.clone_from(&old_profile.hooks.deactivated);
+        self.apply_active_profiles(restore.into(), trigger).await
+    }
+
+    /// Switches the current profile and the per-GPU profiles to the given ones, skipping those that are already active.
+    /// GPUs that are not listed follow the current profile.
+    async fn apply_active_profiles(
+        &self,
+        active: ActiveProfiles,
+        trigger: &ProfileSwitchTrigger,
+    ) -> anyhow::Result<()> {
+        let available_gpu_ids = self.gpu_ids().await;
+        let mut current_profile = active.current_profile;
+        let mut gpu_profiles = active.gpu_profiles;
+        {
+            let config = self.config.read().await;
+            // A profile limited to some GPUs only gets activated on those, like in `activate_profile`
+            if let Some(name) = &current_profile {
+                let scoped_gpu_ids = &config.profile(name)?.gpu_ids;
+                if !scoped_gpu_ids.is_empty() {
+                    for gpu_id in scoped_gpu_ids {
+                        if available_gpu_ids.contains(gpu_id) {
+                            gpu_profiles
+                                .entry(gpu_id.clone())
+                                .or_insert_with(|| Some(name.clone()));
+                        }
+                    }
+                    current_profile.clone_from(&config.current_profile);
                 }
             }
         }
 
-        self.cleanup().await;
-        self.config.write().await.current_profile = name;
+        let overridden_gpu_ids: Vec<String> = self
+            .config
+            .read()
+            .await
+            .current_gpu_profiles
+            .keys()
+            .filter(|id| !matches!(gpu_profiles.get(*id), Some(Some(_))))
+            .cloned()
+            .collect();
+        for gpu_id in overridden_gpu_ids {
+            self.set_gpu_profile(&gpu_id, None, trigger.clone()).await?;
+        }
 
-        self.apply_current_config().await?;
+        if self.config.read().await.current_profile != current_profile {
+            self.set_current_profile(current_profile, trigger.clone())
+                .await?;
+        }
 
-        if let Some(deactivated) = &deactivation_hook {
-            run_hook_command(deactivated).await?;
+        for (gpu_id, name) in gpu_profiles {
+            let Some(name) = name else { continue };
+            let active_profile = self
+                .config
+                .read()
+                .await
+                .current_gpu_profiles
+                .get(&gpu_id)
+                .cloned();
+            if active_profile.as_ref() != Some(&name) {
+                self.set_gpu_profile(&gpu_id, Some(name), trigger.clone())
+                    .await?;
+            }
         }
-        if let Some(activated) = &activation_hook {
-            run_hook_command(activated).await?;
+
+        Ok(())
+    }
+
+    /// Sets the profile for all GPUs that do not have their own profile activated.
+    ///
+    /// The process which caused a rule to match is passed on to the hooks through the `trigger`
+    pub(super) async fn set_current_profile(
+        &self,
+        name: Option<Rc<str>>,
+        trigger: ProfileSwitchTrigger,
+    ) -> anyhow::Result<()> {
+        let mut gpu_ids = self.gpu_ids().await;
+        let (old_profile, hook_commands) = {
+            let config = self.config.read().await;
+            let old_profile = config.current_profile.clone();
+            let hook_commands =
+                profile_hook_commands(&config, old_profile.as_ref(), name.as_ref())?;
+
+            gpu_ids.retain(|id| !config.current_gpu_profiles.contains_key(id));
+            (old_profile, hook_commands)
+        };
+
+        let started_at = Instant::now();
+        self.cleanup_gpus(&gpu_ids).await;
+        self.config.write().await.current_profile.clone_from(&name);
+
+        let errors = self.apply_gpus_config(&gpu_ids).await;
+        self.record_profile_switch(
+            old_profile.as_deref(),
+            name.as_deref(),
+            None,
+            &trigger,
+            started_at,
+            &errors,
+        );
+
+        let context = HookContext {
+            old_profile: old_profile.as_deref(),
+            new_profile: name.as_deref(),
+            process: trigger.process(),
+            gpu_ids: &gpu_ids,
+        };
+        self.run_hooks(hook_commands, &context).await;
+
+        Ok(())
+    }
+
+    /// Activates a profile only for the given GPU. Passing `None` makes the GPU follow the current profile again.
+    pub(super) async fn set_gpu_profile(
+        &self,
+        gpu_id: &str,
+        name: Option<Rc<str>>,
+        trigger: ProfileSwitchTrigger,
+    ) -> anyhow::Result<()> {
+        self.controller_by_id(gpu_id).await?;
+
+        let (old_profile, new_profile, hook_commands) = {
+            let config = self.config.read().await;
+            if let Some(name) = &name {
+                if !config.profile(name)?.applies_to(gpu_id) {
+                    bail!("Profile {name} is not enabled for GPU {gpu_id}");
+                }
+            }
+
+            let old_profile = config.gpu_profile(gpu_id).cloned();
+            let new_profile = name.clone().or_else(|| config.current_profile.clone());
+            let hook_commands =
+                profile_hook_commands(&config, old_profile.as_ref(), new_profile.as_ref())?;
+            (old_profile, new_profile, hook_commands)
+        };
+
+        if old_profile == new_profile {
+            // The settings in effect stay the same
+            self.config.write().await.set_gpu_profile(gpu_id, name);
+            return Ok(());
         }
 
+        let started_at = Instant::now();
+        let gpu_ids = [gpu_id.to_owned()];
+        self.cleanup_gpus(&gpu_ids).await;
+        self.config.write().await.set_gpu_profile(gpu_id, name);
+
+        let errors = self.apply_gpus_config(&gpu_ids).await;
+        self.record_profile_switch(
+            old_profile.as_deref(),
+            new_profile.as_deref(),
+            Some(gpu_id),
+            &trigger,
+            started_at,
+            &errors,
+        );
+
+        let context = HookContext {
+            old_profile: old_profile.as_deref(),
+            new_profile: new_profile.as_deref(),
+            process: trigger.process(),
+            gpu_ids: &gpu_ids,
+        };
+        self.run_hooks(hook_commands, &context).await;
+
         Ok(())
     }
 
+    async fn run_hooks(&self, hook_commands: Vec<HookCommand>, context: &HookContext<'_>) {
+        for (profile, kind, command, hooks) in hook_commands {
+            let result = hooks::run_hook(&profile, kind, &command, &hooks, context).await;
+            self.push_hook_result(result);
+        }
+    }
+
+    /// The switch is completed even if some of the GPUs could not apply the new settings,
+    /// their errors are logged and kept with the switch
+    fn record_profile_switch(
+        &self,
+        old_profile: Option<&str>,
+        new_profile: Option<&str>,
+        gpu_id: Option<&str>,
+        trigger: &ProfileSwitchTrigger,
+        started_at: Instant,
+        errors: &[anyhow::Error],
+    ) {
+        for err in errors {
+            error!("{err:#}");
+        }
+
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let switch = ProfileSwitch {
+            time,
+            old_profile: old_profile.map(str::to_owned),
+            new_profile: new_profile.map(str::to_owned),
+            gpu_id: gpu_id.map(str::to_owned),
+            trigger: trigger.clone(),
+            apply_duration_ms: u64::try_from(started_at.elapsed().as_millis()).unwrap_or(u64::MAX),
+            error: (!errors.is_empty()).then(|| {
+                errors
+                    .iter()
+                    .map(|err| format!("{err:#}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }),
+        };
+        debug!("profile switch: {switch:?}");
+
+        let mut switches = self.profile_switches.borrow_mut();
+        if switches.len() >= MAX_PROFILE_SWITCHES {
+            switches.pop_front();
+        }
+        switches.push_back(switch);
+    }
+
+    pub fn list_profile_switches(&self) -> Vec<ProfileSwitch> {
+        self.profile_switches.borrow().iter().cloned().collect()
+    }
+
+    fn push_hook_result(&self, result: HookResult) {
+        let mut results = self.hook_results.borrow_mut();
+        if results.len() >= MAX_HOOK_RESULTS {
+            results.pop_front();
+        }
+        results.push_back(result);
+    }
+
+    pub fn list_hook_results(&self) -> Vec<HookResult> {
+        self.hook_results.borrow().iter().cloned().collect()
+    }
+
+    /// Invalid rules are logged and disable their profile instead of failing
+    pub fn compile_profile_rules(&self, config: &Config) {
+        *self.profile_rules.borrow_mut() = CompiledRules::new(&config.profiles);
+    }
+
     pub async fn create_profile(&self, name: String, base: ProfileBase) -> anyhow::Result<()> {
         {
             let mut config = self.config.write().await;
@@ -865,6 +1348,7 @@ impl<'a> Handler {
             };
             config.profiles.insert(name.into(), profile);
             config.save(&self.config_last_saved)?;
+            self.compile_profile_rules(&config);
         }
 
         let tx = self.profile_watcher_tx.borrow().clone();
@@ -876,8 +1360,23 @@ impl<'a> Handler {
     }
 
     pub async fn delete_profile(&self, name: String) -> anyhow::Result<()> {
+        let overridden_gpu_ids: Vec<String> = self
+            .config
+            .read()
+            .await
+            .current_gpu_profiles
+            .iter()
+            .filter(|(_, profile)| profile.as_ref() == name)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for gpu_id in overridden_gpu_ids {
+            self.set_gpu_profile(&gpu_id, None, ProfileSwitchTrigger::Manual)
+                .await?;
+        }
+
         if self.config.read().await.current_profile.as_deref() == Some(&name) {
-            self.set_current_profile(None).await?;
+            self.set_current_profile(None, ProfileSwitchTrigger::Manual)
+                .await?;
         }
         self.config
             .write()
@@ -924,8 +1423,14 @@ impl<'a> Handler {
         &self,
         name: &str,
         rule: Option<ProfileRule>,
-        hooks: ProfileHooks,
+        hooks: Option<ProfileHooks>,
+        timing: Option<ProfileTiming>,
+        gpu_ids: Option<Vec<String>>,
     ) -> anyhow::Result<()> {
+        if let Some(rule) = &rule {
+            CompiledRules::compile(rule)?;
+        }
+
         {
             let mut config = self.config.write().await;
             let profile = config
@@ -934,9 +1439,18 @@ impl<'a> Handler {
                 .with_context(|| format!("Profile {name} not found"))?;
 
             profile.rule = rule;
-            profile.hooks = hooks;
+            if let Some(hooks) = hooks {
+                profile.hooks = hooks;
+            }
+            if let Some(timing) = timing {
+                profile.timing = timing;
+            }
+            if let Some(gpu_ids) = gpu_ids {
+                profile.gpu_ids = gpu_ids;
+            }
 
             config.save(&self.config_last_saved)?;
+            self.compile_profile_rules(&config);
         }
 
         let tx = self.profile_watcher_tx.borrow().clone();
@@ -947,13 +1461,95 @@ impl<'a> Handler {
         Ok(())
     }
 
+    pub async fn import_corectrl_profile(
+        &self,
+        data: &[u8],
+        name: Option<String>,
+        dry_run: bool,
+    ) -> anyhow::Result<ProfileImportResult> {
+        let targets: Vec<_> = self
+            .gpu_controllers
+            .read()
+            .await
+            .iter()
+            .map(|(id, controller)| ImportTarget {
+                id: id.clone(),
+                device_id: controller
+                    .controller_info()
+                    .pci_info
+                    .device_pci_info
+                    .model_id
+                    .clone(),
+                power_profile_modes: controller
+                    .get_power_profile_modes()
+                    .map(|table| {
+                        table
+                            .modes
+                            .iter()
+                            .map(|(index, mode)| (*index, mode.name.clone()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        let mut result = corectrl::import_profile(data, &targets)?;
+        if let Some(name) = name {
+            result.name = name;
+        }
+
+        for item in &result.unmapped {
+            info!("could not import '{item}' from profile '{}'", result.name);
+        }
+
+        if !dry_run {
+            self.create_profile(
+                result.name.clone(),
+                ProfileBase::Provided(result.profile.clone()),
+            )
+            .await?;
+        }
+
+        Ok(result)
+    }
+
+    pub async fn export_apply_script(
+        &self,
+        id: &str,
+        profile: Option<String>,
+        format: ApplyScriptFormat,
+    ) -> anyhow::Result<String> {
+        let gpu_config = {
+            let config = self.config.read().await;
+            let gpus = match &profile {
+                Some(name) => &config.profile(name)?.gpus,
+                None => &config.gpus,
+            };
+            let gpu_config = gpus
+                .get(id)
+                .with_context(|| format!("No settings are configured for GPU '{id}'"))?;
+            config.resolve_gpu_config(gpu_config)?
+        };
+
+        let steps = self
+            .controller_by_id(id)
+            .await?
+            .apply_config_steps(&gpu_config)?;
+
+        let description = match &profile {
+            Some(name) => format!("LACT settings for GPU {id} (profile '{name}')"),
+            None => format!("LACT settings for GPU {id}"),
+        };
+        Ok(apply_script::render(&steps, format, &description))
+    }
+
     pub async fn process_list(&self, id: &str) -> anyhow::Result<ProcessList> {
         self.controller_by_id(id).await?.process_list()
     }
 
     pub async fn get_gpu_config(&self, id: &str) -> anyhow::Result<Option<GpuConfig>> {
         let config = self.config.read().await;
-        Ok(config.gpus()?.get(id).cloned())
+        Ok(config.gpus_for(id)?.get(id).cloned())
     }
 
     pub async fn set_gpu_config(&self, id: &str, new_config: GpuConfig) -> anyhow::Result<u64> {
@@ -962,9 +1558,34 @@ impl<'a> Handler {
     }
 
     pub fn evaluate_profile_rule(&self, rule: &ProfileRule) -> anyhow::Result<bool> {
+        let compiled = CompiledRules::compile(rule)?;
+
         let profile_watcher_state_guard = self.profile_watcher_state.borrow();
         match profile_watcher_state_guard.as_ref() {
-            Some(state) => Ok(profiles::profile_rule_matches(state, rule)),
+            Some(state) => Ok(profiles::profile_rule_matches(state, &compiled, rule)),
+            None => Err(anyhow!(
+                "Automatic profile switching is not currently active"
+            )),
+        }
+    }
+
+    pub async fn explain_profile_selection(&self) -> anyhow::Result<ProfileSelectionExplanation> {
+        let available_gpu_ids = self.gpu_ids().await;
+        let config = self.config.read().await;
+        let scoped_gpu_ids: Vec<String> = config
+            .scoped_gpu_ids()
+            .into_iter()
+            .filter(|id| available_gpu_ids.contains(id))
+            .collect();
+
+        let profile_watcher_state_guard = self.profile_watcher_state.borrow();
+        match profile_watcher_state_guard.as_ref() {
+            Some(state) => Ok(profiles::explain_profile_selection(
+                state,
+                &self.profile_rules.borrow(),
+                &config,
+                &scoped_gpu_ids,
+            )),
             None => Err(anyhow!(
                 "Automatic profile switching is not currently active"
             )),
@@ -997,10 +1618,19 @@ impl<'a> Handler {
     }
 
     pub async fn cleanup(&self) {
+        let gpu_ids = self.gpu_ids().await;
+        self.cleanup_gpus(&gpu_ids).await;
+    }
+
+    async fn cleanup_gpus(&self, ids: &[String]) {
         let disable_clocks_cleanup = self.config.read().await.daemon.disable_clocks_cleanup;
 
         let controllers = self.gpu_controllers.read().await;
-        for (id, controller) in controllers.iter() {
+        for id in ids {
+            let Some(controller) = controllers.get(id) else {
+                continue;
+            };
+
             if !disable_clocks_cleanup {
                 debug!("resetting clocks table");
                 if let Err(err) = controller.reset_clocks() {
@@ -1017,27 +1647,107 @@ impl<'a> Handler {
             controller.cleanup().await;
         }
     }
+
+    /// Applies the settings of the profile that is active for each of the given GPUs.
+    ///
+    /// A GPU that fails to apply does not stop the others, the error of each failed GPU is returned.
+    async fn apply_gpus_config(&self, ids: &[String]) -> Vec<anyhow::Error> {
+        let config = self.config.read().await;
+        let controllers = self.gpu_controllers.read().await;
+
+        let mut errors = Vec::new();
+        for id in ids {
+            if let Some(controller) = controllers.get(id) {
+                if let Err(err) = apply_config_to_controller(id, controller.as_ref(), &config).await
+                {
+                    errors.push(err);
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// Profile name, hook type, command and hook options
+type HookCommand = (Rc<str>, HookKind, String, ProfileHooks);
+
+/// Hooks to run when switching from `old_profile` to `new_profile`, in the order they should run.
+/// Returns an error if the new profile does not exist.
+fn profile_hook_commands(
+    config: &Config,
+    old_profile: Option<&Rc<str>>,
+    new_profile: Option<&Rc<str>>,
+) -> anyhow::Result<Vec<HookCommand>> {
+    let mut hook_commands = Vec::new();
+
+    if let Some(old_name) = old_profile {
+        if let Some(old_profile) = config.profiles.get(old_name) {
+            if let Some(command) = &old_profile.hooks.deactivated {
+                hook_commands.push((
+                    old_name.clone(),
+                    HookKind::Deactivated,
+                    command.clone(),
+                    old_profile.hooks.clone(),
+                ));
+            }
+        }
+    }
+
+    if let Some(name) = new_profile {
+        let new_profile = config.profile(name)?;
+        if let Some(command) = &new_profile.hooks.activated {
+            hook_commands.push((
+                name.clone(),
+                HookKind::Activated,
+                command.clone(),
+                new_profile.hooks.clone(),
+            ));
+        }
+    }
+
+    Ok(hook_commands)
 }
 
 async fn apply_config_to_controllers(
     controllers: &BTreeMap<String, Box<dyn GpuController>>,
     config: &Config,
 ) -> anyhow::Result<()> {
-    let gpus = config.gpus()?;
-    for (id, gpu_config) in gpus {
-        if let Some(controller) = controllers.get(id) {
-            debug!("applying config {gpu_config:#?} to controller {id}");
-            if let Err(err) = controller.apply_config(gpu_config).await {
-                error!("could not apply existing config for gpu {id}: {err:#}");
-            }
-        } else {
+    for id in config.gpus()?.keys() {
+        if !controllers.contains_key(id) {
             warn!("could not find GPU with id {id} defined in configuration");
         }
     }
 
+    // Applied at startup and on reload, where one GPU failing should not prevent the rest from being configured
+    for (id, controller) in controllers {
+        if let Err(err) = apply_config_to_controller(id, controller.as_ref(), config).await {
+            error!("{err:#}");
+        }
+    }
+
     Ok(())
 }
 
+async fn apply_config_to_controller(
+    id: &str,
+    controller: &dyn GpuController,
+    config: &Config,
+) -> anyhow::Result<()> {
+    let Some(gpu_config) = config.gpus_for(id)?.get(id) else {
+        return Ok(());
+    };
+
+    let gpu_config = config
+        .resolve_gpu_config(gpu_config)
+        .with_context(|| format!("could not resolve config for gpu {id}"))?;
+
+    debug!("applying config {gpu_config:#?} to controller {id}");
+    controller
+        .apply_config(&gpu_config)
+        .await
+        .with_context(|| format!("could not apply config for gpu {id}"))
+}
+
 #[cfg(test)]
 pub(crate) fn read_pci_db() -> Database {
     let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/pci.ids");
@@ -1251,27 +1961,30 @@ fn add_path_to_archive(
     Ok(())
 }
 
-fn drm_base_path() -> PathBuf {
-    match env::var("_LACT_DRM_SYSFS_PATH") {
-        Ok(custom_path) => PathBuf::from(custom_path),
-        Err(_) => PathBuf::from("/sys/class/drm"),
+/// An empty curve means that the falling curve should be removed
+fn falling_fan_curve(raw_curve: FanCurveMap) -> anyhow::Result<Option<FanCurve>> {
+    if raw_curve.is_empty() {
+        return Ok(None);
     }
+    let curve = FanCurve(raw_curve);
+    curve.validate().context("Invalid falling fan curve")?;
+    Ok(Some(curve))
 }
 
-async fn run_hook_command(command: &str) -> anyhow::Result<()> {
-    let output = Command::new("sh").arg("-c").arg(command).output().await?;
-
-    if !output.status.success() {
-        let mut error_text = String::new();
-        error_text.push_str(&String::from_utf8_lossy(&output.stdout));
-        error_text.push(' ');
-        error_text.push_str(&String::from_utf8_lossy(&output.stderr));
-
-        warn!(
-            "command hook exited with status {}: {error_text}",
-            output.status
-        );
+fn validate_extra_sensors(sensors: &[FanSensorCurve]) -> anyhow::Result<()> {
+    for sensor in sensors {
+        if let Some(curve) = &sensor.curve {
+            curve
+                .validate()
+                .with_context(|| format!("Invalid fan curve for sensor {}", sensor.sensor_key()))?;
+        }
     }
-
     Ok(())
 }
+
+fn drm_base_path() -> PathBuf {
+    match env::var("_LACT_DRM_SYSFS_PATH") {
+        Ok(custom_path) => PathBuf::from(custom_path),
+        Err(_) => PathBuf::from("/sys/class/drm"),
+    }
+}
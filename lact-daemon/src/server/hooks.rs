@@ -0,0 +1,221 @@
+use anyhow::{anyhow, Context};
+use lact_schema::{config::ProfileHooks, HookKind, HookResult};
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::{Pid, User},
+};
+use std::{
+    process::Stdio,
+    time::{Duration, SystemTime},
+};
+use tokio::{process::Command, time::timeout};
+use tracing::{debug, warn};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Output beyond this length is cut off, so a chatty hook does not hold on to a lot of memory
+const MAX_OUTPUT_LEN: usize = 16 * 1024;
+
+/// Information about the profile switch which is passed to hooks as environment variables
+pub struct HookContext<'a> {
+    pub old_profile: Option<&'a str>,
+    pub new_profile: Option<&'a str>,
+    /// PID and name of the process which caused the new profile to be matched
+    pub process: Option<(i32, &'a str)>,
+    pub gpu_ids: &'a [String],
+}
+
+impl HookContext<'_> {
+    fn env(&self, kind: HookKind, profile: &str) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("LACT_HOOK", kind.to_string()),
+            ("LACT_PROFILE", profile.to_owned()),
+            (
+                "LACT_OLD_PROFILE",
+                self.old_profile.unwrap_or_default().to_owned(),
+            ),
+            (
+                "LACT_NEW_PROFILE",
+                self.new_profile.unwrap_or_default().to_owned(),
+            ),
+            ("LACT_GPU_IDS", self.gpu_ids.join(" ")),
+        ];
+        if let Some((pid, name)) = self.process {
+            env.push(("LACT_PROCESS_PID", pid.to_string()));
+            env.push(("LACT_PROCESS_NAME", name.to_owned()));
+        }
+        env
+    }
+}
+
+pub async fn run_hook(
+    profile: &str,
+    kind: HookKind,
+    command: &str,
+    hooks: &ProfileHooks,
+    context: &HookContext<'_>,
+) -> HookResult {
+    let started_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut result = HookResult {
+        profile: profile.to_owned(),
+        kind,
+        command: command.to_owned(),
+        started_at,
+        exit_code: None,
+        timed_out: false,
+        stdout: String::new(),
+        stderr: String::new(),
+        error: None,
+    };
+
+    let hook_timeout = hooks
+        .timeout_secs
+        .map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+
+    match spawn_hook(command, hooks.user.as_deref(), &context.env(kind, profile)) {
+        Ok(child) => {
+            // The hook is the leader of its own process group
+            let pgid = child.id();
+            match timeout(hook_timeout, child.wait_with_output()).await {
+                Ok(Ok(output)) => {
+                    result.exit_code = output.status.code();
+                    result.stdout = truncate_output(&output.stdout);
+                    result.stderr = truncate_output(&output.stderr);
+                }
+                Ok(Err(err)) => result.error = Some(format!("Could not wait for command: {err}")),
+                Err(_) => {
+                    result.timed_out = true;
+                    if let Some(pgid) = pgid {
+                        kill_process_group(pgid);
+                    }
+                }
+            }
+        }
+        Err(err) => result.error = Some(format!("{err:#}")),
+    }
+
+    if result.success() {
+        debug!("{kind} hook of profile '{profile}' finished");
+    } else {
+        warn!(
+            "{kind} hook of profile '{profile}' failed (exit code {:?}, timed out: {}, error: {:?}): {} {}",
+            result.exit_code, result.timed_out, result.error, result.stdout, result.stderr
+        );
+    }
+
+    result
+}
+
+fn spawn_hook(
+    command: &str,
+    user: Option<&str>,
+    env: &[(&'static str, String)],
+) -> anyhow::Result<tokio::process::Child> {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Processes started by the hook end up in the same group, so they can be killed together with it
+        .process_group(0)
+        .kill_on_drop(true);
+
+    if let Some(user_name) = user {
+        let user = User::from_name(user_name)
+            .context("Could not look up user")?
+            .ok_or_else(|| anyhow!("User '{user_name}' does not exist"))?;
+
+        cmd.uid(user.uid.as_raw())
+            .gid(user.gid.as_raw())
+            .current_dir(&user.dir)
+            .env("HOME", &user.dir)
+            .env("USER", &user.name)
+            .env("LOGNAME", &user.name);
+    }
+
+    cmd.spawn().context("Could not start command")
+}
+
+/// Kills a timed out hook along with everything it has started
+fn kill_process_group(pgid: u32) {
+    let Ok(pgid) = i32::try_from(pgid) else {
+        return;
+    };
+    if let Err(err) = killpg(Pid::from_raw(pgid), Signal::SIGKILL) {
+        warn!("could not kill the processes of a timed out hook: {err}");
+    }
+}
+
+fn truncate_output(output: &[u8]) -> String {
+    let output = &output[..output.len().min(MAX_OUTPUT_LEN)];
+    String::from_utf8_lossy(output).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_hook, HookContext};
+    use lact_schema::{config::ProfileHooks, HookKind};
+    use std::{fs, time::Duration};
+
+    fn context() -> HookContext<'static> {
+        HookContext {
+            old_profile: None,
+            new_profile: Some("game"),
+            process: Some((1234, "game.exe")),
+            gpu_ids: &[],
+        }
+    }
+
+    #[tokio::test]
+    async fn hook_env_and_output() {
+        let result = run_hook(
+            "game",
+            HookKind::Activated,
+            "echo $LACT_HOOK $LACT_NEW_PROFILE $LACT_PROCESS_PID $LACT_PROCESS_NAME; echo fail >&2; exit 3",
+            &ProfileHooks::default(),
+            &context(),
+        )
+        .await;
+
+        assert_eq!(Some(3), result.exit_code);
+        assert_eq!("activated game 1234 game.exe\n", result.stdout);
+        assert_eq!("fail\n", result.stderr);
+        assert!(!result.success());
+    }
+
+    #[tokio::test]
+    async fn hook_timeout() {
+        let hooks = ProfileHooks {
+            timeout_secs: Some(0),
+            ..Default::default()
+        };
+        let result = run_hook("game", HookKind::Deactivated, "sleep 5", &hooks, &context()).await;
+
+        assert!(result.timed_out);
+        assert_eq!(None, result.exit_code);
+    }
+
+    #[tokio::test]
+    async fn hook_timeout_kills_children() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_path = dir.path().join("pid");
+        let hooks = ProfileHooks {
+            timeout_secs: Some(1),
+            ..Default::default()
+        };
+        let command = format!("sleep 60 & echo $! > {}; wait", pid_path.display());
+        let result = run_hook("game", HookKind::Deactivated, &command, &hooks, &context()).await;
+        assert!(result.timed_out);
+
+        let pid = fs::read_to_string(&pid_path).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        // The background process is either gone or a zombie waiting to be reaped
+        let state = fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        assert!(state.is_empty() || state.contains(") Z "), "{state}");
+    }
+}
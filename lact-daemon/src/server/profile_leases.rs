@@ -0,0 +1,229 @@
+use indexmap::IndexMap;
+use std::rc::Rc;
+
+/// Profiles which are activated temporarily, along with the profiles to go back to once all of them are released
+#[derive(Default)]
+pub struct ProfileLeases {
+    next_id: u64,
+    /// Oldest first. The newest lease of each scope (all GPUs or a single GPU) is in effect.
+    leases: Vec<ProfileLease>,
+    restore: Option<RestoreState>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileLease {
+    pub id: u64,
+    pub profile: Option<Rc<str>>,
+    pub gpu_id: Option<String>,
+}
+
+/// The profiles that were active before the first lease was acquired
+#[derive(Debug, Default, PartialEq)]
+pub struct RestoreState {
+    pub current_profile: Option<Rc<str>>,
+    pub gpu_profiles: IndexMap<String, Rc<str>>,
+}
+
+/// The profiles which should be in effect while there are leases
+#[derive(Debug, Default, PartialEq)]
+pub struct ActiveProfiles {
+    pub current_profile: Option<Rc<str>>,
+    /// `None` means that the GPU follows the current profile
+    pub gpu_profiles: IndexMap<String, Option<Rc<str>>>,
+}
+
+impl From<RestoreState> for ActiveProfiles {
+    fn from(restore: RestoreState) -> Self {
+        Self {
+            current_profile: restore.current_profile,
+            gpu_profiles: restore
+                .gpu_profiles
+                .into_iter()
+                .map(|(gpu_id, name)| (gpu_id, Some(name)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LeaseRelease {
+    /// A newer lease of the same scope is in effect, so nothing changes
+    Keep,
+    /// The remaining leases should be activated again
+    Activate(ActiveProfiles),
+    /// There are no leases left
+    Restore(RestoreState),
+}
+
+impl ProfileLeases {
+    pub fn is_empty(&self) -> bool {
+        self.leases.is_empty()
+    }
+
+    /// Reserves an ID for a lease that is about to be acquired
+    pub fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// `restore` is only called for the first lease
+    pub fn acquire(&mut self, lease: ProfileLease, restore: impl FnOnce() -> RestoreState) {
+        if self.leases.is_empty() {
+            self.restore = Some(restore());
+        }
+        self.leases.push(lease);
+    }
+
+    /// Returns `None` if the lease does not exist (anymore)
+    pub fn release(&mut self, id: u64) -> Option<LeaseRelease> {
+        let index = self.leases.iter().position(|lease| lease.id == id)?;
+        let released = self.leases.remove(index);
+
+        let release = if self.leases.is_empty() {
+            LeaseRelease::Restore(self.restore.take().unwrap_or_default())
+        } else if self.leases[index..]
+            .iter()
+            .any(|lease| lease.gpu_id == released.gpu_id)
+        {
+            LeaseRelease::Keep
+        } else {
+            LeaseRelease::Activate(self.active_profiles())
+        };
+        Some(release)
+    }
+
+    /// The newest lease of each scope, with the profiles from before the leases for the scopes that have none.
+    /// GPUs without a lease of their own follow a global lease.
+    pub fn active_profiles(&self) -> ActiveProfiles {
+        let restore = self.restore.as_ref();
+        let global_lease = self
+            .leases
+            .iter()
+            .rev()
+            .find(|lease| lease.gpu_id.is_none());
+
+        let mut active = match global_lease {
+            Some(lease) => ActiveProfiles {
+                current_profile: lease.profile.clone(),
+                gpu_profiles: IndexMap::new(),
+            },
+            None => ActiveProfiles {
+                current_profile: restore.and_then(|restore| restore.current_profile.clone()),
+                gpu_profiles: restore
+                    .map(|restore| {
+                        restore
+                            .gpu_profiles
+                            .iter()
+                            .map(|(gpu_id, name)| (gpu_id.clone(), Some(name.clone())))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            },
+        };
+
+        for lease in &self.leases {
+            if let Some(gpu_id) = &lease.gpu_id {
+                active
+                    .gpu_profiles
+                    .insert(gpu_id.clone(), lease.profile.clone());
+            }
+        }
+
+        active
+    }
+
+    /// Forgets all leases without restoring anything, such as when a profile was selected manually
+    pub fn clear(&mut self) {
+        self.leases.clear();
+        self.restore = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActiveProfiles, LeaseRelease, ProfileLease, ProfileLeases, RestoreState};
+    use indexmap::IndexMap;
+    use std::rc::Rc;
+
+    fn acquire(
+        leases: &mut ProfileLeases,
+        profile: &str,
+        gpu_id: Option<&str>,
+        restore: fn() -> RestoreState,
+    ) -> u64 {
+        let id = leases.next_id();
+        let lease = ProfileLease {
+            id,
+            profile: Some(profile.into()),
+            gpu_id: gpu_id.map(str::to_owned),
+        };
+        leases.acquire(lease, restore);
+        id
+    }
+
+    fn desktop() -> RestoreState {
+        RestoreState {
+            current_profile: Some("desktop".into()),
+            gpu_profiles: IndexMap::from([("display".to_owned(), Rc::from("power-save"))]),
+        }
+    }
+
+    #[test]
+    fn nested_leases() {
+        let mut leases = ProfileLeases::default();
+
+        let bench = acquire(&mut leases, "benchmark", None, desktop);
+        // Only the state from before the first lease is restored
+        let render = acquire(
+            &mut leases,
+            "render",
+            Some("compute"),
+            RestoreState::default,
+        );
+        let quiet = acquire(&mut leases, "quiet", None, RestoreState::default);
+
+        // The GPU lease stays in effect when a global one is released
+        assert_eq!(
+            Some(LeaseRelease::Activate(ActiveProfiles {
+                current_profile: Some("benchmark".into()),
+                gpu_profiles: IndexMap::from([("compute".to_owned(), Some(Rc::from("render")))]),
+            })),
+            leases.release(quiet)
+        );
+        assert_eq!(None, leases.release(quiet));
+
+        let gaming = acquire(&mut leases, "gaming", None, RestoreState::default);
+        assert_eq!(Some(LeaseRelease::Keep), leases.release(bench));
+        assert_eq!(
+            Some(LeaseRelease::Activate(ActiveProfiles {
+                current_profile: Some("gaming".into()),
+                gpu_profiles: IndexMap::new(),
+            })),
+            leases.release(render)
+        );
+        assert_eq!(
+            Some(LeaseRelease::Restore(desktop())),
+            leases.release(gaming)
+        );
+        assert!(leases.is_empty());
+    }
+
+    #[test]
+    fn gpu_leases_only() {
+        let mut leases = ProfileLeases::default();
+        acquire(&mut leases, "render", Some("compute"), desktop);
+        let display = acquire(&mut leases, "render", Some("display"), desktop);
+
+        // Without a global lease, the GPU goes back to the profile it had before
+        assert_eq!(
+            Some(LeaseRelease::Activate(ActiveProfiles {
+                current_profile: Some("desktop".into()),
+                gpu_profiles: IndexMap::from([
+                    ("display".to_owned(), Some(Rc::from("power-save"))),
+                    ("compute".to_owned(), Some(Rc::from("render"))),
+                ]),
+            })),
+            leases.release(display)
+        );
+    }
+}
@@ -1,18 +1,35 @@
 mod gamemode;
+mod gpu_usage;
+mod power_source;
 mod process;
+mod rules;
+mod sensor;
 
-use crate::server::handler::Handler;
+pub use rules::CompiledRules;
+
+use crate::{config::Config, server::handler::Handler};
+use chrono::{Datelike, Local, NaiveDateTime, Timelike};
 use futures::StreamExt;
-use lact_schema::{ProfileRule, ProfileWatcherState};
+use indexmap::IndexMap;
+use lact_schema::{
+    config::{Profile, ProfileTiming},
+    AncestorProfileRule, EnvironmentProfileRule, GpuUsageProfileRule, PowerSource,
+    ProcessProfileRule, ProfileExplanation, ProfileProcessInfo, ProfileRule,
+    ProfileSelectionExplanation, ProfileSwitchTrigger, ProfileWatcherState, RuleTrace,
+    ScheduleProfileRule, Weekday,
+};
 use libcopes::PEvent;
 use std::{
+    collections::{HashMap, HashSet},
+    iter,
     rc::Rc,
+    sync::Arc,
     time::{Duration, Instant},
 };
 use tokio::{
     runtime, select,
     sync::{mpsc, Mutex, Notify},
-    time::sleep,
+    time::{self, sleep, MissedTickBehavior},
 };
 use tracing::{debug, error, info, trace};
 use zbus::AsyncDrop;
@@ -21,11 +38,17 @@ static PROFILE_WATCHER_LOCK: Mutex<()> = Mutex::const_new(());
 
 const PROFILE_WATCHER_MIN_DELAY_MS: u64 = 50;
 const PROFILE_WATCHER_MAX_DELAY_MS: u64 = 500;
+/// The schedule timer uses monotonic time, which does not account for suspend or system clock changes,
+/// so schedules are re-checked at least this often
+const SCHEDULE_MAX_SLEEP: Duration = Duration::from_secs(60);
+/// Limits how many parents are followed for ancestor rules, in case the recorded parent IDs form a loop after PID reuse
+const MAX_ANCESTRY_DEPTH: usize = 64;
 
 #[derive(Debug)]
 enum ProfileWatcherEvent {
     Process(PEvent),
     Gamemode(PEvent),
+    PowerSource(Option<PowerSource>),
 }
 
 pub enum ProfileWatcherCommand {
@@ -116,19 +139,76 @@ pub async fn run_watcher(handler: Handler, mut command_rx: mpsc::Receiver<Profil
         }
     }
 
+    let upower = power_source::connect().await;
+    state.power_source = power_source::current(upower.as_ref()).await;
+    debug!("current power source: {:?}", state.power_source);
+
+    let power_source_stop_notify = Rc::new(Notify::new());
+    let power_source_task = power_source::start_listener(
+        upower,
+        state.power_source,
+        event_tx.clone(),
+        power_source_stop_notify.clone(),
+    );
+
+    let mut sensor_tracker = sensor::SensorRuleTracker::default();
+    let mut sensor_interval = time::interval(sensor::SAMPLE_INTERVAL);
+    sensor_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut gpu_usage_interval = time::interval(gpu_usage::SAMPLE_INTERVAL);
+    gpu_usage_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
     *handler.profile_watcher_state.borrow_mut() = Some(state);
 
-    update_profile(&handler).await;
+    let mut switch_state = ProfileSwitchStates::default();
+    update_profile(&handler, &mut switch_state).await;
 
     let mut should_reload = false;
 
     loop {
+        let schedule_delay = next_schedule_change(&handler).await;
+        let sample_sensors = sensor::has_sensor_rules(&handler).await;
+        let sample_gpu_usage = gpu_usage::has_gpu_usage_rules(&handler).await;
+
+        let recheck_at = switch_state.recheck_at();
+
         select! {
+            () = time::sleep_until(recheck_at.unwrap_or_else(Instant::now).into()), if recheck_at.is_some() => {
+                trace!("re-evaluating held back profile switch");
+                update_profile(&handler, &mut switch_state).await;
+            }
+            () = sleep(schedule_delay.unwrap_or_default()), if schedule_delay.is_some() => {
+                trace!("re-evaluating scheduled profile rules");
+                update_profile(&handler, &mut switch_state).await;
+            }
+            _ = sensor_interval.tick(), if sample_sensors => {
+                sensor_tracker.sample(&handler).await;
+                let active_rules = sensor_tracker.active_rules();
+
+                let changed = match handler.profile_watcher_state.borrow_mut().as_mut() {
+                    Some(state) if state.active_sensor_rules != active_rules => {
+                        debug!("active sensor rules changed: {active_rules:?}");
+                        state.active_sensor_rules = active_rules;
+                        true
+                    }
+                    _ => false,
+                };
+                if changed {
+                    update_profile(&handler, &mut switch_state).await;
+                }
+            }
+            _ = gpu_usage_interval.tick(), if sample_gpu_usage => {
+                let gpu_processes = gpu_usage::sample(&handler).await;
+                if let Some(state) = handler.profile_watcher_state.borrow_mut().as_mut() {
+                    state.gpu_processes = gpu_processes;
+                }
+                update_profile(&handler, &mut switch_state).await;
+            }
             Some(cmd) = command_rx.recv() => {
                 match cmd {
                     ProfileWatcherCommand::Stop => break,
                     ProfileWatcherCommand::Update => {
-                        update_profile(&handler).await;
+                        update_profile(&handler, &mut switch_state).await;
                     }
                 }
             }
@@ -167,7 +247,7 @@ pub async fn run_watcher(handler: Handler, mut command_rx: mpsc::Receiver<Profil
                     }
                 }
 
-                update_profile(&handler).await;
+                update_profile(&handler, &mut switch_state).await;
             }
         }
 
@@ -183,6 +263,9 @@ pub async fn run_watcher(handler: Handler, mut command_rx: mpsc::Receiver<Profil
         gamemode_stop_notify.notify_one();
         handle.await.unwrap();
     }
+
+    power_source_stop_notify.notify_one();
+    power_source_task.await.unwrap();
 }
 
 fn handle_profile_event(event: &ProfileWatcherEvent, handler: &Handler, should_reload: &mut bool) {
@@ -219,49 +302,238 @@ fn handle_profile_event(event: &ProfileWatcherEvent, handler: &Handler, should_r
             ProfileWatcherEvent::Gamemode(PEvent::Exit(pid)) => {
                 state.gamemode_games.shift_remove(pid.as_ref());
             }
+            ProfileWatcherEvent::PowerSource(source) => {
+                match source {
+                    Some(source) => info!("power source changed to {source}"),
+                    None => info!("power source could not be detected"),
+                }
+                state.power_source = source;
+            }
         }
     }
 }
 
-async fn update_profile(handler: &Handler) {
-    let new_profile = {
+/// Keeps track of the automatically activated profile, to apply its switching delays
+#[derive(Default)]
+struct ProfileSwitchState {
+    /// The current profile and since when it has been active
+    activated: Option<(Rc<str>, Instant)>,
+    /// When the rule of the current profile stopped matching
+    unmatched_since: Option<Instant>,
+    /// When the rules need to be evaluated again because a profile switch was held back
+    recheck_at: Option<Instant>,
+}
+
+/// Switch states of the current profile and of every GPU that has profiles limited to it
+#[derive(Default)]
+struct ProfileSwitchStates {
+    global: ProfileSwitchState,
+    gpus: HashMap<String, ProfileSwitchState>,
+}
+
+impl ProfileSwitchStates {
+    fn recheck_at(&self) -> Option<Instant> {
+        iter::once(&self.global)
+            .chain(self.gpus.values())
+            .filter_map(|switch_state| switch_state.recheck_at)
+            .min()
+    }
+}
+
+impl ProfileSwitchState {
+    /// Returns for how much longer `current_profile` should be kept instead of switching to `new_profile`
+    fn hold_time(
+        &mut self,
+        config: &Config,
+        state: &ProfileWatcherState,
+        compiled: &CompiledRules,
+        current_profile: Option<&Rc<str>>,
+        new_profile: Option<&Rc<str>>,
+        now: Instant,
+    ) -> Option<Duration> {
+        if self.activated.as_ref().map(|(name, _)| name) != current_profile {
+            // The profile was not switched by the watcher, count its activation time from now on
+            self.activated = current_profile.map(|name| (name.clone(), now));
+            self.unmatched_since = None;
+        }
+        let (name, activated_at) = self.activated.as_ref()?;
+
+        if new_profile == Some(name) {
+            self.unmatched_since = None;
+            return None;
+        }
+
+        let profile = config.profiles.get(name)?;
+        let still_matches = !compiled.is_disabled(name)
+            && profile
+                .rule
+                .as_ref()
+                .is_some_and(|rule| profile_rule_matches(state, compiled, rule));
+        if still_matches {
+            self.unmatched_since = None;
+        } else {
+            self.unmatched_since.get_or_insert(now);
+        }
+
+        profile_hold_time(&profile.timing, *activated_at, self.unmatched_since, now)
+    }
+}
+
+/// The longest of the remaining minimum activation time and deactivation grace period
+fn profile_hold_time(
+    timing: &ProfileTiming,
+    activated_at: Instant,
+    unmatched_since: Option<Instant>,
+    now: Instant,
+) -> Option<Duration> {
+    let remaining = |since: Instant, secs: u64| {
+        (since + Duration::from_secs(secs)).saturating_duration_since(now)
+    };
+
+    let min_active = timing
+        .min_active_secs
+        .map(|secs| remaining(activated_at, secs));
+    let grace = unmatched_since
+        .zip(timing.deactivation_grace_secs)
+        .map(|(since, secs)| remaining(since, secs));
+
+    min_active
+        .into_iter()
+        .chain(grace)
+        .max()
+        .filter(|duration| !duration.is_zero())
+}
+
+async fn update_profile(handler: &Handler, switch_states: &mut ProfileSwitchStates) {
+    update_scope(handler, None, &mut switch_states.global).await;
+
+    let available_gpu_ids = handler.gpu_ids().await;
+    let scoped_gpu_ids: Vec<String> = handler
+        .config
+        .read()
+        .await
+        .scoped_gpu_ids()
+        .into_iter()
+        .filter(|id| available_gpu_ids.contains(id))
+        .collect();
+
+    switch_states
+        .gpus
+        .retain(|id, _| scoped_gpu_ids.contains(id));
+    for gpu_id in scoped_gpu_ids {
+        let switch_state = switch_states.gpus.entry(gpu_id.clone()).or_default();
+        update_scope(handler, Some(&gpu_id), switch_state).await;
+    }
+}
+
+/// Evaluates the rules of the profiles in a scope and switches to the matching one.
+///
+/// Without a GPU this picks the current profile out of the profiles that apply to all GPUs.
+/// With a GPU, the profiles limited to it are considered as well, but when an unscoped profile (or none) is selected
+/// the GPU simply follows the current profile.
+async fn update_scope(
+    handler: &Handler,
+    gpu_id: Option<&str>,
+    switch_state: &mut ProfileSwitchState,
+) {
+    let now = Instant::now();
+    switch_state.recheck_at = None;
+
+    let (current_profile, new_profile, hold_time, matched_process) = {
         let config = handler.config.read().await;
-        let profile_rules = config
-            .profiles
-            .iter()
+        let current_profile = match gpu_id {
+            Some(gpu_id) => config.current_gpu_profiles.get(gpu_id).cloned(),
+            None => config.current_profile.clone(),
+        };
+        let profile_rules = scope_profiles(&config, gpu_id)
             .filter_map(|(name, profile)| Some((name, profile.rule.as_ref()?)));
 
         let state_guard = handler.profile_watcher_state.borrow();
+        let compiled = handler.profile_rules.borrow();
         if let Some(state) = state_guard.as_ref() {
             let started_at = Instant::now();
-            let new_profile = evaluate_current_profile(state, profile_rules);
+            let mut new_profile =
+                evaluate_current_profile(state, &compiled, profile_rules).cloned();
             trace!("evaluated profile rules in {:?}", started_at.elapsed());
-            new_profile.cloned()
+
+            if gpu_id.is_some() {
+                new_profile = new_profile
+                    .filter(|name| config.profiles.get(name).is_some_and(Profile::is_scoped));
+            }
+
+            let hold_time = switch_state.hold_time(
+                &config,
+                state,
+                &compiled,
+                current_profile.as_ref(),
+                new_profile.as_ref(),
+                now,
+            );
+
+            let matched_process = if current_profile == new_profile {
+                None
+            } else {
+                new_profile
+                    .as_ref()
+                    .and_then(|name| config.profiles.get(name)?.rule.as_ref())
+                    .and_then(|rule| {
+                        matched_process(state, &profile_rule_trace(state, &compiled, rule))
+                    })
+            };
+            (current_profile, new_profile, hold_time, matched_process)
         } else {
-            None
+            (current_profile, None, None, None)
         }
     };
 
-    if handler.config.read().await.current_profile != new_profile {
-        if let Some(name) = &new_profile {
-            info!("setting current profile to '{name}'");
-        } else {
-            info!("setting default profile");
-        }
+    if let Some(hold_time) = hold_time {
+        debug!("keeping the current profile for {hold_time:?} before switching");
+        switch_state.recheck_at = Some(now + hold_time);
+        return;
+    }
+
+    if current_profile != new_profile {
+        let trigger = ProfileSwitchTrigger::Rule {
+            process: matched_process.map(|(pid, name)| (pid, name.to_string())),
+        };
+        let result = match gpu_id {
+            Some(gpu_id) => {
+                match &new_profile {
+                    Some(name) => info!("setting profile of GPU {gpu_id} to '{name}'"),
+                    None => info!("GPU {gpu_id} is following the current profile"),
+                }
+                handler
+                    .set_gpu_profile(gpu_id, new_profile.clone(), trigger)
+                    .await
+            }
+            None => {
+                match &new_profile {
+                    Some(name) => info!("setting current profile to '{name}'"),
+                    None => info!("setting default profile"),
+                }
+                handler
+                    .set_current_profile(new_profile.clone(), trigger)
+                    .await
+            }
+        };
 
-        if let Err(err) = handler.set_current_profile(new_profile).await {
+        if let Err(err) = result {
             error!("failed to apply profile: {err:#}");
         }
+
+        switch_state.activated = new_profile.map(|name| (name, Instant::now()));
+        switch_state.unmatched_since = None;
     }
 }
 
 /// Returns the new active profile
 fn evaluate_current_profile<'a>(
     state: &ProfileWatcherState,
+    compiled: &CompiledRules,
     profile_rules: impl Iterator<Item = (&'a Rc<str>, &'a ProfileRule)>,
 ) -> Option<&'a Rc<str>> {
     for (profile_name, rule) in profile_rules {
-        if profile_rule_matches(state, rule) {
+        if !compiled.is_disabled(profile_name) && profile_rule_matches(state, compiled, rule) {
             return Some(profile_name);
         }
     }
@@ -270,63 +542,578 @@ fn evaluate_current_profile<'a>(
 }
 
 #[inline]
-pub(crate) fn profile_rule_matches(state: &ProfileWatcherState, rule: &ProfileRule) -> bool {
+pub(crate) fn profile_rule_matches(
+    state: &ProfileWatcherState,
+    compiled: &CompiledRules,
+    rule: &ProfileRule,
+) -> bool {
     match rule {
         ProfileRule::Process(process_rule) => {
-            if let Some(pids) = state.process_names_map.get(&process_rule.name) {
-                match &process_rule.args {
-                    Some(args_filter) => {
-                        for pid in pids {
-                            if let Some(process_info) = state.process_list.get(pid) {
-                                if process_info.cmdline.contains(args_filter) {
-                                    return true;
-                                }
-                            } else {
-                                error!("process {pid} not found in process map");
-                            }
-                        }
-                    }
-                    None => return true,
-                }
-            }
+            process_rule_matches(state, compiled, process_rule, |_| true)
         }
-        ProfileRule::Gamemode(None) => return !state.gamemode_games.is_empty(),
+        ProfileRule::Gamemode(None) => !state.gamemode_games.is_empty(),
         ProfileRule::Gamemode(Some(gamemode_rule)) => {
-            if let Some(pids) = state.process_names_map.get(&gamemode_rule.name) {
-                for pid in pids {
-                    if state.gamemode_games.contains(pid) {
-                        match &gamemode_rule.args {
-                            Some(args_filter) => {
-                                if let Some(process_info) = state.process_list.get(pid) {
-                                    if process_info.cmdline.contains(args_filter) {
-                                        return true;
-                                    }
-                                } else {
-                                    error!("process {pid} not found in process map");
-                                }
-                            }
-                            None => return true,
-                        }
-                    }
-                }
-            }
+            process_rule_matches(state, compiled, gamemode_rule, |pid| {
+                state.gamemode_games.contains(&pid)
+            })
         }
         ProfileRule::And(rules) => {
-            return !rules.is_empty() && rules.iter().all(|rule| profile_rule_matches(state, rule));
+            !rules.is_empty()
+                && rules
+                    .iter()
+                    .all(|rule| profile_rule_matches(state, compiled, rule))
         }
         ProfileRule::Or(rules) => {
-            return !rules.is_empty() && rules.iter().any(|rule| profile_rule_matches(state, rule));
+            !rules.is_empty()
+                && rules
+                    .iter()
+                    .any(|rule| profile_rule_matches(state, compiled, rule))
+        }
+        ProfileRule::Not(rule) => !profile_rule_matches(state, compiled, rule),
+        ProfileRule::Schedule(schedule) => {
+            schedule_rule_matches(schedule, &Local::now().naive_local())
+        }
+        ProfileRule::PowerSource(source) => state.power_source == Some(*source),
+        ProfileRule::Sensor(sensor_rule) => state.active_sensor_rules.contains(sensor_rule),
+        ProfileRule::Environment(env_rule) => state
+            .process_list
+            .iter()
+            .any(|(pid, info)| process_environment_matches(compiled, env_rule, *pid, info)),
+        ProfileRule::Cgroup(cgroup_rule) => state
+            .process_list
+            .values()
+            .any(|info| compiled.cgroup_matches(cgroup_rule, &info.cgroup)),
+        ProfileRule::Ancestor(ancestor_rule) => {
+            let ancestors = ancestor_pids(state, compiled, ancestor_rule);
+            if ancestors.is_empty() {
+                return false;
+            }
+            match &ancestor_rule.process {
+                Some(process_rule) => process_rule_matches(state, compiled, process_rule, |pid| {
+                    descends_from(state, pid, &ancestors, ancestor_rule.parent_only)
+                }),
+                // A process has descendants exactly when it has direct children
+                None => state
+                    .process_list
+                    .values()
+                    .any(|info| ancestors.contains(&info.ppid)),
+            }
+        }
+        ProfileRule::GpuUsage(usage_rule) => {
+            gpu_usage_pids(state, compiled, usage_rule).next().is_some()
+        }
+    }
+}
+
+/// Checks if any of the processes accepted by `pid_filter` matches the rule
+#[inline]
+fn process_rule_matches(
+    state: &ProfileWatcherState,
+    compiled: &CompiledRules,
+    rule: &ProcessProfileRule,
+    pid_filter: impl Fn(i32) -> bool,
+) -> bool {
+    let pids_match = |pids: &HashSet<i32>| {
+        pids.iter()
+            .any(|pid| pid_filter(*pid) && process_args_match(state, compiled, rule, *pid))
+    };
+
+    if rule.has_exact_name() {
+        state
+            .process_names_map
+            .get(&rule.name)
+            .is_some_and(pids_match)
+    } else {
+        state
+            .process_names_map
+            .iter()
+            .any(|(name, pids)| compiled.name_matches(rule, name) && pids_match(pids))
+    }
+}
+
+/// Same as `process_rule_matches`, but collects all matching processes instead of stopping at the first one
+fn matching_pids(
+    state: &ProfileWatcherState,
+    compiled: &CompiledRules,
+    rule: &ProcessProfileRule,
+    pid_filter: impl Fn(i32) -> bool,
+) -> Vec<i32> {
+    let mut pids: Vec<i32> = name_matching_pids(state, compiled, rule)
+        .filter(|pid| pid_filter(*pid) && process_args_match(state, compiled, rule, *pid))
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+/// Processes whose name matches the rule, looked up directly when the name is matched exactly
+fn name_matching_pids<'a>(
+    state: &'a ProfileWatcherState,
+    compiled: &'a CompiledRules,
+    rule: &'a ProcessProfileRule,
+) -> impl Iterator<Item = i32> + 'a {
+    let (exact_pids, pattern_pids) = if rule.has_exact_name() {
+        (state.process_names_map.get(&rule.name), None)
+    } else {
+        let pids = state
+            .process_names_map
+            .iter()
+            .filter(|(name, _)| compiled.name_matches(rule, name))
+            .map(|(_, pids)| pids);
+        (None, Some(pids))
+    };
+
+    exact_pids
+        .into_iter()
+        .chain(pattern_pids.into_iter().flatten())
+        .flat_map(|pids| pids.iter().copied())
+}
+
+/// Processes in the sampled GPU process lists which pass the filters of the rule
+fn gpu_usage_pids<'a>(
+    state: &'a ProfileWatcherState,
+    compiled: &'a CompiledRules,
+    rule: &'a GpuUsageProfileRule,
+) -> impl Iterator<Item = i32> + 'a {
+    state
+        .gpu_processes
+        .iter()
+        .filter(|(gpu_id, _)| rule.gpu_id.as_ref().is_none_or(|id| id == *gpu_id))
+        .flat_map(|(_, process_list)| &process_list.processes)
+        .filter(|(_, info)| rule.usage_matches(&info.util))
+        .filter_map(|(pid, info)| {
+            let pid = i32::try_from(*pid).ok()?;
+            let process_matches = match &rule.process {
+                None => true,
+                // The watcher's process info is preferred, so that names are matched the same way as in process rules
+                Some(process_rule) => match state.process_list.get(&pid) {
+                    Some(process_info) => {
+                        compiled.name_matches(process_rule, &process_info.name)
+                            && compiled.args_match(process_rule, &process_info.cmdline)
+                    }
+                    None => {
+                        compiled.name_matches(process_rule, &info.name)
+                            && compiled.args_match(process_rule, &info.args)
+                    }
+                },
+            };
+            process_matches.then_some(pid)
+        })
+}
+
+fn ancestor_pids(
+    state: &ProfileWatcherState,
+    compiled: &CompiledRules,
+    rule: &AncestorProfileRule,
+) -> HashSet<i32> {
+    name_matching_pids(state, compiled, &rule.ancestor)
+        .filter(|pid| process_args_match(state, compiled, &rule.ancestor, *pid))
+        .collect()
+}
+
+/// Follows the recorded parent IDs of the process until one of the ancestors is found
+fn descends_from(
+    state: &ProfileWatcherState,
+    pid: i32,
+    ancestors: &HashSet<i32>,
+    parent_only: bool,
+) -> bool {
+    let mut current = pid;
+    for _ in 0..MAX_ANCESTRY_DEPTH {
+        let Some(info) = state.process_list.get(&current) else {
+            return false;
+        };
+        if ancestors.contains(&info.ppid) {
+            return true;
         }
+        if parent_only || info.ppid <= 1 {
+            return false;
+        }
+        current = info.ppid;
     }
     false
 }
 
+/// The environment is read on first use and cached along with the process info
+fn process_environment_matches(
+    compiled: &CompiledRules,
+    rule: &EnvironmentProfileRule,
+    pid: i32,
+    info: &ProfileProcessInfo,
+) -> bool {
+    compiled.environment_matches(
+        rule,
+        info.environment
+            .get_or_load(|| process::read_environment(pid)),
+    )
+}
+
+/// Checks the arguments of a process whose name already matched the rule
+fn process_args_match(
+    state: &ProfileWatcherState,
+    compiled: &CompiledRules,
+    rule: &ProcessProfileRule,
+    pid: i32,
+) -> bool {
+    if rule.args.is_none() {
+        return true;
+    }
+    match state.process_list.get(&pid) {
+        Some(process_info) => compiled.args_match(rule, &process_info.cmdline),
+        None => {
+            error!("process {pid} not found in process map");
+            false
+        }
+    }
+}
+
+/// Evaluates the rule like `profile_rule_matches`, but without short-circuiting,
+/// so that the result of every nested rule is available
+pub(crate) fn profile_rule_trace(
+    state: &ProfileWatcherState,
+    compiled: &CompiledRules,
+    rule: &ProfileRule,
+) -> RuleTrace {
+    let mut pids = Vec::new();
+    let mut children = Vec::new();
+
+    let matched = match rule {
+        ProfileRule::Process(process_rule) => {
+            pids = matching_pids(state, compiled, process_rule, |_| true);
+            !pids.is_empty()
+        }
+        ProfileRule::Gamemode(None) => {
+            pids = state.gamemode_games.iter().copied().collect();
+            !pids.is_empty()
+        }
+        ProfileRule::Gamemode(Some(gamemode_rule)) => {
+            pids = matching_pids(state, compiled, gamemode_rule, |pid| {
+                state.gamemode_games.contains(&pid)
+            });
+            !pids.is_empty()
+        }
+        ProfileRule::And(rules) => {
+            children = rules
+                .iter()
+                .map(|rule| profile_rule_trace(state, compiled, rule))
+                .collect();
+            !children.is_empty() && children.iter().all(|child| child.matched)
+        }
+        ProfileRule::Or(rules) => {
+            children = rules
+                .iter()
+                .map(|rule| profile_rule_trace(state, compiled, rule))
+                .collect();
+            children.iter().any(|child| child.matched)
+        }
+        ProfileRule::Not(rule) => {
+            let child = profile_rule_trace(state, compiled, rule);
+            let matched = !child.matched;
+            children.push(child);
+            matched
+        }
+        ProfileRule::Environment(env_rule) => {
+            pids = state
+                .process_list
+                .iter()
+                .filter(|(pid, info)| process_environment_matches(compiled, env_rule, **pid, info))
+                .map(|(pid, _)| *pid)
+                .collect();
+            pids.sort_unstable();
+            !pids.is_empty()
+        }
+        ProfileRule::Cgroup(cgroup_rule) => {
+            pids = state
+                .process_list
+                .iter()
+                .filter(|(_, info)| compiled.cgroup_matches(cgroup_rule, &info.cgroup))
+                .map(|(pid, _)| *pid)
+                .collect();
+            pids.sort_unstable();
+            !pids.is_empty()
+        }
+        ProfileRule::Ancestor(ancestor_rule) => {
+            let ancestors = ancestor_pids(state, compiled, ancestor_rule);
+            if !ancestors.is_empty() {
+                let is_descendant =
+                    |pid| descends_from(state, pid, &ancestors, ancestor_rule.parent_only);
+                pids = match &ancestor_rule.process {
+                    Some(process_rule) => {
+                        matching_pids(state, compiled, process_rule, is_descendant)
+                    }
+                    None => {
+                        let mut pids: Vec<i32> = state
+                            .process_list
+                            .keys()
+                            .copied()
+                            .filter(|pid| is_descendant(*pid))
+                            .collect();
+                        pids.sort_unstable();
+                        pids
+                    }
+                };
+            }
+            !pids.is_empty()
+        }
+        ProfileRule::GpuUsage(usage_rule) => {
+            pids = gpu_usage_pids(state, compiled, usage_rule).collect();
+            // The same process can be using multiple GPUs
+            pids.sort_unstable();
+            pids.dedup();
+            !pids.is_empty()
+        }
+        ProfileRule::Schedule(_) | ProfileRule::PowerSource(_) | ProfileRule::Sensor(_) => {
+            profile_rule_matches(state, compiled, rule)
+        }
+    };
+
+    RuleTrace {
+        rule: rule.clone(),
+        matched,
+        pids,
+        children,
+    }
+}
+
+/// Finds the first process that contributed to the rule being matched
+fn matched_process(state: &ProfileWatcherState, trace: &RuleTrace) -> Option<(i32, Arc<str>)> {
+    if !trace.matched {
+        return None;
+    }
+
+    match trace.pids.first() {
+        Some(pid) => {
+            let info = state.process_list.get(pid)?;
+            Some((*pid, info.name.clone()))
+        }
+        None => trace
+            .children
+            .iter()
+            .find_map(|child| matched_process(state, child)),
+    }
+}
+
+/// Traces the rules of all profiles, both for the current profile and for the GPUs with profiles limited to them
+pub(crate) fn explain_profile_selection(
+    state: &ProfileWatcherState,
+    compiled: &CompiledRules,
+    config: &Config,
+    gpu_ids: &[String],
+) -> ProfileSelectionExplanation {
+    let mut explanation = explain_scope_selection(state, compiled, config, None);
+    explanation.gpus = gpu_ids
+        .iter()
+        .map(|gpu_id| {
+            (
+                gpu_id.clone(),
+                explain_scope_selection(state, compiled, config, Some(gpu_id)),
+            )
+        })
+        .collect();
+    explanation
+}
+
+/// The first matching profile is selected the same way as in `update_scope`
+fn explain_scope_selection(
+    state: &ProfileWatcherState,
+    compiled: &CompiledRules,
+    config: &Config,
+    gpu_id: Option<&str>,
+) -> ProfileSelectionExplanation {
+    let mut selected_profile = None;
+
+    let profiles = scope_profiles(config, gpu_id)
+        .map(|(name, profile)| {
+            let trace = profile
+                .rule
+                .as_ref()
+                .map(|rule| profile_rule_trace(state, compiled, rule));
+            if selected_profile.is_none()
+                && !compiled.is_disabled(name)
+                && trace.as_ref().is_some_and(|trace| trace.matched)
+            {
+                selected_profile = Some(name.to_string());
+            }
+
+            ProfileExplanation {
+                name: name.to_string(),
+                trace,
+            }
+        })
+        .collect();
+
+    if gpu_id.is_some() {
+        selected_profile = selected_profile.filter(|name| {
+            config
+                .profiles
+                .get(name.as_str())
+                .is_some_and(Profile::is_scoped)
+        });
+    }
+
+    ProfileSelectionExplanation {
+        profiles,
+        selected_profile,
+        gpus: IndexMap::new(),
+    }
+}
+
+/// Profiles which can be selected in a scope: the ones that apply to all GPUs, and with a GPU also the ones limited to it
+fn scope_profiles<'a>(
+    config: &'a Config,
+    gpu_id: Option<&'a str>,
+) -> impl Iterator<Item = (&'a Rc<str>, &'a Profile)> {
+    config
+        .profiles
+        .iter()
+        .filter(move |(_, profile)| match gpu_id {
+            Some(gpu_id) => profile.applies_to(gpu_id),
+            None => !profile.is_scoped(),
+        })
+}
+
+fn schedule_rule_matches(rule: &ScheduleProfileRule, now: &NaiveDateTime) -> bool {
+    let day_enabled = |day: chrono::Weekday| {
+        rule.weekdays.is_empty()
+            || rule
+                .weekdays
+                .contains(&Weekday::ALL[day.num_days_from_monday() as usize])
+    };
+    let today = now.weekday();
+
+    if rule.times.is_empty() {
+        return day_enabled(today);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    let minute = (now.hour() * 60 + now.minute()) as u16;
+
+    rule.times.iter().any(|range| {
+        let start = range.start.minutes();
+        let end = range.end.minutes();
+
+        if start <= end {
+            day_enabled(today) && (start..end).contains(&minute)
+        } else {
+            // The part after midnight belongs to the range that started on the previous day
+            (day_enabled(today) && minute >= start) || (day_enabled(today.pred()) && minute < end)
+        }
+    })
+}
+
+/// Returns how long to wait before schedule rules need to be evaluated again, if there are any
+async fn next_schedule_change(handler: &Handler) -> Option<Duration> {
+    let config = handler.config.read().await;
+
+    let mut schedules = Vec::new();
+    for profile in config.profiles.values() {
+        if let Some(rule) = &profile.rule {
+            visit_rules(rule, &mut |rule| {
+                if let ProfileRule::Schedule(schedule) = rule {
+                    schedules.push(schedule);
+                }
+            });
+        }
+    }
+
+    schedule_change_delay(&schedules, &Local::now().naive_local())
+        .map(|delay| delay.min(SCHEDULE_MAX_SLEEP))
+}
+
+/// Calls the function on the rule and all of its nested rules
+fn visit_rules<'a>(rule: &'a ProfileRule, f: &mut impl FnMut(&'a ProfileRule)) {
+    f(rule);
+    match rule {
+        ProfileRule::And(rules) | ProfileRule::Or(rules) => {
+            for rule in rules {
+                visit_rules(rule, f);
+            }
+        }
+        ProfileRule::Not(rule) => visit_rules(rule, f),
+        _ => (),
+    }
+}
+
+/// Time until the next range start or end (or midnight, when the rule depends on the day of the week)
+fn schedule_change_delay(
+    schedules: &[&ScheduleProfileRule],
+    now: &NaiveDateTime,
+) -> Option<Duration> {
+    const DAY_SECS: u32 = 24 * 60 * 60;
+
+    let now_secs = now.num_seconds_from_midnight();
+
+    schedules
+        .iter()
+        .flat_map(|schedule| {
+            let midnight = (!schedule.weekdays.is_empty()).then_some(0);
+            schedule
+                .times
+                .iter()
+                .flat_map(|range| [range.start.minutes(), range.end.minutes()])
+                .chain(midnight)
+        })
+        .map(|boundary_minute| {
+            let boundary_secs = u32::from(boundary_minute) * 60 % DAY_SECS;
+            if boundary_secs > now_secs {
+                boundary_secs - now_secs
+            } else {
+                boundary_secs + DAY_SECS - now_secs
+            }
+        })
+        .min()
+        .map(|secs| {
+            Duration::from_secs(secs.into())
+                .saturating_sub(Duration::from_nanos(now.nanosecond().into()))
+        })
+}
+
 #[cfg(test)]
 mod tests {
-    use super::evaluate_current_profile;
-    use lact_schema::{ProcessProfileRule, ProfileProcessInfo, ProfileRule, ProfileWatcherState};
+    use super::{
+        evaluate_current_profile, explain_profile_selection, matched_process, profile_hold_time,
+        profile_rule_matches, profile_rule_trace, schedule_change_delay, schedule_rule_matches,
+        CompiledRules, ProfileSwitchState,
+    };
+    use crate::config::Config;
+    use chrono::NaiveDateTime;
+    use lact_schema::{
+        config::{Profile, ProfileTiming},
+        AncestorProfileRule, CgroupMatchKind, CgroupProfileRule, EnvironmentProfileRule,
+        GpuUsageProfileRule, MatchMode, ProcessInfo, ProcessList, ProcessProfileRule,
+        ProcessUtilizationType, ProfileProcessInfo, ProfileRule, ProfileWatcherState, RuleTrace,
+        ScheduleProfileRule, TimeRange, Weekday,
+    };
     use pretty_assertions::assert_eq;
-    use std::rc::Rc;
+    use std::{
+        collections::HashSet,
+        rc::Rc,
+        time::{Duration, Instant},
+    };
+
+    /// Compiles the patterns of the rule before matching it. Invalid patterns are left out, so they never match.
+    fn rule_matches(state: &ProfileWatcherState, rule: &ProfileRule) -> bool {
+        let compiled = CompiledRules::compile(rule).unwrap_or_default();
+        profile_rule_matches(state, &compiled, rule)
+    }
+
+    fn rule_trace(state: &ProfileWatcherState, rule: &ProfileRule) -> RuleTrace {
+        let compiled = CompiledRules::compile(rule).unwrap_or_default();
+        profile_rule_trace(state, &compiled, rule)
+    }
+
+    fn datetime(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn night_schedule() -> ScheduleProfileRule {
+        ScheduleProfileRule {
+            weekdays: vec![Weekday::Friday, Weekday::Saturday],
+            times: vec![TimeRange {
+                start: "22:30".parse().unwrap(),
+                end: "07:00".parse().unwrap(),
+            }],
+        }
+    }
 
     #[test]
     fn evaluate_basic_profile() {
@@ -336,6 +1123,7 @@ mod tests {
             ProfileProcessInfo {
                 name: "game1".into(),
                 cmdline: "".into(),
+                ..Default::default()
             },
         );
 
@@ -344,21 +1132,25 @@ mod tests {
                 "1".into(),
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game1".into(),
-                    args: None,
+                    ..Default::default()
                 }),
             ),
             (
                 "2".into(),
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game2".into(),
-                    args: None,
+                    ..Default::default()
                 }),
             ),
         ];
 
         assert_eq!(
             Some(&Rc::from("1")),
-            evaluate_current_profile(&state, profile_rules.iter().map(|(key, rule)| (key, rule)))
+            evaluate_current_profile(
+                &state,
+                &CompiledRules::default(),
+                profile_rules.iter().map(|(key, rule)| (key, rule))
+            )
         );
 
         state.push_process(
@@ -366,11 +1158,16 @@ mod tests {
             ProfileProcessInfo {
                 name: "game2".into(),
                 cmdline: "".into(),
+                ..Default::default()
             },
         );
         assert_eq!(
             Some(&Rc::from("2")),
-            evaluate_current_profile(&state, profile_rules.iter().map(|(key, rule)| (key, rule)))
+            evaluate_current_profile(
+                &state,
+                &CompiledRules::default(),
+                profile_rules.iter().map(|(key, rule)| (key, rule))
+            )
         );
 
         state.push_process(
@@ -378,52 +1175,719 @@ mod tests {
             ProfileProcessInfo {
                 name: "game3".into(),
                 cmdline: "".into(),
+                ..Default::default()
             },
         );
         assert_eq!(
             None,
-            evaluate_current_profile(&state, profile_rules.iter().map(|(key, rule)| (key, rule)))
+            evaluate_current_profile(
+                &state,
+                &CompiledRules::default(),
+                profile_rules.iter().map(|(key, rule)| (key, rule))
+            )
+        );
+    }
+
+    #[test]
+    fn evaluate_not_rule() {
+        let mut state = ProfileWatcherState::default();
+        state.push_process(
+            1,
+            ProfileProcessInfo {
+                name: "game1".into(),
+                cmdline: "".into(),
+                ..Default::default()
+            },
+        );
+
+        let process_rule = |name: &str| {
+            ProfileRule::Process(ProcessProfileRule {
+                name: name.into(),
+                ..Default::default()
+            })
+        };
+        let rule = ProfileRule::And(vec![
+            process_rule("game1"),
+            ProfileRule::Not(Box::new(process_rule("obs"))),
+        ]);
+        assert!(rule_matches(&state, &rule));
+
+        state.push_process(
+            2,
+            ProfileProcessInfo {
+                name: "obs".into(),
+                cmdline: "".into(),
+                ..Default::default()
+            },
+        );
+        assert!(!rule_matches(&state, &rule));
+    }
+
+    #[test]
+    fn explain_selection() {
+        let mut state = ProfileWatcherState::default();
+        for (pid, name) in [(1, "game1"), (2, "obs"), (3, "game1")] {
+            state.push_process(
+                pid,
+                ProfileProcessInfo {
+                    name: name.into(),
+                    cmdline: "".into(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let process_rule = |name: &str| {
+            ProfileRule::Process(ProcessProfileRule {
+                name: name.into(),
+                ..Default::default()
+            })
+        };
+        let no_obs_rule = ProfileRule::And(vec![
+            process_rule("game1"),
+            ProfileRule::Not(Box::new(process_rule("obs"))),
+        ]);
+        let any_game_rule = ProfileRule::Or(vec![process_rule("game2"), process_rule("game1")]);
+        let mut config = Config::default();
+        for (name, rule, gpu_ids) in [
+            ("no-obs", Some(no_obs_rule), vec![]),
+            ("manual", None, vec![]),
+            ("game", Some(any_game_rule), vec![]),
+            ("obs", Some(process_rule("obs")), vec!["gpu-1".to_owned()]),
+        ] {
+            config.profiles.insert(
+                name.into(),
+                Profile {
+                    rule,
+                    gpu_ids,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let explanation = explain_profile_selection(
+            &state,
+            &CompiledRules::default(),
+            &config,
+            &["gpu-1".to_owned()],
+        );
+        assert_eq!(Some("game".to_owned()), explanation.selected_profile);
+        assert_eq!(
+            evaluate_current_profile(
+                &state,
+                &CompiledRules::default(),
+                config
+                    .profiles
+                    .iter()
+                    .filter(|(_, profile)| !profile.is_scoped())
+                    .filter_map(|(name, profile)| Some((name, profile.rule.as_ref()?)))
+            )
+            .map(Rc::to_string),
+            explanation.selected_profile
+        );
+        assert_eq!(3, explanation.profiles.len());
+
+        // The scoped profile comes after the matching unscoped one, so the GPU follows the current profile
+        let gpu_explanation = &explanation.gpus["gpu-1"];
+        assert_eq!(4, gpu_explanation.profiles.len());
+        assert!(gpu_explanation.profiles[3].trace.as_ref().unwrap().matched);
+        assert_eq!(None, gpu_explanation.selected_profile);
+
+        config.profiles.move_index(3, 0);
+        let explanation = explain_profile_selection(
+            &state,
+            &CompiledRules::default(),
+            &config,
+            &["gpu-1".to_owned()],
+        );
+        assert_eq!(
+            Some("obs".to_owned()),
+            explanation.gpus["gpu-1"].selected_profile
+        );
+
+        let explanation =
+            explain_profile_selection(&state, &CompiledRules::default(), &config, &[]);
+        let no_obs = explanation.profiles[0].trace.as_ref().unwrap();
+        assert!(!no_obs.matched);
+        // All nested rules are evaluated, even after the first match
+        assert_eq!(vec![1, 3], no_obs.children[0].pids);
+        assert!(no_obs.children[0].matched);
+        assert!(!no_obs.children[1].matched);
+        assert_eq!(vec![2], no_obs.children[1].children[0].pids);
+
+        assert_eq!(None, explanation.profiles[1].trace);
+
+        let game = explanation.profiles[2].trace.as_ref().unwrap();
+        assert!(game.matched);
+        assert!(!game.children[0].matched);
+        assert!(game.children[0].pids.is_empty());
+        assert_eq!(vec![1, 3], game.children[1].pids);
+        assert_eq!(Some((1, "game1".into())), matched_process(&state, game));
+        assert_eq!(None, matched_process(&state, no_obs));
+    }
+
+    #[test]
+    fn hold_time_delays() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let timing = ProfileTiming {
+            min_active_secs: Some(30),
+            deactivation_grace_secs: Some(10),
+        };
+
+        assert_eq!(
+            Some(Duration::from_secs(25)),
+            profile_hold_time(&timing, start, Some(at(5)), at(5))
+        );
+        // The grace period outlasts the minimum activation time
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            profile_hold_time(&timing, start, Some(at(28)), at(28))
+        );
+        assert_eq!(
+            None,
+            profile_hold_time(&timing, start, Some(at(28)), at(38))
+        );
+        assert_eq!(None, profile_hold_time(&timing, start, None, at(30)));
+        assert_eq!(
+            None,
+            profile_hold_time(&ProfileTiming::default(), start, Some(start), start)
+        );
+    }
+
+    #[test]
+    fn switch_state_grace_period() {
+        let mut state = ProfileWatcherState::default();
+        let mut config = Config::default();
+        config.profiles.insert(
+            "game".into(),
+            Profile {
+                rule: Some(ProfileRule::Process(ProcessProfileRule {
+                    name: "game".into(),
+                    ..Default::default()
+                })),
+                timing: ProfileTiming {
+                    min_active_secs: None,
+                    deactivation_grace_secs: Some(10),
+                },
+                ..Default::default()
+            },
+        );
+        config.current_profile = Some("game".into());
+        let current = config.current_profile.clone();
+        let compiled = CompiledRules::new(&config.profiles);
+
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut switch_state = ProfileSwitchState::default();
+
+        // The game process is gone
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            switch_state.hold_time(&config, &state, &compiled, current.as_ref(), None, start)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(4)),
+            switch_state.hold_time(&config, &state, &compiled, current.as_ref(), None, at(6))
+        );
+
+        // Restarting within the grace period resets it
+        state.push_process(
+            1,
+            ProfileProcessInfo {
+                name: "game".into(),
+                cmdline: "".into(),
+                ..Default::default()
+            },
+        );
+        let game = Rc::from("game");
+        assert_eq!(
+            None,
+            switch_state.hold_time(
+                &config,
+                &state,
+                &compiled,
+                current.as_ref(),
+                Some(&game),
+                at(8)
+            )
+        );
+        state.remove_process(1);
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            switch_state.hold_time(&config, &state, &compiled, current.as_ref(), None, at(9))
+        );
+        assert_eq!(
+            None,
+            switch_state.hold_time(&config, &state, &compiled, current.as_ref(), None, at(19))
+        );
+    }
+
+    #[test]
+    fn evaluate_environment_rule() {
+        let mut state = ProfileWatcherState::default();
+        let info = ProfileProcessInfo {
+            name: "wine64-preloader".into(),
+            cmdline: "".into(),
+            ..Default::default()
+        };
+        // Loaded in advance, so the rule does not read the environment from /proc
+        info.environment.get_or_load(|| {
+            vec![
+                ("WINEPREFIX".into(), "/games/pfx".into()),
+                ("SteamAppId".into(), "1091500".into()),
+            ]
+            .into()
+        });
+        state.push_process(100, info);
+
+        let rule = ProfileRule::Environment(EnvironmentProfileRule {
+            name: "SteamAppId".to_owned(),
+            value: Some("1091500".to_owned()),
+            ..Default::default()
+        });
+        assert!(rule_matches(&state, &rule));
+        assert_eq!(vec![100], rule_trace(&state, &rule).pids);
+
+        let other_game_rule = ProfileRule::Environment(EnvironmentProfileRule {
+            name: "SteamAppId".to_owned(),
+            value: Some("570".to_owned()),
+            ..Default::default()
+        });
+        assert!(!rule_matches(&state, &other_game_rule));
+    }
+
+    #[test]
+    fn evaluate_cgroup_rule() {
+        let mut state = ProfileWatcherState::default();
+        for (pid, cgroup) in [
+            (1, "/user.slice/user-1000.slice/session-2.scope"),
+            (
+                2,
+                "/render-farm.slice/render-farm-blender.slice/job-1.service",
+            ),
+            (
+                3,
+                "/render-farm.slice/render-farm-blender.slice/job-1.service",
+            ),
+        ] {
+            state.push_process(
+                pid,
+                ProfileProcessInfo {
+                    name: "python".into(),
+                    cmdline: "".into(),
+                    cgroup: cgroup.into(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let rule = ProfileRule::Cgroup(CgroupProfileRule {
+            kind: CgroupMatchKind::Slice,
+            value: "render-farm.slice".to_owned(),
+            ..Default::default()
+        });
+        assert!(rule_matches(&state, &rule));
+        assert_eq!(vec![2, 3], rule_trace(&state, &rule).pids);
+
+        state.remove_process(2);
+        state.remove_process(3);
+        assert!(!rule_matches(&state, &rule));
+    }
+
+    #[test]
+    fn evaluate_ancestor_rule() {
+        let mut state = ProfileWatcherState::default();
+        for (pid, ppid, name) in [
+            (1, 0, "systemd"),
+            (10, 1, "steam"),
+            (11, 10, "reaper"),
+            (12, 11, "game.exe"),
+            (20, 1, "game.exe"),
+        ] {
+            state.push_process(
+                pid,
+                ProfileProcessInfo {
+                    name: name.into(),
+                    cmdline: "".into(),
+                    ppid,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let process = |name: &str| ProcessProfileRule {
+            name: name.into(),
+            ..Default::default()
+        };
+        let rule = |ancestor, process, parent_only| {
+            ProfileRule::Ancestor(AncestorProfileRule {
+                ancestor,
+                process,
+                parent_only,
+            })
+        };
+
+        let game_rule = rule(process("steam"), Some(process("game.exe")), false);
+        assert!(rule_matches(&state, &game_rule));
+        assert_eq!(vec![12], rule_trace(&state, &game_rule).pids);
+
+        let child_rule = rule(process("steam"), Some(process("game.exe")), true);
+        assert!(!rule_matches(&state, &child_rule));
+        let reaper_child_rule = rule(process("reaper"), Some(process("game.exe")), true);
+        assert!(rule_matches(&state, &reaper_child_rule));
+
+        let any_descendant_rule = rule(process("steam"), None, false);
+        assert!(rule_matches(&state, &any_descendant_rule));
+        assert_eq!(vec![11, 12], rule_trace(&state, &any_descendant_rule).pids);
+
+        state.remove_process(11);
+        state.remove_process(12);
+        assert!(!rule_matches(&state, &any_descendant_rule));
+        assert!(!rule_trace(&state, &game_rule).matched);
+    }
+
+    #[test]
+    fn evaluate_gpu_usage_rule() {
+        let mut state = ProfileWatcherState::default();
+        state.push_process(
+            100,
+            ProfileProcessInfo {
+                name: "game".into(),
+                cmdline: "game --fullscreen".into(),
+                ..Default::default()
+            },
+        );
+        state.push_process(
+            101,
+            ProfileProcessInfo {
+                name: "game".into(),
+                cmdline: "game --update".into(),
+                ..Default::default()
+            },
+        );
+
+        let gpu_process = |name: &str, graphics_usage| ProcessInfo {
+            name: name.to_owned(),
+            args: String::new(),
+            memory_used: 0,
+            types: vec![],
+            util: [(ProcessUtilizationType::Graphics, graphics_usage)].into(),
+        };
+        state.gpu_processes.insert(
+            "gpu-1".to_owned(),
+            ProcessList {
+                processes: [(100, gpu_process("game", 80)), (200, gpu_process("obs", 5))].into(),
+                supported_util_types: HashSet::new(),
+            },
+        );
+
+        let rule = |gpu_id: Option<&str>, name: Option<&str>, min_usage, engine| {
+            ProfileRule::GpuUsage(GpuUsageProfileRule {
+                gpu_id: gpu_id.map(str::to_owned),
+                process: name.map(|name| ProcessProfileRule {
+                    name: name.into(),
+                    ..Default::default()
+                }),
+                min_usage,
+                engine,
+            })
+        };
+
+        // The updater process has the same name, but is not using the GPU
+        let game_rule = rule(Some("gpu-1"), Some("game"), None, None);
+        assert!(rule_matches(&state, &game_rule));
+        assert_eq!(vec![100], rule_trace(&state, &game_rule).pids);
+
+        assert!(!rule_matches(
+            &state,
+            &rule(Some("gpu-2"), Some("game"), None, None)
+        ));
+
+        let busy_rule = rule(None, None, Some(50), None);
+        assert_eq!(vec![100], rule_trace(&state, &busy_rule).pids);
+        assert!(!rule_matches(
+            &state,
+            &rule(None, None, Some(50), Some(ProcessUtilizationType::Compute))
+        ));
+        assert!(rule_matches(
+            &state,
+            &rule(
+                None,
+                Some("obs"),
+                Some(5),
+                Some(ProcessUtilizationType::Graphics)
+            )
+        ));
+    }
+
+    #[test]
+    fn process_match_modes() {
+        let mut state = ProfileWatcherState::default();
+        state.push_process(
+            1,
+            ProfileProcessInfo {
+                name: "wine64-preloader".into(),
+                cmdline: "Z:\\games\\Cyberpunk 2077\\bin\\x64\\Cyberpunk2077.exe --launcher-skip"
+                    .into(),
+                ..Default::default()
+            },
+        );
+
+        let rule = |name: &str, name_match, args: Option<&str>, args_match| {
+            ProfileRule::Process(ProcessProfileRule {
+                name: name.into(),
+                args: args.map(str::to_owned),
+                name_match,
+                args_match,
+                ..Default::default()
+            })
+        };
+
+        assert!(rule_matches(
+            &state,
+            &rule("wine64-preloader", None, Some("Cyberpunk2077"), None)
+        ));
+        assert!(!rule_matches(&state, &rule("wine*", None, None, None)));
+        assert!(rule_matches(
+            &state,
+            &rule("wine*", Some(MatchMode::Glob), None, None)
+        ));
+        assert!(rule_matches(
+            &state,
+            &rule(
+                "wine*",
+                Some(MatchMode::Glob),
+                Some(r"(?i)cyberpunk\d+\.exe"),
+                Some(MatchMode::Regex)
+            )
+        ));
+        assert!(!rule_matches(
+            &state,
+            &rule(
+                "wine*",
+                Some(MatchMode::Glob),
+                Some("Cyberpunk2077"),
+                Some(MatchMode::Exact)
+            )
+        ));
+        assert!(rule_matches(
+            &state,
+            &rule("preloader", Some(MatchMode::Contains), None, None)
+        ));
+        // Invalid patterns never match
+        assert!(!rule_matches(
+            &state,
+            &rule("wine(", Some(MatchMode::Regex), None, None)
+        ));
+
+        // A profile with an invalid pattern is skipped, even if its rule would match because of a `not`
+        let mut config = Config::default();
+        for (name, rule) in [
+            (
+                "invalid",
+                ProfileRule::Not(Box::new(rule("wine(", Some(MatchMode::Regex), None, None))),
+            ),
+            ("wine", rule("wine*", Some(MatchMode::Glob), None, None)),
+        ] {
+            config.profiles.insert(
+                name.into(),
+                Profile {
+                    rule: Some(rule),
+                    ..Default::default()
+                },
+            );
+        }
+        let compiled = CompiledRules::new(&config.profiles);
+        assert_eq!(
+            Some(&Rc::from("wine")),
+            evaluate_current_profile(
+                &state,
+                &compiled,
+                config
+                    .profiles
+                    .iter()
+                    .filter_map(|(name, profile)| Some((name, profile.rule.as_ref()?)))
+            )
+        );
+    }
+
+    #[test]
+    fn schedule_overnight_range() {
+        let rule = night_schedule();
+
+        // 2025-01-03 is a Friday
+        assert!(!schedule_rule_matches(
+            &rule,
+            &datetime("2025-01-03 22:29:59")
+        ));
+        assert!(schedule_rule_matches(
+            &rule,
+            &datetime("2025-01-03 22:30:00")
+        ));
+        assert!(schedule_rule_matches(
+            &rule,
+            &datetime("2025-01-04 06:59:00")
+        ));
+        assert!(!schedule_rule_matches(
+            &rule,
+            &datetime("2025-01-04 07:00:00")
+        ));
+        // Sunday morning is still a part of the range that started on Saturday
+        assert!(schedule_rule_matches(
+            &rule,
+            &datetime("2025-01-05 03:00:00")
+        ));
+        assert!(!schedule_rule_matches(
+            &rule,
+            &datetime("2025-01-05 23:00:00")
+        ));
+        // Friday morning belongs to Thursday
+        assert!(!schedule_rule_matches(
+            &rule,
+            &datetime("2025-01-03 03:00:00")
+        ));
+    }
+
+    #[test]
+    fn schedule_whole_day() {
+        let rule = ScheduleProfileRule {
+            weekdays: vec![Weekday::Sunday],
+            times: vec![],
+        };
+        assert!(schedule_rule_matches(
+            &rule,
+            &datetime("2025-01-05 00:00:00")
+        ));
+        assert!(!schedule_rule_matches(
+            &rule,
+            &datetime("2025-01-06 00:00:00")
+        ));
+        assert!(schedule_rule_matches(
+            &ScheduleProfileRule::default(),
+            &datetime("2025-01-06 12:00:00")
+        ));
+    }
+
+    #[test]
+    fn schedule_next_change() {
+        let rule = night_schedule();
+
+        assert_eq!(
+            Some(Duration::from_secs(1800)),
+            schedule_change_delay(&[&rule], &datetime("2025-01-03 22:00:00"))
+        );
+        assert_eq!(
+            Some(Duration::from_secs(3600)),
+            schedule_change_delay(&[&rule], &datetime("2025-01-03 23:00:00"))
+        );
+        assert_eq!(
+            Some(Duration::from_secs(55_800)),
+            schedule_change_delay(&[&rule], &datetime("2025-01-04 07:00:00"))
+        );
+        assert_eq!(
+            None,
+            schedule_change_delay(
+                &[&ScheduleProfileRule::default()],
+                &datetime("2025-01-04 07:00:00")
+            )
         );
     }
 }
 
 #[cfg(feature = "bench")]
 mod benches {
-    use super::evaluate_current_profile;
+    use super::{evaluate_current_profile, CompiledRules};
     use divan::Bencher;
-    use lact_schema::{ProcessProfileRule, ProfileProcessInfo, ProfileRule, ProfileWatcherState};
+    use lact_schema::{
+        MatchMode, ProcessProfileRule, ProfileProcessInfo, ProfileRule, ProfileWatcherState,
+    };
     use std::hint::black_box;
 
-    #[divan::bench(sample_size = 1000, min_time = 2)]
-    fn evaluate_profiles(bencher: Bencher) {
+    fn process_state() -> ProfileWatcherState {
         let mut state = ProfileWatcherState::default();
 
         for pid in 1..2000 {
             let name = format!("process-{pid}").into();
             let cmdline = format!("{name} arg1 arg2 --arg3").into();
-            state.push_process(pid, ProfileProcessInfo { name, cmdline });
+            state.push_process(
+                pid,
+                ProfileProcessInfo {
+                    name,
+                    cmdline,
+                    ..Default::default()
+                },
+            );
         }
 
+        state
+    }
+
+    #[divan::bench(sample_size = 1000, min_time = 2)]
+    fn evaluate_profiles(bencher: Bencher) {
+        let state = process_state();
+
         let profile_rules = [
             (
                 "1".into(),
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game-abc".into(),
-                    args: None,
+                    ..Default::default()
                 }),
             ),
             (
                 "2".into(),
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game-1034".into(),
-                    args: None,
+                    ..Default::default()
                 }),
             ),
         ];
 
+        let compiled = CompiledRules::default();
+
+        bencher.bench_local(move || {
+            evaluate_current_profile(
+                black_box(&state),
+                &compiled,
+                black_box(profile_rules.iter().map(|(key, rule)| (key, rule))),
+            );
+        });
+    }
+
+    #[divan::bench(sample_size = 1000, min_time = 2)]
+    fn evaluate_pattern_profiles(bencher: Bencher) {
+        let state = process_state();
+
+        let profile_rules = [
+            (
+                "1".into(),
+                ProfileRule::Process(ProcessProfileRule {
+                    name: "game-*".into(),
+                    name_match: Some(MatchMode::Glob),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "2".into(),
+                ProfileRule::Process(ProcessProfileRule {
+                    name: "process-1034".into(),
+                    args: Some(r"--arg\d+$".to_owned()),
+                    args_match: Some(MatchMode::Regex),
+                    ..Default::default()
+                }),
+            ),
+        ];
+
+        let compiled = CompiledRules::compile(&ProfileRule::Or(
+            profile_rules.iter().map(|(_, rule)| rule.clone()).collect(),
+        ))
+        .unwrap();
+
         bencher.bench_local(move || {
             evaluate_current_profile(
                 black_box(&state),
+                &compiled,
                 black_box(profile_rules.iter().map(|(key, rule)| (key, rule))),
             );
         });
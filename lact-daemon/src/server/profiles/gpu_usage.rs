@@ -0,0 +1,67 @@
+use super::visit_rules;
+use crate::server::handler::Handler;
+use lact_schema::{ProcessList, ProfileRule};
+use std::{collections::HashMap, time::Duration};
+use tracing::debug;
+
+/// Utilization is calculated from the difference between two samples, so it is only known after the second one
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reads the process lists of the GPUs referenced by GPU usage rules
+pub async fn sample(handler: &Handler) -> HashMap<String, ProcessList> {
+    let mut gpu_processes = HashMap::new();
+
+    let Some(gpu_ids) = sampled_gpus(handler).await else {
+        return gpu_processes;
+    };
+
+    for gpu_id in gpu_ids {
+        match handler.process_list(&gpu_id).await {
+            Ok(process_list) => {
+                gpu_processes.insert(gpu_id, process_list);
+            }
+            Err(err) => {
+                debug!("could not get process list of GPU {gpu_id} for usage rule: {err:#}")
+            }
+        }
+    }
+
+    gpu_processes
+}
+
+pub async fn has_gpu_usage_rules(handler: &Handler) -> bool {
+    sampled_gpus(handler).await.is_some()
+}
+
+/// `None` when there are no GPU usage rules
+async fn sampled_gpus(handler: &Handler) -> Option<Vec<String>> {
+    let mut gpu_ids = Vec::new();
+    let mut has_rules = false;
+    let mut all_gpus = false;
+
+    {
+        let config = handler.config.read().await;
+        for profile in config.profiles.values() {
+            if let Some(rule) = &profile.rule {
+                visit_rules(rule, &mut |rule| {
+                    if let ProfileRule::GpuUsage(usage_rule) = rule {
+                        has_rules = true;
+                        match &usage_rule.gpu_id {
+                            Some(gpu_id) => {
+                                if !gpu_ids.contains(gpu_id) {
+                                    gpu_ids.push(gpu_id.clone());
+                                }
+                            }
+                            None => all_gpus = true,
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    if all_gpus {
+        gpu_ids = handler.gpu_ids().await;
+    }
+    has_rules.then_some(gpu_ids)
+}
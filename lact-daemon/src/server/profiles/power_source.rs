@@ -0,0 +1,222 @@
+use super::ProfileWatcherEvent;
+use futures::StreamExt;
+use lact_schema::PowerSource;
+use std::{fs, path::Path, rc::Rc, time::Duration};
+use tokio::{
+    select,
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+    time::sleep,
+};
+use tracing::{debug, error, info};
+use zbus::{proxy, Connection};
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+/// How often sysfs is checked for changes when UPower is not available
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+pub trait UPower {
+    #[zbus(property)]
+    fn on_battery(&self) -> zbus::Result<bool>;
+}
+
+pub async fn connect() -> Option<UPowerProxy<'static>> {
+    let conn = Connection::system()
+        .await
+        .map_err(|err| debug!("could not connect to system DBus: {err}"))
+        .ok()?;
+
+    let proxy = UPowerProxy::new(&conn)
+        .await
+        .map_err(|err| debug!("could not connect to UPower: {err}"))
+        .ok()?;
+
+    // Make sure the service is actually running
+    match proxy.on_battery().await {
+        Ok(_) => {
+            info!("using UPower for power source detection");
+            Some(proxy)
+        }
+        Err(err) => {
+            info!("UPower is not available ({err}), reading power source from sysfs");
+            None
+        }
+    }
+}
+
+pub async fn current(upower: Option<&UPowerProxy<'_>>) -> Option<PowerSource> {
+    if let Some(proxy) = upower {
+        match proxy.on_battery().await {
+            Ok(on_battery) => return Some(from_on_battery(on_battery)),
+            Err(err) => error!("could not get power source from UPower: {err}"),
+        }
+    }
+    read_sysfs(Path::new(POWER_SUPPLY_PATH))
+}
+
+pub(super) fn start_listener(
+    upower: Option<UPowerProxy<'static>>,
+    initial: Option<PowerSource>,
+    event_tx: mpsc::Sender<ProfileWatcherEvent>,
+    stop_notify: Rc<Notify>,
+) -> JoinHandle<()> {
+    tokio::task::spawn_local(async move {
+        if let Some(proxy) = upower {
+            let mut changes = proxy.receive_on_battery_changed().await;
+            loop {
+                select! {
+                    Some(change) = changes.next() => {
+                        match change.get().await {
+                            Ok(on_battery) => {
+                                let source = Some(from_on_battery(on_battery));
+                                let _ = event_tx.send(ProfileWatcherEvent::PowerSource(source)).await;
+                            }
+                            Err(err) => error!("could not get power source change: {err}"),
+                        }
+                    }
+                    () = stop_notify.notified() => break,
+                }
+            }
+        } else {
+            let mut last_source = initial;
+            loop {
+                select! {
+                    () = sleep(POLL_INTERVAL) => {
+                        let source = read_sysfs(Path::new(POWER_SUPPLY_PATH));
+                        if source != last_source {
+                            last_source = source;
+                            let _ = event_tx.send(ProfileWatcherEvent::PowerSource(source)).await;
+                        }
+                    }
+                    () = stop_notify.notified() => break,
+                }
+            }
+        }
+        debug!("exited power source watcher");
+    })
+}
+
+fn from_on_battery(on_battery: bool) -> PowerSource {
+    if on_battery {
+        PowerSource::Battery
+    } else {
+        PowerSource::Ac
+    }
+}
+
+/// Systems without a battery are considered to always be on AC power, the same way as in UPower
+fn read_sysfs(base_path: &Path) -> Option<PowerSource> {
+    let mut has_external_supply = false;
+    let mut external_online = false;
+    let mut has_battery = false;
+    let mut discharging = false;
+
+    for entry in fs::read_dir(base_path).ok()?.flatten() {
+        let path = entry.path();
+        let Ok(supply_type) = fs::read_to_string(path.join("type")) else {
+            continue;
+        };
+
+        match supply_type.trim() {
+            "Mains" | "USB" => {
+                if let Ok(online) = fs::read_to_string(path.join("online")) {
+                    has_external_supply = true;
+                    external_online |= online.trim() == "1";
+                }
+            }
+            "Battery" => {
+                // Batteries of peripherals such as mice and headsets
+                if fs::read_to_string(path.join("scope"))
+                    .is_ok_and(|scope| scope.trim() == "Device")
+                {
+                    continue;
+                }
+
+                has_battery = true;
+                discharging |= fs::read_to_string(path.join("status"))
+                    .is_ok_and(|status| status.trim() == "Discharging");
+            }
+            _ => (),
+        }
+    }
+
+    let on_battery = if !has_battery {
+        false
+    } else if has_external_supply {
+        !external_online
+    } else {
+        discharging
+    };
+
+    Some(from_on_battery(on_battery))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_sysfs;
+    use lact_schema::PowerSource;
+    use std::{fs, path::Path};
+
+    fn add_supply(base: &Path, name: &str, files: &[(&str, &str)]) {
+        let path = base.join(name);
+        fs::create_dir(&path).unwrap();
+        for (file, contents) in files {
+            fs::write(path.join(file), format!("{contents}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn laptop_power_source() {
+        let dir = tempfile::tempdir().unwrap();
+        add_supply(
+            dir.path(),
+            "BAT0",
+            &[("type", "Battery"), ("status", "Charging")],
+        );
+        add_supply(dir.path(), "AC", &[("type", "Mains"), ("online", "1")]);
+        add_supply(
+            dir.path(),
+            "ucsi-source-psy-USBC000:001",
+            &[("type", "USB"), ("online", "0")],
+        );
+        assert_eq!(Some(PowerSource::Ac), read_sysfs(dir.path()));
+
+        fs::write(dir.path().join("AC/online"), "0\n").unwrap();
+        assert_eq!(Some(PowerSource::Battery), read_sysfs(dir.path()));
+
+        fs::write(dir.path().join("ucsi-source-psy-USBC000:001/online"), "1\n").unwrap();
+        assert_eq!(Some(PowerSource::Ac), read_sysfs(dir.path()));
+    }
+
+    #[test]
+    fn battery_status_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        add_supply(
+            dir.path(),
+            "BAT1",
+            &[("type", "Battery"), ("status", "Discharging")],
+        );
+        assert_eq!(Some(PowerSource::Battery), read_sysfs(dir.path()));
+    }
+
+    #[test]
+    fn desktop_power_source() {
+        let dir = tempfile::tempdir().unwrap();
+        add_supply(
+            dir.path(),
+            "hidpp_battery_0",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("status", "Discharging"),
+            ],
+        );
+        assert_eq!(Some(PowerSource::Ac), read_sysfs(dir.path()));
+        assert_eq!(None, read_sysfs(&dir.path().join("missing")));
+    }
+}
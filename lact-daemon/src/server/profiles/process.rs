@@ -1,9 +1,9 @@
 use super::ProfileWatcherEvent;
-use lact_schema::{ProfileProcessInfo, ProfileWatcherState};
+use lact_schema::{ProcessEnvironment, ProfileProcessInfo, ProfileWatcherState};
 use libcopes::{ProcessEventsConnector, PID};
 use std::fs;
 use tokio::sync::mpsc;
-use tracing::{debug, error};
+use tracing::{debug, error, trace};
 
 pub fn load_full_process_list(state: &mut ProfileWatcherState) {
     let process_entries = fs::read_dir("/proc")
@@ -69,11 +69,123 @@ pub fn get_pid_info(pid: PID) -> std::io::Result<ProfileProcessInfo> {
         .to_string()
         .into();
 
+    // The environment is only read when it is needed by a rule, see `read_environment`
     Ok(ProfileProcessInfo {
         name,
         cmdline: cmdline
             .to_string()
             .trim_matches(|c| c == '[' || c == ']')
             .into(),
+        ppid: read_ppid(pid),
+        cgroup: read_cgroup(pid),
+        environment: ProcessEnvironment::default(),
     })
 }
+
+fn read_ppid(pid: PID) -> i32 {
+    match fs::read_to_string(format!("/proc/{pid}/stat")) {
+        Ok(data) => parse_ppid(&data).unwrap_or_default(),
+        Err(err) => {
+            trace!("could not read stat of process {pid}: {err}");
+            0
+        }
+    }
+}
+
+/// The process name can contain spaces and parentheses, so the fields are counted from the last `)`
+fn parse_ppid(stat: &str) -> Option<i32> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Processes which exited or are not in any cgroup get an empty path
+fn read_cgroup(pid: PID) -> Box<str> {
+    match fs::read_to_string(format!("/proc/{pid}/cgroup")) {
+        Ok(data) => parse_cgroup(&data).into(),
+        Err(err) => {
+            trace!("could not read cgroup of process {pid}: {err}");
+            Box::default()
+        }
+    }
+}
+
+/// Uses the unified hierarchy, or the systemd one on hybrid cgroup v1 systems
+fn parse_cgroup(data: &str) -> &str {
+    let mut systemd_path = "";
+    for line in data.lines() {
+        let mut parts = line.splitn(3, ':');
+        let (Some(id), Some(controllers), Some(path)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+
+        if id == "0" && controllers.is_empty() && path != "/" {
+            return path;
+        }
+        if controllers == "name=systemd" {
+            systemd_path = path;
+        }
+    }
+    systemd_path
+}
+
+pub fn read_environment(pid: i32) -> Box<[(Box<str>, Box<str>)]> {
+    match fs::read(format!("/proc/{pid}/environ")) {
+        Ok(data) => parse_environment(&data),
+        Err(err) => {
+            trace!("could not read environment of process {pid}: {err}");
+            Box::default()
+        }
+    }
+}
+
+fn parse_environment(data: &[u8]) -> Box<[(Box<str>, Box<str>)]> {
+    data.split(|byte| *byte == 0)
+        .filter_map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            let (name, value) = entry.split_once('=')?;
+            Some((name.into(), value.into()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cgroup, parse_environment, parse_ppid};
+
+    #[test]
+    fn parse_environ() {
+        let env = parse_environment(b"HOME=/home/user\0SteamAppId=1091500\0EMPTY=\0INVALID\0");
+        let expected: Vec<(Box<str>, Box<str>)> = vec![
+            ("HOME".into(), "/home/user".into()),
+            ("SteamAppId".into(), "1091500".into()),
+            ("EMPTY".into(), "".into()),
+        ];
+        assert_eq!(expected, env.into_vec());
+    }
+
+    #[test]
+    fn parse_cgroup_path() {
+        assert_eq!(
+            "/render-farm.slice/job-1.service",
+            parse_cgroup("0::/render-farm.slice/job-1.service\n")
+        );
+
+        let hybrid =
+            "12:cpu,cpuacct:/\n1:name=systemd:/user.slice/user-1000.slice/session-2.scope\n0::/\n";
+        assert_eq!(
+            "/user.slice/user-1000.slice/session-2.scope",
+            parse_cgroup(hybrid)
+        );
+        assert_eq!("", parse_cgroup(""));
+    }
+
+    #[test]
+    fn parse_stat_ppid() {
+        assert_eq!(
+            Some(1000),
+            parse_ppid("1234 (my (weird) name) S 1000 1234 1234 0 -1 4194560")
+        );
+        assert_eq!(None, parse_ppid("1234 (name"));
+    }
+}
@@ -0,0 +1,358 @@
+use anyhow::{anyhow, Context};
+use indexmap::IndexMap;
+use lact_schema::{
+    config::Profile, CgroupMatchKind, CgroupProfileRule, EnvironmentProfileRule, MatchMode,
+    ProcessProfileRule, ProfileRule,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+use tracing::error;
+
+/// Glob and regex patterns of the profile rules, compiled when the rules are loaded or changed
+/// so that evaluating the rules only needs to look them up
+#[derive(Default, Debug)]
+pub struct CompiledRules {
+    globs: HashMap<Box<str>, glob::Pattern>,
+    regexes: HashMap<Box<str>, regex::Regex>,
+    /// Profiles with invalid patterns in their rule, which are never selected automatically
+    invalid_profiles: HashSet<Rc<str>>,
+}
+
+impl CompiledRules {
+    /// Invalid rules are logged and only disable the profile they belong to
+    pub fn new(profiles: &IndexMap<Rc<str>, Profile>) -> Self {
+        let mut rules = Self::default();
+        for (name, profile) in profiles {
+            if let Some(rule) = &profile.rule {
+                if let Err(err) = rules.add_rule(rule) {
+                    error!("disabling the rule of profile '{name}': {err:#}");
+                    rules.invalid_profiles.insert(name.clone());
+                }
+            }
+        }
+        rules
+    }
+
+    /// Compiles a single rule, failing if any of its patterns are invalid
+    pub fn compile(rule: &ProfileRule) -> anyhow::Result<Self> {
+        let mut rules = Self::default();
+        rules.add_rule(rule)?;
+        Ok(rules)
+    }
+
+    pub fn is_disabled(&self, profile_name: &str) -> bool {
+        self.invalid_profiles.contains(profile_name)
+    }
+
+    fn add_rule(&mut self, rule: &ProfileRule) -> anyhow::Result<()> {
+        match rule {
+            ProfileRule::Process(rule) | ProfileRule::Gamemode(Some(rule)) => {
+                self.add_process_rule(rule)
+            }
+            ProfileRule::And(rules) | ProfileRule::Or(rules) => {
+                rules.iter().try_for_each(|rule| self.add_rule(rule))
+            }
+            ProfileRule::Not(rule) => self.add_rule(rule),
+            ProfileRule::Environment(rule) => match &rule.value {
+                Some(value) => self
+                    .add_pattern(value, rule.value_match.unwrap_or(MatchMode::Exact))
+                    .with_context(|| {
+                        format!("Invalid value pattern '{value}' for variable {}", rule.name)
+                    }),
+                None => Ok(()),
+            },
+            ProfileRule::Cgroup(rule) => self
+                .add_pattern(&rule.value, rule.value_match.unwrap_or(MatchMode::Exact))
+                .with_context(|| format!("Invalid cgroup pattern '{}'", rule.value)),
+            ProfileRule::Ancestor(rule) => {
+                self.add_process_rule(&rule.ancestor)?;
+                rule.process
+                    .as_ref()
+                    .map_or(Ok(()), |rule| self.add_process_rule(rule))
+            }
+            ProfileRule::GpuUsage(rule) => rule
+                .process
+                .as_ref()
+                .map_or(Ok(()), |rule| self.add_process_rule(rule)),
+            ProfileRule::Gamemode(None)
+            | ProfileRule::Schedule(_)
+            | ProfileRule::PowerSource(_)
+            | ProfileRule::Sensor(_) => Ok(()),
+        }
+    }
+
+    fn add_process_rule(&mut self, rule: &ProcessProfileRule) -> anyhow::Result<()> {
+        self.add_pattern(&rule.name, rule.name_match.unwrap_or(MatchMode::Exact))
+            .with_context(|| format!("Invalid process name pattern '{}'", rule.name))?;
+
+        if let Some(args) = &rule.args {
+            self.add_pattern(args, rule.args_match.unwrap_or(MatchMode::Contains))
+                .with_context(|| format!("Invalid arguments pattern '{args}'"))?;
+        }
+        Ok(())
+    }
+
+    fn add_pattern(&mut self, pattern: &str, mode: MatchMode) -> anyhow::Result<()> {
+        match mode {
+            MatchMode::Exact | MatchMode::Contains => (),
+            MatchMode::Glob => {
+                if !self.globs.contains_key(pattern) {
+                    let glob = glob::Pattern::new(pattern).map_err(|err| anyhow!("{err}"))?;
+                    self.globs.insert(pattern.into(), glob);
+                }
+            }
+            MatchMode::Regex => {
+                if !self.regexes.contains_key(pattern) {
+                    let regex = regex::Regex::new(pattern).map_err(|err| anyhow!("{err}"))?;
+                    self.regexes.insert(pattern.into(), regex);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn name_matches(&self, rule: &ProcessProfileRule, name: &str) -> bool {
+        let mode = rule.name_match.unwrap_or(MatchMode::Exact);
+        self.matches_pattern(&rule.name, mode, name)
+    }
+
+    /// Always matches when there is no arguments filter
+    pub fn args_match(&self, rule: &ProcessProfileRule, cmdline: &str) -> bool {
+        match &rule.args {
+            Some(args) => {
+                let mode = rule.args_match.unwrap_or(MatchMode::Contains);
+                self.matches_pattern(args, mode, cmdline)
+            }
+            None => true,
+        }
+    }
+
+    pub fn environment_matches(
+        &self,
+        rule: &EnvironmentProfileRule,
+        environment: &[(Box<str>, Box<str>)],
+    ) -> bool {
+        environment
+            .iter()
+            .filter(|(name, _)| **name == *rule.name)
+            .any(|(_, value)| match &rule.value {
+                Some(expected) => {
+                    let mode = rule.value_match.unwrap_or(MatchMode::Exact);
+                    self.matches_pattern(expected, mode, value)
+                }
+                None => true,
+            })
+    }
+
+    pub fn cgroup_matches(&self, rule: &CgroupProfileRule, cgroup: &str) -> bool {
+        let mode = rule.value_match.unwrap_or(MatchMode::Exact);
+        let value_matches = |value: &str| self.matches_pattern(&rule.value, mode, value);
+
+        let mut components = cgroup.split('/').filter(|component| !component.is_empty());
+        match rule.kind {
+            CgroupMatchKind::Path => value_matches(cgroup),
+            CgroupMatchKind::Unit => components.any(|component| {
+                (component.ends_with(".service") || component.ends_with(".scope"))
+                    && value_matches(component)
+            }),
+            CgroupMatchKind::Slice => components
+                .any(|component| component.ends_with(".slice") && value_matches(component)),
+            CgroupMatchKind::Flatpak => components.filter_map(flatpak_app_id).any(value_matches),
+        }
+    }
+
+    /// Patterns which were not compiled (because they are invalid) never match
+    fn matches_pattern(&self, pattern: &str, mode: MatchMode, value: &str) -> bool {
+        match mode {
+            MatchMode::Exact => value == pattern,
+            MatchMode::Contains => value.contains(pattern),
+            MatchMode::Glob => self
+                .globs
+                .get(pattern)
+                .is_some_and(|glob| glob.matches(value)),
+            MatchMode::Regex => self
+                .regexes
+                .get(pattern)
+                .is_some_and(|regex| regex.is_match(value)),
+        }
+    }
+}
+
+/// Flatpak runs applications in scopes named `app-flatpak-<app id>-<number>.scope`
+fn flatpak_app_id(component: &str) -> Option<&str> {
+    let (app_id, _) = component
+        .strip_prefix("app-flatpak-")?
+        .strip_suffix(".scope")?
+        .rsplit_once('-')?;
+    Some(app_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CompiledRules;
+    use crate::config::Config;
+    use lact_schema::{
+        AncestorProfileRule, CgroupMatchKind, CgroupProfileRule, EnvironmentProfileRule, MatchMode,
+        ProcessProfileRule, ProfileRule,
+    };
+
+    #[test]
+    fn process_match_modes() {
+        let rule = ProcessProfileRule {
+            name: "*.exe".into(),
+            args: Some("^--profile \\w+$".to_owned()),
+            name_match: Some(MatchMode::Glob),
+            args_match: Some(MatchMode::Regex),
+        };
+        let rules = CompiledRules::compile(&ProfileRule::Process(rule.clone())).unwrap();
+
+        assert!(rules.name_matches(&rule, "Cyberpunk2077.exe"));
+        assert!(!rules.name_matches(&rule, "steam"));
+        assert!(rules.args_match(&rule, "--profile quiet"));
+        assert!(!rules.args_match(&rule, "--profile quiet --fullscreen"));
+
+        let invalid_rule = ProfileRule::Or(vec![ProfileRule::Process(ProcessProfileRule {
+            name: "game[".into(),
+            name_match: Some(MatchMode::Regex),
+            ..Default::default()
+        })]);
+        let err = CompiledRules::compile(&invalid_rule).unwrap_err();
+        assert!(format!("{err:#}").starts_with("Invalid process name pattern 'game['"));
+
+        let invalid_ancestor_rule = ProfileRule::Ancestor(AncestorProfileRule {
+            ancestor: ProcessProfileRule {
+                name: "(".into(),
+                name_match: Some(MatchMode::Regex),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        assert!(CompiledRules::compile(&invalid_ancestor_rule).is_err());
+    }
+
+    #[test]
+    fn environment_rule() {
+        let env = |value: &str| -> Vec<(Box<str>, Box<str>)> {
+            vec![
+                ("HOME".into(), "/home/user".into()),
+                ("SteamAppId".into(), value.into()),
+            ]
+        };
+        let rules = CompiledRules::default();
+
+        let rule = EnvironmentProfileRule {
+            name: "SteamAppId".to_owned(),
+            value: Some("1091500".to_owned()),
+            value_match: None,
+        };
+        assert!(rules.environment_matches(&rule, &env("1091500")));
+        assert!(!rules.environment_matches(&rule, &env("10915000")));
+        assert!(!rules.environment_matches(&rule, &[]));
+
+        let any_value_rule = EnvironmentProfileRule {
+            name: "SteamAppId".to_owned(),
+            ..Default::default()
+        };
+        assert!(rules.environment_matches(&any_value_rule, &env("")));
+
+        let regex_rule = EnvironmentProfileRule {
+            name: "SteamAppId".to_owned(),
+            value: Some("^10915".to_owned()),
+            value_match: Some(MatchMode::Regex),
+        };
+        let rules = CompiledRules::compile(&ProfileRule::Environment(regex_rule.clone())).unwrap();
+        assert!(rules.environment_matches(&regex_rule, &env("1091500")));
+
+        let invalid_rule = ProfileRule::Environment(EnvironmentProfileRule {
+            name: "SteamAppId".to_owned(),
+            value: Some("(".to_owned()),
+            value_match: Some(MatchMode::Regex),
+        });
+        assert!(CompiledRules::compile(&invalid_rule).is_err());
+    }
+
+    #[test]
+    fn cgroup_rule() {
+        let slice_rule = CgroupProfileRule {
+            kind: CgroupMatchKind::Slice,
+            value: "render-farm.slice".to_owned(),
+            value_match: None,
+        };
+        let unit_rule = CgroupProfileRule {
+            kind: CgroupMatchKind::Unit,
+            value: "job-*.service".to_owned(),
+            value_match: Some(MatchMode::Glob),
+        };
+        let flatpak_rule = CgroupProfileRule {
+            kind: CgroupMatchKind::Flatpak,
+            value: "org.blender.Blender".to_owned(),
+            ..Default::default()
+        };
+        let path_rule = CgroupProfileRule {
+            value: "/system.slice/render.service".to_owned(),
+            ..Default::default()
+        };
+        let rules = CompiledRules::compile(&ProfileRule::Or(
+            [&slice_rule, &unit_rule, &flatpak_rule, &path_rule]
+                .into_iter()
+                .map(|rule| ProfileRule::Cgroup(rule.clone()))
+                .collect(),
+        ))
+        .unwrap();
+
+        assert!(rules.cgroup_matches(
+            &slice_rule,
+            "/render-farm.slice/render-farm-blender.slice/job-1.service"
+        ));
+        assert!(!rules.cgroup_matches(&slice_rule, "/system.slice/render-farm.service"));
+
+        assert!(rules.cgroup_matches(&unit_rule, "/render-farm.slice/job-1.service"));
+        assert!(!rules.cgroup_matches(&unit_rule, "/render-farm.slice/job-1.slice"));
+
+        assert!(rules.cgroup_matches(
+            &flatpak_rule,
+            "/user.slice/user-1000.slice/user@1000.service/app.slice/app-flatpak-org.blender.Blender-4321.scope"
+        ));
+        assert!(!rules.cgroup_matches(
+            &flatpak_rule,
+            "/user.slice/user-1000.slice/user@1000.service/app.slice"
+        ));
+
+        assert!(rules.cgroup_matches(&path_rule, "/system.slice/render.service"));
+        assert!(!rules.cgroup_matches(&path_rule, "/system.slice/render.service/child"));
+    }
+
+    #[test]
+    fn invalid_profile_rule() {
+        let raw_config = r"
+daemon:
+  log_level: info
+profiles:
+  game:
+    rule:
+      type: process
+      filter:
+        name: wine(
+        name_match: regex
+  desktop:
+    rule:
+      type: process
+      filter:
+        name: '*.exe'
+        name_match: glob
+";
+        let mut config: Config = serde_yml::from_str(raw_config).unwrap();
+        let rules = CompiledRules::new(&config.profiles);
+        assert!(rules.is_disabled("game"));
+        assert!(!rules.is_disabled("desktop"));
+
+        let Some(ProfileRule::Process(rule)) = &mut config.profiles["game"].rule else {
+            panic!("unexpected rule type");
+        };
+        rule.name_match = Some(MatchMode::Glob);
+        let rules = CompiledRules::new(&config.profiles);
+        assert!(!rules.is_disabled("game"));
+    }
+}
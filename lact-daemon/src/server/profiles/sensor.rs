@@ -0,0 +1,216 @@
+use super::visit_rules;
+use crate::server::handler::Handler;
+use lact_schema::{DeviceStats, ProfileRule, ProfileSensor, SensorProfileRule, ThresholdDirection};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps track of how long sensor values have been past their thresholds
+#[derive(Default)]
+pub struct SensorRuleTracker {
+    rules: Vec<TrackedRule>,
+}
+
+struct TrackedRule {
+    rule: SensorProfileRule,
+    active: bool,
+    /// When the value first went past the threshold, while the rule is not yet active
+    exceeded_since: Option<Instant>,
+}
+
+impl SensorRuleTracker {
+    pub async fn sample(&mut self, handler: &Handler) {
+        let rules = collect_sensor_rules(handler).await;
+
+        // Rules that are still configured keep their state
+        let mut old_rules = std::mem::take(&mut self.rules);
+        self.rules = rules
+            .into_iter()
+            .map(
+                |rule| match old_rules.iter().position(|tracked| tracked.rule == rule) {
+                    Some(i) => old_rules.swap_remove(i),
+                    None => TrackedRule {
+                        rule,
+                        active: false,
+                        exceeded_since: None,
+                    },
+                },
+            )
+            .collect();
+
+        let mut gpu_stats: HashMap<String, Option<DeviceStats>> = HashMap::new();
+        let now = Instant::now();
+
+        for tracked in &mut self.rules {
+            let gpu_id = &tracked.rule.gpu_id;
+            if !gpu_stats.contains_key(gpu_id) {
+                let stats = handler
+                    .get_gpu_stats(gpu_id)
+                    .await
+                    .map_err(|err| debug!("could not get stats for sensor rule: {err:#}"))
+                    .ok();
+                gpu_stats.insert(gpu_id.clone(), stats);
+            }
+
+            let value = gpu_stats[gpu_id]
+                .as_ref()
+                .and_then(|stats| sensor_value(&tracked.rule.sensor, stats));
+            tracked.update(value, now);
+        }
+    }
+
+    pub fn active_rules(&self) -> Vec<SensorProfileRule> {
+        self.rules
+            .iter()
+            .filter(|tracked| tracked.active)
+            .map(|tracked| tracked.rule.clone())
+            .collect()
+    }
+}
+
+impl TrackedRule {
+    fn update(&mut self, value: Option<f64>, now: Instant) {
+        let rule = &self.rule;
+        let Some(value) = value else {
+            self.active = false;
+            self.exceeded_since = None;
+            return;
+        };
+
+        if self.active {
+            let released = match rule.direction {
+                ThresholdDirection::Above => value < rule.threshold - rule.hysteresis,
+                ThresholdDirection::Below => value > rule.threshold + rule.hysteresis,
+            };
+            if released {
+                self.active = false;
+            }
+        } else {
+            let exceeded = match rule.direction {
+                ThresholdDirection::Above => value > rule.threshold,
+                ThresholdDirection::Below => value < rule.threshold,
+            };
+
+            if exceeded {
+                let since = *self.exceeded_since.get_or_insert(now);
+                if now.duration_since(since) >= Duration::from_secs(rule.dwell_secs) {
+                    self.active = true;
+                    self.exceeded_since = None;
+                }
+            } else {
+                self.exceeded_since = None;
+            }
+        }
+    }
+}
+
+pub async fn has_sensor_rules(handler: &Handler) -> bool {
+    !collect_sensor_rules(handler).await.is_empty()
+}
+
+async fn collect_sensor_rules(handler: &Handler) -> Vec<SensorProfileRule> {
+    let config = handler.config.read().await;
+
+    let mut rules = Vec::new();
+    for profile in config.profiles.values() {
+        if let Some(rule) = &profile.rule {
+            visit_rules(rule, &mut |rule| {
+                if let ProfileRule::Sensor(sensor_rule) = rule {
+                    if !rules.contains(sensor_rule) {
+                        rules.push(sensor_rule.clone());
+                    }
+                }
+            });
+        }
+    }
+    rules
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn sensor_value(sensor: &ProfileSensor, stats: &DeviceStats) -> Option<f64> {
+    match sensor {
+        ProfileSensor::Temperature(name) => stats.temps.get(name)?.current.map(f64::from),
+        ProfileSensor::BusyPercent => stats.busy_percent.map(f64::from),
+        ProfileSensor::PowerAverage => stats.power.average,
+        ProfileSensor::PowerCurrent => stats.power.current,
+        ProfileSensor::FanSpeed => stats.fan.speed_current.map(f64::from),
+        ProfileSensor::FanPwm => stats.fan.pwm_current.map(f64::from),
+        ProfileSensor::GpuClockspeed => stats.clockspeed.gpu_clockspeed.map(|value| value as f64),
+        ProfileSensor::VramClockspeed => stats.clockspeed.vram_clockspeed.map(|value| value as f64),
+        ProfileSensor::GpuVoltage => stats.voltage.gpu.map(|value| value as f64),
+        ProfileSensor::VramUsed => stats.vram.used.map(|value| value as f64 / 1024.0 / 1024.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrackedRule;
+    use lact_schema::{ProfileSensor, SensorProfileRule, ThresholdDirection};
+    use std::time::{Duration, Instant};
+
+    fn tracked(direction: ThresholdDirection) -> TrackedRule {
+        TrackedRule {
+            rule: SensorProfileRule {
+                gpu_id: String::new(),
+                sensor: ProfileSensor::Temperature("junction".to_owned()),
+                threshold: 90.0,
+                direction,
+                hysteresis: 10.0,
+                dwell_secs: 30,
+            },
+            active: false,
+            exceeded_since: None,
+        }
+    }
+
+    #[test]
+    fn sensor_dwell_and_hysteresis() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut rule = tracked(ThresholdDirection::Above);
+
+        rule.update(Some(95.0), at(0));
+        rule.update(Some(95.0), at(20));
+        assert!(!rule.active);
+
+        // Dropping below the threshold resets the dwell timer
+        rule.update(Some(89.0), at(21));
+        rule.update(Some(95.0), at(22));
+        rule.update(Some(95.0), at(40));
+        assert!(!rule.active);
+
+        rule.update(Some(91.0), at(52));
+        assert!(rule.active);
+
+        rule.update(Some(85.0), at(60));
+        assert!(rule.active);
+
+        rule.update(Some(79.0), at(70));
+        assert!(!rule.active);
+    }
+
+    #[test]
+    fn sensor_below_threshold() {
+        let start = Instant::now();
+        let mut rule = tracked(ThresholdDirection::Below);
+        rule.rule.dwell_secs = 0;
+
+        rule.update(Some(89.0), start);
+        assert!(rule.active);
+
+        rule.update(Some(95.0), start);
+        assert!(rule.active);
+
+        rule.update(Some(101.0), start);
+        assert!(!rule.active);
+
+        rule.update(Some(50.0), start);
+        assert!(rule.active);
+        rule.update(None, start);
+        assert!(!rule.active);
+    }
+}
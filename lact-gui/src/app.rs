@@ -28,7 +28,9 @@ use gtk::{
     MessageType, ResponseType,
 };
 use header::{
-    profile_rule_window::{profile_row::ProfileRuleRowMsg, ProfileRuleWindowMsg},
+    profile_rule_window::{
+        format_selection_explanation, profile_row::ProfileRuleRowMsg, ProfileRuleWindowMsg,
+    },
     Header, HeaderMsg,
 };
 use lact_client::{ConnectionStatusMsg, DaemonClient};
@@ -489,6 +491,27 @@ impl AppModel {
             AppMsg::DebugSnapshot => {
                 self.generate_debug_snapshot(root).await;
             }
+            AppMsg::ExplainProfileSelection => {
+                let explanation = self
+                    .daemon_client
+                    .explain_profile_selection()
+                    .await
+                    .context("Could not evaluate profile rules")?;
+
+                let diag = MessageDialog::builder()
+                    .title("Profile selection")
+                    .message_type(MessageType::Info)
+                    .text("Profile rule evaluation")
+                    .secondary_use_markup(true)
+                    .secondary_text(format_selection_explanation(&explanation))
+                    .buttons(ButtonsType::Ok)
+                    .transient_for(root)
+                    .build();
+
+                diag.run_async(|diag, _| {
+                    diag.hide();
+                });
+            }
             AppMsg::EnableOverdrive => {
                 toggle_overdrive(&self.daemon_client, true, root.clone()).await;
             }
@@ -540,9 +563,15 @@ impl AppModel {
                     }
                 }
             }
-            AppMsg::SetProfileRule { name, rule, hooks } => {
+            AppMsg::SetProfileRule {
+                name,
+                rule,
+                hooks,
+                timing,
+                gpu_ids,
+            } => {
                 self.daemon_client
-                    .set_profile_rule(name, rule, hooks)
+                    .set_profile_rule(name, rule, Some(hooks), Some(timing), Some(gpu_ids))
                     .await?;
                 self.reload_profiles(None).await?;
             }
@@ -1193,6 +1222,7 @@ fn register_actions(sender: &AsyncComponentSender<AppModel>) {
         (ShowProcessMonitor, AppMsg::ShowProcessMonitor),
         (DumpVBios, AppMsg::DumpVBios),
         (DebugSnapshot, AppMsg::DebugSnapshot),
+        (ExplainProfileSelection, AppMsg::ExplainProfileSelection),
         (
             DisableOverdrive,
             AppMsg::ask_confirmation(
@@ -1221,6 +1251,11 @@ relm4::new_stateless_action!(ShowGraphsWindow, AppActionGroup, "show-graphs-wind
 relm4::new_stateless_action!(ShowProcessMonitor, AppActionGroup, "show-process-monitor");
 relm4::new_stateless_action!(DumpVBios, AppActionGroup, "dump-vbios");
 relm4::new_stateless_action!(DebugSnapshot, AppActionGroup, "generate-debug-snapshot");
+relm4::new_stateless_action!(
+    ExplainProfileSelection,
+    AppActionGroup,
+    "explain-profile-selection"
+);
 relm4::new_stateless_action!(DisableOverdrive, AppActionGroup, "disable-overdrive");
 relm4::new_stateless_action!(ResetConfig, AppActionGroup, "reset-config");
 
@@ -8,7 +8,10 @@ use crate::{
     CONFIG,
 };
 
-use super::{AppMsg, DebugSnapshot, DisableOverdrive, DumpVBios, ResetConfig, ShowGraphsWindow};
+use super::{
+    AppMsg, DebugSnapshot, DisableOverdrive, DumpVBios, ExplainProfileSelection, ResetConfig,
+    ShowGraphsWindow,
+};
 use glib::clone;
 use gtk::prelude::*;
 use gtk::*;
@@ -155,6 +158,7 @@ impl Component for Header {
             section! {
                 "Show historical charts" => ShowGraphsWindow,
                 "Show process monitor" => ShowProcessMonitor,
+                "Explain profile selection" => ExplainProfileSelection,
             },
             section! {
                 "Generate debug snapshot" => DebugSnapshot,
@@ -357,6 +361,8 @@ impl Component for Header {
                     name,
                     rule,
                     hooks,
+                    timing,
+                    gpu_ids,
                     auto,
                     ..
                 } = &profile.row
@@ -365,18 +371,24 @@ impl Component for Header {
                         name: name.clone(),
                         rule: rule.clone().unwrap_or_default(),
                         hooks: hooks.clone(),
+                        timing: *timing,
+                        gpu_ids: gpu_ids.clone(),
                         auto_switch: *auto,
                         root_window: root.toplevel_window().expect("Widget not in a window"),
                     };
                     let rule_window = ProfileRuleWindow::builder().launch(params).into_stream();
 
                     sender.clone().oneshot_command(async move {
-                        if let Some((name, rule, hooks)) = rule_window.recv_one().await {
+                        if let Some((name, rule, hooks, timing, gpu_ids)) =
+                            rule_window.recv_one().await
+                        {
                             sender
                                 .output(AppMsg::SetProfileRule {
                                     name,
                                     rule: Some(rule),
                                     hooks,
+                                    timing,
+                                    gpu_ids,
                                 })
                                 .unwrap();
                         }
@@ -409,6 +421,16 @@ impl Header {
                 .get(name)
                 .cloned()
                 .unwrap_or_default();
+            let timing = profiles_info
+                .profile_timing
+                .get(name)
+                .copied()
+                .unwrap_or_default();
+            let gpu_ids = profiles_info
+                .profile_gpu_ids
+                .get(name)
+                .cloned()
+                .unwrap_or_default();
 
             let profile = ProfileRowType::Profile {
                 name: name.to_string(),
@@ -417,6 +439,8 @@ impl Header {
                 auto: profiles_info.auto_switch,
                 rule: rule.clone(),
                 hooks,
+                timing,
+                gpu_ids,
             };
             profiles.push_back(profile);
         }
@@ -494,7 +518,14 @@ impl Header {
 
     fn update_label(&mut self) {
         let gpu_index = self.gpu_selector.selection_model.selected();
-        let profile = self.selected_profile().unwrap_or("Default");
+        let selected_profile = self.selected_profile().unwrap_or("Default");
+
+        // The selected GPU may have a profile of its own activated
+        let gpu_profile = self
+            .selected_gpu_id()
+            .and_then(|id| self.profiles_info.gpu_profiles.get(&id).cloned())
+            .map(|profile| profile.unwrap_or_else(|| "Default".to_owned()));
+        let profile = gpu_profile.as_deref().unwrap_or(selected_profile);
 
         self.selector_label = format!("GPU {gpu_index} | {profile}");
     }
@@ -1,7 +1,10 @@
 use super::HeaderMsg;
 use crate::app::{msg::AppMsg, APP_BROKER};
 use gtk::{pango, prelude::*};
-use lact_schema::{config::ProfileHooks, ProfileRule};
+use lact_schema::{
+    config::{ProfileHooks, ProfileTiming},
+    ProfileRule,
+};
 use relm4::{
     css,
     factory::{DynamicIndex, FactoryComponent},
@@ -22,6 +25,8 @@ pub enum ProfileRowType {
         auto: bool,
         rule: Option<ProfileRule>,
         hooks: ProfileHooks,
+        timing: ProfileTiming,
+        gpu_ids: Vec<String>,
     },
 }
 
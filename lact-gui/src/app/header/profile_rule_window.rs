@@ -2,21 +2,25 @@ pub mod profile_row;
 
 use crate::app::{msg::AppMsg, APP_BROKER};
 use gtk::{
+    glib::markup_escape_text,
     pango,
     prelude::{
         BoxExt, ButtonExt, CheckButtonExt, DialogExt, DialogExtManual, EntryBufferExtManual,
         EntryExt, GtkWindowExt, OrientableExt, WidgetExt,
     },
 };
-use lact_schema::{config::ProfileHooks, ProfileRule};
-use profile_row::ProfileRuleRow;
+use lact_schema::{
+    config::{ProfileHooks, ProfileTiming},
+    ProfileRule, ProfileSelectionExplanation, RuleTrace,
+};
+use profile_row::{format_rule, ProfileRuleRow};
 use relm4::{
     binding::BoolBinding,
     prelude::{DynamicIndex, FactoryVecDeque},
     tokio::time::sleep,
     ComponentParts, ComponentSender, RelmObjectExt, RelmWidgetExt,
 };
-use std::time::Duration;
+use std::{fmt::Write, time::Duration};
 
 const EVALUATE_INTERVAL_MS: u64 = 250;
 
@@ -31,12 +35,22 @@ pub struct ProfileRuleWindow {
 
     deactivated_hook_enabled: BoolBinding,
     deactivated_hook: gtk::EntryBuffer,
+
+    hook_user: gtk::EntryBuffer,
+    hook_timeout_secs: gtk::Adjustment,
+
+    min_active_secs: gtk::Adjustment,
+    deactivation_grace_secs: gtk::Adjustment,
+
+    gpu_ids: gtk::EntryBuffer,
 }
 
 pub struct ProfileEditParams {
     pub name: String,
     pub rule: ProfileRule,
     pub hooks: ProfileHooks,
+    pub timing: ProfileTiming,
+    pub gpu_ids: Vec<String>,
     pub auto_switch: bool,
     pub root_window: gtk::Window,
 }
@@ -54,7 +68,13 @@ pub enum ProfileRuleWindowMsg {
 impl relm4::Component for ProfileRuleWindow {
     type Init = ProfileEditParams;
     type Input = ProfileRuleWindowMsg;
-    type Output = (String, ProfileRule, ProfileHooks);
+    type Output = (
+        String,
+        ProfileRule,
+        ProfileHooks,
+        ProfileTiming,
+        Vec<String>,
+    );
     type CommandOutput = ();
 
     view! {
@@ -165,6 +185,43 @@ impl relm4::Component for ProfileRuleWindow {
                         },
 
                         gtk::Separator {},
+
+                        gtk::Grid {
+                            set_margin_all: 10,
+                            set_row_spacing: 5,
+                            set_column_spacing: 5,
+
+                            attach[0, 0, 1, 1] = &gtk::Label {
+                                set_label: "Keep the profile active for at least (seconds):",
+                                set_halign: gtk::Align::Start,
+                                set_hexpand: true,
+                            },
+
+                            attach[1, 0, 1, 1] = &gtk::SpinButton {
+                                set_adjustment: &model.min_active_secs,
+                            },
+
+                            attach[0, 1, 1, 1] = &gtk::Label {
+                                set_label: "Deactivate after the rules are not matched for (seconds):",
+                                set_halign: gtk::Align::Start,
+                                set_hexpand: true,
+                            },
+
+                            attach[1, 1, 1, 1] = &gtk::SpinButton {
+                                set_adjustment: &model.deactivation_grace_secs,
+                            },
+
+                            attach[0, 2, 1, 1] = &gtk::Label {
+                                set_label: "Only activate for GPUs (comma-separated IDs):",
+                                set_halign: gtk::Align::Start,
+                                set_hexpand: true,
+                            },
+
+                            attach[1, 2, 1, 1] = &gtk::Entry {
+                                set_buffer: &model.gpu_ids,
+                                set_placeholder_text: Some("All GPUs"),
+                            },
+                        },
                     },
 
                     add_titled[None, "Hooks"] = &gtk::Box {
@@ -221,6 +278,35 @@ impl relm4::Component for ProfileRuleWindow {
 
                         gtk::Separator {},
 
+                        gtk::Grid {
+                            set_margin_vertical: 5,
+                            set_margin_horizontal: 10,
+                            set_row_spacing: 5,
+                            set_column_spacing: 5,
+
+                            attach[0, 0, 1, 1] = &gtk::Label {
+                                set_label: "Run as user:",
+                                set_halign: gtk::Align::Start,
+                            },
+
+                            attach[1, 0, 1, 1] = &gtk::Entry {
+                                set_buffer: &model.hook_user,
+                                set_placeholder_text: Some("root"),
+                                set_hexpand: true,
+                            },
+
+                            attach[0, 1, 1, 1] = &gtk::Label {
+                                set_label: "Timeout (seconds, 0 for default):",
+                                set_halign: gtk::Align::Start,
+                            },
+
+                            attach[1, 1, 1, 1] = &gtk::SpinButton {
+                                set_adjustment: &model.hook_timeout_secs,
+                            },
+                        },
+
+                        gtk::Separator {},
+
                         gtk::Box {
                             set_orientation: gtk::Orientation::Horizontal,
                             set_spacing: 5,
@@ -232,7 +318,7 @@ impl relm4::Component for ProfileRuleWindow {
                             },
 
                             gtk::Label {
-                                set_label: "Note: these commands are executed as root by the LACT daemon, and do not have access to the desktop environment. As such, they cannot be used directly to launch graphical applications.",
+                                set_label: "Note: these commands are executed by the LACT daemon (as root unless a user is specified), and do not have access to the desktop environment. As such, they cannot be used directly to launch graphical applications. Details about the profile switch are available in the LACT_* environment variables.",
                                 set_wrap: true,
                                 set_wrap_mode: pango::WrapMode::Word,
                                 add_css_class: "caption-heading",
@@ -257,6 +343,8 @@ impl relm4::Component for ProfileRuleWindow {
             name,
             rule,
             hooks,
+            timing,
+            gpu_ids,
             auto_switch,
             root_window,
         } = params;
@@ -296,6 +384,11 @@ impl relm4::Component for ProfileRuleWindow {
             activated_hook: gtk::EntryBuffer::new(hooks.activated),
             deactivated_hook_enabled: BoolBinding::new(hooks.deactivated.is_some()),
             deactivated_hook: gtk::EntryBuffer::new(hooks.deactivated),
+            hook_user: gtk::EntryBuffer::new(hooks.user),
+            hook_timeout_secs: seconds_adjustment(hooks.timeout_secs),
+            min_active_secs: seconds_adjustment(timing.min_active_secs),
+            deactivation_grace_secs: seconds_adjustment(timing.deactivation_grace_secs),
+            gpu_ids: gtk::EntryBuffer::new(Some(gpu_ids.join(", "))),
         };
 
         let sub_rules_listview = model.sub_rules_list_view.widget();
@@ -340,6 +433,8 @@ impl relm4::Component for ProfileRuleWindow {
                         self.profile_name.clone(),
                         self.get_rule(widgets),
                         self.get_hooks(),
+                        self.get_timing(),
+                        self.get_gpu_ids(),
                     ))
                     .unwrap();
             }
@@ -378,6 +473,114 @@ impl ProfileRuleWindow {
             } else {
                 None
             },
+            timeout_secs: adjustment_seconds(&self.hook_timeout_secs),
+            user: Some(self.hook_user.text().trim().to_owned()).filter(|user| !user.is_empty()),
+        }
+    }
+
+    fn get_timing(&self) -> ProfileTiming {
+        ProfileTiming {
+            min_active_secs: adjustment_seconds(&self.min_active_secs),
+            deactivation_grace_secs: adjustment_seconds(&self.deactivation_grace_secs),
+        }
+    }
+
+    fn get_gpu_ids(&self) -> Vec<String> {
+        self.gpu_ids
+            .text()
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+fn adjustment_seconds(adjustment: &gtk::Adjustment) -> Option<u64> {
+    Some(adjustment.value() as u64).filter(|secs| *secs > 0)
+}
+
+/// A value of 0 means the option is not set
+fn seconds_adjustment(value: Option<u64>) -> gtk::Adjustment {
+    gtk::Adjustment::new(value.unwrap_or(0) as f64, 0.0, 86_400.0, 1.0, 10.0, 0.0)
+}
+
+/// Formats the rule evaluation of every profile as an indented tree
+pub fn format_selection_explanation(explanation: &ProfileSelectionExplanation) -> String {
+    let mut text = String::new();
+
+    write_scope_explanation(&mut text, explanation);
+    match &explanation.selected_profile {
+        Some(name) => write!(
+            text,
+            "\nSelected profile: <b>{}</b>",
+            markup_escape_text(name)
+        )
+        .unwrap(),
+        None => write!(text, "\nNo rules matched, using the default profile").unwrap(),
+    }
+
+    for (gpu_id, gpu_explanation) in &explanation.gpus {
+        write!(text, "\n\n<b>GPU {}</b>\n", markup_escape_text(gpu_id)).unwrap();
+        write_scope_explanation(&mut text, gpu_explanation);
+        match &gpu_explanation.selected_profile {
+            Some(name) => write!(
+                text,
+                "\nSelected profile: <b>{}</b>",
+                markup_escape_text(name)
+            )
+            .unwrap(),
+            None => write!(
+                text,
+                "\nNo profile limited to this GPU matched, it follows the current profile"
+            )
+            .unwrap(),
+        }
+    }
+
+    text
+}
+
+fn write_scope_explanation(text: &mut String, explanation: &ProfileSelectionExplanation) {
+    for profile in &explanation.profiles {
+        let name = markup_escape_text(&profile.name);
+        match &profile.trace {
+            Some(trace) => {
+                if explanation.selected_profile.as_ref() == Some(&profile.name) {
+                    writeln!(text, "<b>{name}</b> (selected)").unwrap();
+                } else {
+                    writeln!(text, "<b>{name}</b>").unwrap();
+                }
+                write_rule_trace(text, trace, 1);
+            }
+            None => writeln!(text, "<b>{name}</b>: no rule").unwrap(),
         }
     }
 }
+
+fn write_rule_trace(text: &mut String, trace: &RuleTrace, depth: usize) {
+    let indent = "    ".repeat(depth);
+    let mark = if trace.matched { "✓" } else { "✗" };
+    let description = match &trace.rule {
+        ProfileRule::And(_) => "All of the following rules are matched:".to_owned(),
+        ProfileRule::Or(_) => "Any of the following rules are matched:".to_owned(),
+        ProfileRule::Not(_) => "<b>Not:</b>".to_owned(),
+        rule => format_rule(rule),
+    };
+    write!(text, "{indent}{mark} {description}").unwrap();
+
+    if !trace.pids.is_empty() {
+        let pids = trace
+            .pids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(text, " (PID {pids})").unwrap();
+    }
+    text.push('\n');
+
+    for child in &trace.children {
+        write_rule_trace(text, child, depth + 1);
+    }
+}
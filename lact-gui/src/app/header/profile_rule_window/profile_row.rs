@@ -1,15 +1,20 @@
 use crate::app::{header::profile_rule_window::ProfileRuleWindowMsg, msg::AppMsg, APP_BROKER};
 use gtk::{
-    glib::GString,
+    glib::{markup_escape_text, GString},
     pango,
     prelude::{
         BoxExt, ButtonExt, CheckButtonExt, EditableExt, EntryBufferExt, EntryBufferExtManual,
         EntryExt, GridExt, OrientableExt, PopoverExt, SelectionModelExt, WidgetExt,
     },
 };
-use lact_schema::{ProcessProfileRule, ProfileProcessInfo, ProfileRule, ProfileWatcherState};
+use lact_schema::{
+    AncestorProfileRule, CgroupMatchKind, CgroupProfileRule, EnvironmentProfileRule,
+    GpuUsageProfileRule, MatchMode, PowerSource, ProcessProfileRule, ProcessUtilizationType,
+    ProfileProcessInfo, ProfileRule, ProfileSensor, ProfileWatcherState, ScheduleProfileRule,
+    SensorProfileRule, ThresholdDirection, TimeRange, Weekday,
+};
 use relm4::{
-    binding::{BoolBinding, StringBinding},
+    binding::{BoolBinding, StringBinding, U32Binding},
     typed_view::list::{RelmListItem, TypedListView},
     view, RelmObjectExt, RelmWidgetExt,
 };
@@ -18,11 +23,31 @@ use tracing::debug;
 
 const PROCESS_PAGE: &str = "process";
 const GAMEMODE_PAGE: &str = "gamemode";
+const SCHEDULE_PAGE: &str = "schedule";
+const POWER_SOURCE_PAGE: &str = "power_source";
+const SENSOR_PAGE: &str = "sensor";
+const ENVIRONMENT_PAGE: &str = "environment";
+const CGROUP_PAGE: &str = "cgroup";
+const ANCESTOR_PAGE: &str = "ancestor";
+const GPU_USAGE_PAGE: &str = "gpu_usage";
+
+/// Names of [`MatchMode::ALL`]
+const MATCH_MODE_NAMES: [&str; 4] = ["Exact", "Contains", "Glob", "Regex"];
+/// Names of [`CgroupMatchKind::ALL`]
+/// The first entry means any engine, followed by the names of [`ProcessUtilizationType::ALL`]
+const ENGINE_NAMES: [&str; 6] = ["Any", "Graphics", "Compute", "Memory", "Encode", "Decode"];
+const CGROUP_KIND_NAMES: [&str; 4] = [
+    "Cgroup path",
+    "Systemd unit",
+    "Systemd slice",
+    "Flatpak app ID",
+];
 
 pub struct ProfileRuleRow {
     process_listview: TypedListView<ProcessListItem, gtk::SingleSelection>,
 
     selected_page: StringBinding,
+    negate: BoolBinding,
     filter_by_args: BoolBinding,
     gamemode_filter_by_process: BoolBinding,
 
@@ -30,6 +55,47 @@ pub struct ProfileRuleRow {
 
     process_name_buffer: gtk::EntryBuffer,
     args_buffer: gtk::EntryBuffer,
+    /// Index in `MatchMode::ALL`
+    name_match_mode: U32Binding,
+    args_match_mode: U32Binding,
+
+    schedule_weekdays: [BoolBinding; 7],
+    schedule_start_buffer: gtk::EntryBuffer,
+    schedule_end_buffer: gtk::EntryBuffer,
+    /// Time ranges after the first one, which can only be configured in the config file
+    schedule_extra_times: Vec<TimeRange>,
+
+    power_source_battery: BoolBinding,
+
+    sensor_gpu_id_buffer: gtk::EntryBuffer,
+    sensor_name_buffer: gtk::EntryBuffer,
+    sensor_below: BoolBinding,
+    sensor_threshold: gtk::Adjustment,
+    sensor_hysteresis: gtk::Adjustment,
+    sensor_dwell_secs: gtk::Adjustment,
+
+    env_name_buffer: gtk::EntryBuffer,
+    env_value_buffer: gtk::EntryBuffer,
+    env_value_match_mode: U32Binding,
+
+    /// Index in `CgroupMatchKind::ALL`
+    cgroup_kind: U32Binding,
+    cgroup_value_buffer: gtk::EntryBuffer,
+    cgroup_value_match_mode: U32Binding,
+
+    ancestor_name_buffer: gtk::EntryBuffer,
+    descendant_name_buffer: gtk::EntryBuffer,
+    ancestor_parent_only: BoolBinding,
+    /// The original rule, so that match modes and arguments which can only be configured in the config file are kept
+    ancestor_rule: AncestorProfileRule,
+
+    gpu_usage_gpu_id_buffer: gtk::EntryBuffer,
+    gpu_usage_process_buffer: gtk::EntryBuffer,
+    /// 0 means that using the GPU at all is enough
+    gpu_usage_min_usage: gtk::Adjustment,
+    /// Index in `ENGINE_NAMES`
+    gpu_usage_engine: U32Binding,
+    gpu_usage_rule: GpuUsageProfileRule,
 }
 
 #[derive(Debug)]
@@ -50,6 +116,12 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
 
     view! {
         gtk::Box {
+            gtk::CheckButton {
+                set_label: Some("Not"),
+                set_tooltip: "Match when this rule does not match",
+                add_binding: (&self.negate, "active"),
+            },
+
             gtk::Label {
                 set_hexpand: true,
                 set_halign: gtk::Align::Start,
@@ -127,7 +199,7 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
                                     },
 
                                     attach[0, 1, 1, 1] = &gtk::Label {
-                                        set_label: "Arguments Contain:",
+                                        set_label: "Arguments:",
                                         set_halign: gtk::Align::Start,
                                     },
 
@@ -143,6 +215,25 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
                                         set_sensitive: false,
                                         add_binding: (&self.filter_by_args, "sensitive"),
                                     },
+
+                                    attach[0, 2, 2, 1] = &gtk::Label {
+                                        set_label: "Name Matching:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[2, 2, 2, 1] = &gtk::DropDown::from_strings(&MATCH_MODE_NAMES) {
+                                        add_binding: (&self.name_match_mode, "selected"),
+                                    },
+
+                                    attach[0, 3, 2, 1] = &gtk::Label {
+                                        set_label: "Arguments Matching:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[2, 3, 2, 1] = &gtk::DropDown::from_strings(&MATCH_MODE_NAMES) {
+                                        add_binding: (&self.args_match_mode, "selected"),
+                                        add_binding: (&self.filter_by_args, "sensitive"),
+                                    },
                                 },
 
                                 add_titled[Some(GAMEMODE_PAGE), "Gamemode is active"] = &gtk::Grid {
@@ -168,7 +259,7 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
                                     },
 
                                     attach[0, 1, 1, 1] = &gtk::Label {
-                                        set_label: "Arguments Contain:",
+                                        set_label: "Arguments:",
                                         set_halign: gtk::Align::Start,
                                     },
 
@@ -183,6 +274,331 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
                                         set_sensitive: false,
                                         add_binding: (&self.filter_by_args, "sensitive"),
                                     },
+
+                                    attach[0, 2, 2, 1] = &gtk::Label {
+                                        set_label: "Name Matching:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[2, 2, 1, 1] = &gtk::DropDown::from_strings(&MATCH_MODE_NAMES) {
+                                        add_binding: (&self.name_match_mode, "selected"),
+                                        add_binding: (&self.gamemode_filter_by_process, "sensitive"),
+                                    },
+
+                                    attach[0, 3, 2, 1] = &gtk::Label {
+                                        set_label: "Arguments Matching:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[2, 3, 1, 1] = &gtk::DropDown::from_strings(&MATCH_MODE_NAMES) {
+                                        add_binding: (&self.args_match_mode, "selected"),
+                                        add_binding: (&self.filter_by_args, "sensitive"),
+                                    },
+                                },
+
+                                add_titled[Some(SCHEDULE_PAGE), "On a schedule"] = &gtk::Grid {
+                                    set_row_spacing: 5,
+                                    set_column_spacing: 5,
+
+                                    attach[0, 0, 1, 1] = &gtk::Label {
+                                        set_label: "Days:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 0, 3, 1] = &gtk::Box {
+                                        set_spacing: 5,
+
+                                        gtk::CheckButton {
+                                            set_label: Some("Mon"),
+                                            add_binding: (&self.schedule_weekdays[0], "active"),
+                                        },
+                                        gtk::CheckButton {
+                                            set_label: Some("Tue"),
+                                            add_binding: (&self.schedule_weekdays[1], "active"),
+                                        },
+                                        gtk::CheckButton {
+                                            set_label: Some("Wed"),
+                                            add_binding: (&self.schedule_weekdays[2], "active"),
+                                        },
+                                        gtk::CheckButton {
+                                            set_label: Some("Thu"),
+                                            add_binding: (&self.schedule_weekdays[3], "active"),
+                                        },
+                                        gtk::CheckButton {
+                                            set_label: Some("Fri"),
+                                            add_binding: (&self.schedule_weekdays[4], "active"),
+                                        },
+                                        gtk::CheckButton {
+                                            set_label: Some("Sat"),
+                                            add_binding: (&self.schedule_weekdays[5], "active"),
+                                        },
+                                        gtk::CheckButton {
+                                            set_label: Some("Sun"),
+                                            add_binding: (&self.schedule_weekdays[6], "active"),
+                                        },
+                                    },
+
+                                    attach[0, 1, 1, 1] = &gtk::Label {
+                                        set_label: "From:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 1, 1, 1] = &gtk::Entry {
+                                        set_buffer: &self.schedule_start_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("22:00"),
+                                    },
+
+                                    attach[2, 1, 1, 1] = &gtk::Label {
+                                        set_label: "To:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[3, 1, 1, 1] = &gtk::Entry {
+                                        set_buffer: &self.schedule_end_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("07:00"),
+                                    },
+
+                                    attach[0, 2, 4, 1] = &gtk::Label {
+                                        set_label: "Leave the days unchecked to match every day, and the times empty to match the whole day",
+                                        set_halign: gtk::Align::Start,
+                                        set_wrap: true,
+                                        add_css_class: "dim-label",
+                                    },
+                                },
+
+                                add_titled[Some(POWER_SOURCE_PAGE), "Power source"] = &gtk::Box {
+                                    set_orientation: gtk::Orientation::Vertical,
+                                    set_spacing: 5,
+
+                                    #[name = "power_source_ac_checkbutton"]
+                                    gtk::CheckButton {
+                                        set_label: Some("Running on AC power"),
+                                        set_active: !self.power_source_battery.value(),
+                                    },
+
+                                    gtk::CheckButton {
+                                        set_label: Some("Running on battery"),
+                                        set_group: Some(&power_source_ac_checkbutton),
+                                        add_binding: (&self.power_source_battery, "active"),
+                                    },
+                                },
+
+                                add_titled[Some(SENSOR_PAGE), "A sensor value"] = &gtk::Grid {
+                                    set_row_spacing: 5,
+                                    set_column_spacing: 10,
+
+                                    attach[0, 0, 1, 1] = &gtk::Label {
+                                        set_label: "GPU ID:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 0, 2, 1] = &gtk::Entry {
+                                        set_buffer: &self.sensor_gpu_id_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("1002:744C-1EAE:7905-0000:09:00.0"),
+                                    },
+
+                                    attach[0, 1, 1, 1] = &gtk::Label {
+                                        set_label: "Sensor:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 1, 2, 1] = &gtk::Entry {
+                                        set_buffer: &self.sensor_name_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("temperature.junction"),
+                                    },
+
+                                    attach[0, 2, 1, 1]: sensor_above_checkbutton = &gtk::CheckButton {
+                                        set_label: Some("Above"),
+                                        set_active: !self.sensor_below.value(),
+                                    },
+
+                                    attach[1, 2, 1, 1] = &gtk::CheckButton {
+                                        set_label: Some("Below"),
+                                        set_group: Some(&sensor_above_checkbutton),
+                                        add_binding: (&self.sensor_below, "active"),
+                                    },
+
+                                    attach[2, 2, 1, 1] = &gtk::SpinButton {
+                                        set_adjustment: &self.sensor_threshold,
+                                        set_digits: 1,
+                                    },
+
+                                    attach[0, 3, 2, 1] = &gtk::Label {
+                                        set_label: "Hysteresis:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[2, 3, 1, 1] = &gtk::SpinButton {
+                                        set_adjustment: &self.sensor_hysteresis,
+                                        set_digits: 1,
+                                    },
+
+                                    attach[0, 4, 2, 1] = &gtk::Label {
+                                        set_label: "For at least (seconds):",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[2, 4, 1, 1] = &gtk::SpinButton {
+                                        set_adjustment: &self.sensor_dwell_secs,
+                                    },
+                                },
+
+                                add_titled[Some(ENVIRONMENT_PAGE), "An environment variable"] = &gtk::Grid {
+                                    set_row_spacing: 5,
+                                    set_column_spacing: 10,
+
+                                    attach[0, 0, 1, 1] = &gtk::Label {
+                                        set_label: "Variable:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 0, 1, 1] = &gtk::Entry {
+                                        set_buffer: &self.env_name_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("SteamAppId"),
+                                    },
+
+                                    attach[0, 1, 1, 1] = &gtk::Label {
+                                        set_label: "Value:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 1, 1, 1] = &gtk::Entry {
+                                        set_buffer: &self.env_value_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("Any value"),
+                                    },
+
+                                    attach[0, 2, 1, 1] = &gtk::Label {
+                                        set_label: "Value Matching:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 2, 1, 1] = &gtk::DropDown::from_strings(&MATCH_MODE_NAMES) {
+                                        add_binding: (&self.env_value_match_mode, "selected"),
+                                    },
+                                },
+
+                                add_titled[Some(CGROUP_PAGE), "A cgroup"] = &gtk::Grid {
+                                    set_row_spacing: 5,
+                                    set_column_spacing: 10,
+
+                                    attach[0, 0, 1, 1] = &gtk::Label {
+                                        set_label: "Match on:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 0, 1, 1] = &gtk::DropDown::from_strings(&CGROUP_KIND_NAMES) {
+                                        add_binding: (&self.cgroup_kind, "selected"),
+                                    },
+
+                                    attach[0, 1, 1, 1] = &gtk::Label {
+                                        set_label: "Value:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 1, 1, 1] = &gtk::Entry {
+                                        set_buffer: &self.cgroup_value_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("render-farm.slice"),
+                                    },
+
+                                    attach[0, 2, 1, 1] = &gtk::Label {
+                                        set_label: "Value Matching:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 2, 1, 1] = &gtk::DropDown::from_strings(&MATCH_MODE_NAMES) {
+                                        add_binding: (&self.cgroup_value_match_mode, "selected"),
+                                    },
+                                },
+
+                                add_titled[Some(ANCESTOR_PAGE), "A process started by another process"] = &gtk::Grid {
+                                    set_row_spacing: 5,
+                                    set_column_spacing: 10,
+
+                                    attach[0, 0, 1, 1] = &gtk::Label {
+                                        set_label: "Started by:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 0, 1, 1] = &gtk::Entry {
+                                        set_buffer: &self.ancestor_name_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("steam"),
+                                    },
+
+                                    attach[0, 1, 1, 1] = &gtk::Label {
+                                        set_label: "Process:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 1, 1, 1] = &gtk::Entry {
+                                        set_buffer: &self.descendant_name_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("Any process"),
+                                    },
+
+                                    attach[0, 2, 2, 1] = &gtk::CheckButton {
+                                        set_label: Some("Only direct children"),
+                                        add_binding: (&self.ancestor_parent_only, "active"),
+                                    },
+                                },
+
+                                add_titled[Some(GPU_USAGE_PAGE), "A process is using the GPU"] = &gtk::Grid {
+                                    set_row_spacing: 5,
+                                    set_column_spacing: 10,
+
+                                    attach[0, 0, 1, 1] = &gtk::Label {
+                                        set_label: "GPU ID:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 0, 1, 1] = &gtk::Entry {
+                                        set_buffer: &self.gpu_usage_gpu_id_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("Any GPU"),
+                                    },
+
+                                    attach[0, 1, 1, 1] = &gtk::Label {
+                                        set_label: "Process:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 1, 1, 1] = &gtk::Entry {
+                                        set_buffer: &self.gpu_usage_process_buffer,
+                                        set_hexpand: true,
+                                        set_placeholder_text: Some("Any process"),
+                                    },
+
+                                    attach[0, 2, 1, 1] = &gtk::Label {
+                                        set_label: "Minimum usage (%):",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 2, 1, 1] = &gtk::SpinButton {
+                                        set_adjustment: &self.gpu_usage_min_usage,
+                                    },
+
+                                    attach[0, 3, 1, 1] = &gtk::Label {
+                                        set_label: "Engine:",
+                                        set_halign: gtk::Align::Start,
+                                    },
+
+                                    attach[1, 3, 1, 1] = &gtk::DropDown::from_strings(&ENGINE_NAMES) {
+                                        add_binding: (&self.gpu_usage_engine, "selected"),
+                                    },
+
+                                    attach[0, 4, 2, 1] = &gtk::Label {
+                                        set_label: "With a minimum usage of 0, any process that has used the GPU is matched",
+                                        set_halign: gtk::Align::Start,
+                                        set_wrap: true,
+                                        add_css_class: "dim-label",
+                                    },
                                 },
 
                                 add_binding: (&self.selected_page, "visible-child-name"),
@@ -216,6 +632,12 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
         _index: &Self::Index,
         sender: relm4::FactorySender<Self>,
     ) -> Self {
+        let (rule, negate) = match rule {
+            ProfileRule::Not(rule) => (*rule, true),
+            rule => (rule, false),
+        };
+        let negate = BoolBinding::new(negate);
+
         let process_name_buffer = gtk::EntryBuffer::default();
         let args_buffer = gtk::EntryBuffer::default();
 
@@ -232,10 +654,56 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
             }
         });
 
+        let mut name_match = MatchMode::Exact;
+        let mut args_match = MatchMode::Contains;
         if let ProfileRule::Process(rule) | ProfileRule::Gamemode(Some(rule)) = &rule {
             process_name_buffer.set_text(rule.name.as_ref());
             args_buffer.set_text(rule.args.as_deref().unwrap_or_default());
+            name_match = rule.name_match.unwrap_or(name_match);
+            args_match = rule.args_match.unwrap_or(args_match);
+        };
+        let match_mode_index = |mode| {
+            MatchMode::ALL
+                .iter()
+                .position(|item| *item == mode)
+                .unwrap_or_default() as u32
         };
+        let name_match_mode = U32Binding::new(match_mode_index(name_match));
+        let args_match_mode = U32Binding::new(match_mode_index(args_match));
+        for binding in [&name_match_mode, &args_match_mode] {
+            binding.connect_value_notify({
+                let sender = sender.clone();
+                move |_| {
+                    sender.input(ProfileRuleRowMsg::Changed);
+                }
+            });
+        }
+
+        let schedule_start_buffer = gtk::EntryBuffer::default();
+        let schedule_end_buffer = gtk::EntryBuffer::default();
+        let mut schedule_extra_times = Vec::new();
+
+        let schedule_weekdays = Weekday::ALL.map(|day| {
+            let enabled = matches!(&rule, ProfileRule::Schedule(schedule) if schedule.weekdays.contains(&day));
+            BoolBinding::new(enabled)
+        });
+
+        if let ProfileRule::Schedule(schedule) = &rule {
+            if let Some((first_range, extra_ranges)) = schedule.times.split_first() {
+                schedule_start_buffer.set_text(first_range.start.to_string());
+                schedule_end_buffer.set_text(first_range.end.to_string());
+                schedule_extra_times = extra_ranges.to_vec();
+            }
+        }
+
+        for buffer in [&schedule_start_buffer, &schedule_end_buffer] {
+            buffer.connect_text_notify({
+                let sender = sender.clone();
+                move |_| {
+                    sender.input(ProfileRuleRowMsg::Changed);
+                }
+            });
+        }
 
         let mut process_listview = TypedListView::<ProcessListItem, gtk::SingleSelection>::new();
         process_listview.selection_model.set_autoselect(false);
@@ -243,14 +711,170 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
         let initial_page = match &rule {
             ProfileRule::Process(_) => PROCESS_PAGE,
             ProfileRule::Gamemode(_) => GAMEMODE_PAGE,
+            ProfileRule::Schedule(_) => SCHEDULE_PAGE,
+            ProfileRule::PowerSource(_) => POWER_SOURCE_PAGE,
+            ProfileRule::Sensor(_) => SENSOR_PAGE,
+            ProfileRule::Environment(_) => ENVIRONMENT_PAGE,
+            ProfileRule::Cgroup(_) => CGROUP_PAGE,
+            ProfileRule::Ancestor(_) => ANCESTOR_PAGE,
+            ProfileRule::GpuUsage(_) => GPU_USAGE_PAGE,
             _ => PROCESS_PAGE, // Fallback
         };
         let selected_page = StringBinding::new(initial_page);
 
         let filter_by_args = BoolBinding::new(args_buffer.length() > 0);
         let gamemode_filter_by_process = BoolBinding::new(process_name_buffer.length() > 0);
+        let power_source_battery = BoolBinding::new(matches!(
+            rule,
+            ProfileRule::PowerSource(PowerSource::Battery)
+        ));
+
+        let sensor_gpu_id_buffer = gtk::EntryBuffer::default();
+        let sensor_name_buffer = gtk::EntryBuffer::default();
+        let sensor_threshold = gtk::Adjustment::new(0.0, -1_000_000.0, 1_000_000.0, 1.0, 10.0, 0.0);
+        let sensor_hysteresis = gtk::Adjustment::new(0.0, 0.0, 1_000_000.0, 1.0, 10.0, 0.0);
+        let sensor_dwell_secs = gtk::Adjustment::new(0.0, 0.0, 86_400.0, 1.0, 10.0, 0.0);
 
-        for bool_bind in [&filter_by_args, &gamemode_filter_by_process] {
+        if let ProfileRule::Sensor(sensor_rule) = &rule {
+            sensor_gpu_id_buffer.set_text(sensor_rule.gpu_id.as_str());
+            sensor_name_buffer.set_text(sensor_rule.sensor.to_string());
+            sensor_threshold.set_value(sensor_rule.threshold);
+            sensor_hysteresis.set_value(sensor_rule.hysteresis);
+            sensor_dwell_secs.set_value(sensor_rule.dwell_secs as f64);
+        }
+        let sensor_below = BoolBinding::new(matches!(
+            &rule,
+            ProfileRule::Sensor(SensorProfileRule {
+                direction: ThresholdDirection::Below,
+                ..
+            })
+        ));
+
+        for buffer in [&sensor_gpu_id_buffer, &sensor_name_buffer] {
+            buffer.connect_text_notify({
+                let sender = sender.clone();
+                move |_| {
+                    sender.input(ProfileRuleRowMsg::Changed);
+                }
+            });
+        }
+        for adjustment in [&sensor_threshold, &sensor_hysteresis, &sensor_dwell_secs] {
+            adjustment.connect_value_changed({
+                let sender = sender.clone();
+                move |_| {
+                    sender.input(ProfileRuleRowMsg::Changed);
+                }
+            });
+        }
+
+        let env_name_buffer = gtk::EntryBuffer::default();
+        let env_value_buffer = gtk::EntryBuffer::default();
+        let mut env_value_match = MatchMode::Exact;
+        if let ProfileRule::Environment(env_rule) = &rule {
+            env_name_buffer.set_text(env_rule.name.as_str());
+            env_value_buffer.set_text(env_rule.value.as_deref().unwrap_or_default());
+            env_value_match = env_rule.value_match.unwrap_or(env_value_match);
+        }
+        let env_value_match_mode = U32Binding::new(match_mode_index(env_value_match));
+
+        let cgroup_value_buffer = gtk::EntryBuffer::default();
+        let mut cgroup_kind = CgroupMatchKind::default();
+        let mut cgroup_value_match = MatchMode::Exact;
+        if let ProfileRule::Cgroup(cgroup_rule) = &rule {
+            cgroup_value_buffer.set_text(cgroup_rule.value.as_str());
+            cgroup_kind = cgroup_rule.kind;
+            cgroup_value_match = cgroup_rule.value_match.unwrap_or(cgroup_value_match);
+        }
+        let cgroup_kind = U32Binding::new(
+            CgroupMatchKind::ALL
+                .iter()
+                .position(|kind| *kind == cgroup_kind)
+                .unwrap_or_default() as u32,
+        );
+        let cgroup_value_match_mode = U32Binding::new(match_mode_index(cgroup_value_match));
+        for binding in [
+            &env_value_match_mode,
+            &cgroup_kind,
+            &cgroup_value_match_mode,
+            &gpu_usage_engine,
+        ] {
+            binding.connect_value_notify({
+                let sender = sender.clone();
+                move |_| {
+                    sender.input(ProfileRuleRowMsg::Changed);
+                }
+            });
+        }
+
+        let ancestor_name_buffer = gtk::EntryBuffer::default();
+        let descendant_name_buffer = gtk::EntryBuffer::default();
+        let ancestor_rule = match &rule {
+            ProfileRule::Ancestor(ancestor_rule) => ancestor_rule.clone(),
+            _ => AncestorProfileRule::default(),
+        };
+        ancestor_name_buffer.set_text(ancestor_rule.ancestor.name.as_ref());
+        if let Some(process_rule) = &ancestor_rule.process {
+            descendant_name_buffer.set_text(process_rule.name.as_ref());
+        }
+        let ancestor_parent_only = BoolBinding::new(ancestor_rule.parent_only);
+
+        let gpu_usage_gpu_id_buffer = gtk::EntryBuffer::default();
+        let gpu_usage_process_buffer = gtk::EntryBuffer::default();
+        let gpu_usage_min_usage = gtk::Adjustment::new(0.0, 0.0, 100.0, 1.0, 10.0, 0.0);
+        let gpu_usage_rule = match &rule {
+            ProfileRule::GpuUsage(usage_rule) => usage_rule.clone(),
+            _ => GpuUsageProfileRule::default(),
+        };
+        gpu_usage_gpu_id_buffer.set_text(gpu_usage_rule.gpu_id.as_deref().unwrap_or_default());
+        if let Some(process_rule) = &gpu_usage_rule.process {
+            gpu_usage_process_buffer.set_text(process_rule.name.as_ref());
+        }
+        gpu_usage_min_usage.set_value(gpu_usage_rule.min_usage.unwrap_or(0).into());
+        gpu_usage_min_usage.connect_value_changed({
+            let sender = sender.clone();
+            move |_| {
+                sender.input(ProfileRuleRowMsg::Changed);
+            }
+        });
+        let gpu_usage_engine = U32Binding::new(
+            gpu_usage_rule
+                .engine
+                .and_then(|engine| {
+                    ProcessUtilizationType::ALL
+                        .iter()
+                        .position(|item| *item == engine)
+                })
+                .map_or(0, |index| index as u32 + 1),
+        );
+
+        for buffer in [
+            &env_name_buffer,
+            &env_value_buffer,
+            &cgroup_value_buffer,
+            &ancestor_name_buffer,
+            &descendant_name_buffer,
+            &gpu_usage_gpu_id_buffer,
+            &gpu_usage_process_buffer,
+        ] {
+            buffer.connect_text_notify({
+                let sender = sender.clone();
+                move |_| {
+                    sender.input(ProfileRuleRowMsg::Changed);
+                }
+            });
+        }
+
+        for bool_bind in [
+            &negate,
+            &filter_by_args,
+            &gamemode_filter_by_process,
+            &power_source_battery,
+            &sensor_below,
+            &ancestor_parent_only,
+        ]
+        .into_iter()
+        .chain(&schedule_weekdays)
+        {
             bool_bind.connect_value_notify({
                 let sender = sender.clone();
                 move |_| {
@@ -273,12 +897,41 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
 
         Self {
             selected_page,
+            negate,
             process_name_buffer,
+            name_match_mode,
+            args_match_mode,
             gamemode_filter_by_process,
             process_search_filter,
             filter_by_args,
             args_buffer,
             process_listview,
+            schedule_weekdays,
+            schedule_start_buffer,
+            schedule_end_buffer,
+            schedule_extra_times,
+            power_source_battery,
+            sensor_gpu_id_buffer,
+            sensor_name_buffer,
+            sensor_below,
+            sensor_threshold,
+            sensor_hysteresis,
+            sensor_dwell_secs,
+            env_name_buffer,
+            env_value_buffer,
+            env_value_match_mode,
+            cgroup_kind,
+            cgroup_value_buffer,
+            cgroup_value_match_mode,
+            ancestor_name_buffer,
+            descendant_name_buffer,
+            ancestor_parent_only,
+            ancestor_rule,
+            gpu_usage_gpu_id_buffer,
+            gpu_usage_process_buffer,
+            gpu_usage_min_usage,
+            gpu_usage_engine,
+            gpu_usage_rule,
         }
     }
 
@@ -337,8 +990,25 @@ impl relm4::factory::FactoryComponent for ProfileRuleRow {
 
 impl ProfileRuleRow {
     pub fn get_configured_rule(&self) -> ProfileRule {
+        let rule = self.get_page_rule();
+        if self.negate.value() {
+            ProfileRule::Not(Box::new(rule))
+        } else {
+            rule
+        }
+    }
+
+    fn get_page_rule(&self) -> ProfileRule {
         let process_name = self.process_name_buffer.text();
         let process_args = self.args_buffer.text();
+        let match_mode = |binding: &U32Binding, default| {
+            MatchMode::ALL
+                .get(binding.value() as usize)
+                .copied()
+                .filter(|mode| *mode != default)
+        };
+        let name_match = match_mode(&self.name_match_mode, MatchMode::Exact);
+        let args_match = match_mode(&self.args_match_mode, MatchMode::Contains);
 
         match self.selected_page.value().as_str() {
             PROCESS_PAGE => {
@@ -350,6 +1020,9 @@ impl ProfileRuleRow {
                 ProfileRule::Process(ProcessProfileRule {
                     name: process_name.as_str().into(),
                     args,
+                    name_match,
+                    args_match,
+                    ..Default::default()
                 })
             }
             GAMEMODE_PAGE => {
@@ -364,38 +1037,303 @@ impl ProfileRuleRow {
                     Some(ProcessProfileRule {
                         name: process_name.as_str().into(),
                         args,
+                        name_match,
+                        args_match,
+                        ..Default::default()
                     })
                 };
                 ProfileRule::Gamemode(rule)
             }
+            SCHEDULE_PAGE => {
+                let weekdays = Weekday::ALL
+                    .into_iter()
+                    .zip(&self.schedule_weekdays)
+                    .filter(|(_, enabled)| enabled.value())
+                    .map(|(day, _)| day)
+                    .collect();
+
+                let first_range = self
+                    .schedule_start_buffer
+                    .text()
+                    .parse()
+                    .and_then(|start| {
+                        let end = self.schedule_end_buffer.text().parse()?;
+                        Ok(TimeRange { start, end })
+                    })
+                    .ok();
+                let times = first_range
+                    .into_iter()
+                    .chain(self.schedule_extra_times.iter().copied())
+                    .collect();
+
+                ProfileRule::Schedule(ScheduleProfileRule { weekdays, times })
+            }
+            POWER_SOURCE_PAGE => {
+                if self.power_source_battery.value() {
+                    ProfileRule::PowerSource(PowerSource::Battery)
+                } else {
+                    ProfileRule::PowerSource(PowerSource::Ac)
+                }
+            }
+            SENSOR_PAGE => {
+                let sensor_name = self.sensor_name_buffer.text();
+                let sensor = sensor_name
+                    .parse()
+                    .unwrap_or_else(|_| ProfileSensor::Temperature(sensor_name.to_string()));
+                let direction = if self.sensor_below.value() {
+                    ThresholdDirection::Below
+                } else {
+                    ThresholdDirection::Above
+                };
+
+                ProfileRule::Sensor(SensorProfileRule {
+                    gpu_id: self.sensor_gpu_id_buffer.text().to_string(),
+                    sensor,
+                    threshold: self.sensor_threshold.value(),
+                    direction,
+                    hysteresis: self.sensor_hysteresis.value(),
+                    dwell_secs: self.sensor_dwell_secs.value() as u64,
+                })
+            }
+            ENVIRONMENT_PAGE => {
+                let value = self.env_value_buffer.text();
+                ProfileRule::Environment(EnvironmentProfileRule {
+                    name: self.env_name_buffer.text().to_string(),
+                    value: Some(value.to_string()).filter(|value| !value.is_empty()),
+                    value_match: match_mode(&self.env_value_match_mode, MatchMode::Exact),
+                    ..Default::default()
+                })
+            }
+            CGROUP_PAGE => ProfileRule::Cgroup(CgroupProfileRule {
+                kind: CgroupMatchKind::ALL
+                    .get(self.cgroup_kind.value() as usize)
+                    .copied()
+                    .unwrap_or_default(),
+                value: self.cgroup_value_buffer.text().to_string(),
+                value_match: match_mode(&self.cgroup_value_match_mode, MatchMode::Exact),
+                ..Default::default()
+            }),
+            ANCESTOR_PAGE => {
+                let descendant_name = self.descendant_name_buffer.text();
+                let process = (!descendant_name.is_empty()).then(|| {
+                    with_process_name(self.ancestor_rule.process.as_ref(), &descendant_name)
+                });
+
+                ProfileRule::Ancestor(AncestorProfileRule {
+                    ancestor: with_process_name(
+                        Some(&self.ancestor_rule.ancestor),
+                        &self.ancestor_name_buffer.text(),
+                    ),
+                    process,
+                    parent_only: self.ancestor_parent_only.value(),
+                })
+            }
+            GPU_USAGE_PAGE => {
+                let gpu_id = self.gpu_usage_gpu_id_buffer.text();
+                let process_name = self.gpu_usage_process_buffer.text();
+                let min_usage = self.gpu_usage_min_usage.value() as u32;
+
+                ProfileRule::GpuUsage(GpuUsageProfileRule {
+                    gpu_id: (!gpu_id.is_empty()).then(|| gpu_id.to_string()),
+                    process: (!process_name.is_empty()).then(|| {
+                        with_process_name(self.gpu_usage_rule.process.as_ref(), &process_name)
+                    }),
+                    min_usage: (min_usage > 0).then_some(min_usage),
+                    engine: (self.gpu_usage_engine.value() as usize)
+                        .checked_sub(1)
+                        .and_then(|index| ProcessUtilizationType::ALL.get(index))
+                        .copied(),
+                })
+            }
             _ => unreachable!(),
         }
     }
 }
 
-fn format_rule(rule: &ProfileRule) -> String {
+/// Builds a process filter with the given name, keeping the match modes and arguments
+/// which can only be configured in the config file
+fn with_process_name(rule: Option<&ProcessProfileRule>, name: &str) -> ProcessProfileRule {
+    let rule = rule.cloned().unwrap_or_default();
+    ProcessProfileRule {
+        name: name.into(),
+        args: rule.args,
+        name_match: rule.name_match,
+        args_match: rule.args_match,
+        ..Default::default()
+    }
+}
+
+pub(super) fn format_rule(rule: &ProfileRule) -> String {
     let mut text = String::new();
 
     match rule {
         ProfileRule::Process(process_rule) => {
             if !process_rule.name.is_empty() {
-                write!(text, "Process <b>{}</b> is running", process_rule.name).unwrap();
+                write!(
+                    text,
+                    "Process <b>{}</b>{} is running",
+                    markup_escape_text(&process_rule.name),
+                    match_mode_suffix(process_rule.name_match, MatchMode::Exact)
+                )
+                .unwrap();
             } else {
                 write!(text, "Process is running <b>(unconfigured)</b>").unwrap();
             }
             if let Some(args) = &process_rule.args {
-                write!(text, " with args <b>{args}</b>").unwrap();
+                write!(
+                    text,
+                    " with args <b>{}</b>{}",
+                    markup_escape_text(args),
+                    match_mode_suffix(process_rule.args_match, MatchMode::Contains)
+                )
+                .unwrap();
             }
         }
         ProfileRule::Gamemode(process_rule) => {
             write!(text, "Gamemode is active").unwrap();
             if let Some(process_rule) = process_rule {
-                write!(text, "with process <b>{}</b>", process_rule.name).unwrap();
+                write!(
+                    text,
+                    "with process <b>{}</b>{}",
+                    markup_escape_text(&process_rule.name),
+                    match_mode_suffix(process_rule.name_match, MatchMode::Exact)
+                )
+                .unwrap();
                 if let Some(args) = &process_rule.args {
-                    write!(text, " and args <b>{args}</b>").unwrap();
+                    write!(
+                        text,
+                        " and args <b>{}</b>{}",
+                        markup_escape_text(args),
+                        match_mode_suffix(process_rule.args_match, MatchMode::Contains)
+                    )
+                    .unwrap();
                 }
             }
         }
+        ProfileRule::Schedule(schedule) => {
+            write!(text, "On a schedule").unwrap();
+            if !schedule.weekdays.is_empty() {
+                let days = schedule
+                    .weekdays
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(text, " on <b>{days}</b>").unwrap();
+            }
+            if !schedule.times.is_empty() {
+                let times = schedule
+                    .times
+                    .iter()
+                    .map(|range| format!("{}-{}", range.start, range.end))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(text, " at <b>{times}</b>").unwrap();
+            }
+        }
+        ProfileRule::PowerSource(source) => {
+            write!(text, "Running on <b>{source}</b> power").unwrap();
+        }
+        ProfileRule::Sensor(sensor_rule) => {
+            let direction = match sensor_rule.direction {
+                ThresholdDirection::Above => "above",
+                ThresholdDirection::Below => "below",
+            };
+            write!(
+                text,
+                "Sensor <b>{}</b> is {direction} <b>{}</b>",
+                sensor_rule.sensor, sensor_rule.threshold
+            )
+            .unwrap();
+            if sensor_rule.dwell_secs > 0 {
+                write!(text, " for <b>{}s</b>", sensor_rule.dwell_secs).unwrap();
+            }
+        }
+        ProfileRule::Environment(env_rule) => {
+            write!(
+                text,
+                "Process with variable <b>{}</b>",
+                markup_escape_text(&env_rule.name)
+            )
+            .unwrap();
+            if let Some(value) = &env_rule.value {
+                write!(
+                    text,
+                    " set to <b>{}</b>{}",
+                    markup_escape_text(value),
+                    match_mode_suffix(env_rule.value_match, MatchMode::Exact)
+                )
+                .unwrap();
+            }
+            write!(text, " is running").unwrap();
+        }
+        ProfileRule::Cgroup(cgroup_rule) => {
+            let kind = match cgroup_rule.kind {
+                CgroupMatchKind::Path => "cgroup",
+                CgroupMatchKind::Unit => "systemd unit",
+                CgroupMatchKind::Slice => "systemd slice",
+                CgroupMatchKind::Flatpak => "Flatpak app",
+            };
+            write!(
+                text,
+                "Process in {kind} <b>{}</b>{} is running",
+                markup_escape_text(&cgroup_rule.value),
+                match_mode_suffix(cgroup_rule.value_match, MatchMode::Exact)
+            )
+            .unwrap();
+        }
+        ProfileRule::Ancestor(ancestor_rule) => {
+            match &ancestor_rule.process {
+                Some(process_rule) => write!(
+                    text,
+                    "Process <b>{}</b>{}",
+                    markup_escape_text(&process_rule.name),
+                    match_mode_suffix(process_rule.name_match, MatchMode::Exact)
+                )
+                .unwrap(),
+                None => write!(text, "Any process").unwrap(),
+            }
+            let relation = if ancestor_rule.parent_only {
+                "a child"
+            } else {
+                "a descendant"
+            };
+            write!(
+                text,
+                " that is {relation} of <b>{}</b>{} is running",
+                markup_escape_text(&ancestor_rule.ancestor.name),
+                match_mode_suffix(ancestor_rule.ancestor.name_match, MatchMode::Exact)
+            )
+            .unwrap();
+        }
+        ProfileRule::GpuUsage(usage_rule) => {
+            match &usage_rule.process {
+                Some(process_rule) => write!(
+                    text,
+                    "Process <b>{}</b>{}",
+                    markup_escape_text(&process_rule.name),
+                    match_mode_suffix(process_rule.name_match, MatchMode::Exact)
+                )
+                .unwrap(),
+                None => write!(text, "Any process").unwrap(),
+            }
+            match &usage_rule.gpu_id {
+                Some(gpu_id) => {
+                    write!(text, " is using GPU <b>{}</b>", markup_escape_text(gpu_id)).unwrap()
+                }
+                None => write!(text, " is using a GPU").unwrap(),
+            }
+            if let Some(min_usage) = usage_rule.min_usage {
+                let engine = usage_rule
+                    .engine
+                    .map(|engine| format!("{engine:?} ").to_lowercase())
+                    .unwrap_or_default();
+                write!(text, " with at least <b>{min_usage}%</b> {engine}usage").unwrap();
+            }
+        }
+        ProfileRule::Not(rule) => {
+            write!(text, "<b>Not:</b> {}", format_rule(rule)).unwrap();
+        }
         ProfileRule::And(subrules) => {
             write!(text, "All of the following rules are matched: ").unwrap();
             for (i, rule) in subrules.iter().enumerate() {
@@ -419,6 +1357,13 @@ fn format_rule(rule: &ProfileRule) -> String {
     text
 }
 
+fn match_mode_suffix(mode: Option<MatchMode>, default: MatchMode) -> String {
+    match mode {
+        Some(mode) if mode != default => format!(" ({})", mode.to_string().to_lowercase()),
+        _ => String::new(),
+    }
+}
+
 struct ProcessListItem(ProfileProcessInfo);
 
 struct ProcessListItemWidgets {
@@ -3,7 +3,11 @@ use super::{
     header::profile_rule_window::{profile_row::ProfileRuleRowMsg, ProfileRuleWindowMsg},
 };
 use lact_client::ConnectionStatusMsg;
-use lact_schema::{config::ProfileHooks, request::ProfileBase, DeviceStats, ProfileRule};
+use lact_schema::{
+    config::{ProfileHooks, ProfileTiming},
+    request::ProfileBase,
+    DeviceStats, ProfileRule,
+};
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
@@ -22,6 +26,7 @@ pub enum AppMsg {
     ShowProcessMonitor,
     DumpVBios,
     DebugSnapshot,
+    ExplainProfileSelection,
     EnableOverdrive,
     DisableOverdrive,
     ResetConfig,
@@ -42,6 +47,8 @@ pub enum AppMsg {
         name: String,
         rule: Option<ProfileRule>,
         hooks: ProfileHooks,
+        timing: ProfileTiming,
+        gpu_ids: Vec<String>,
     },
     ImportProfile,
     ExportProfile(Option<String>),
@@ -24,7 +24,7 @@ use gtk::{
 };
 use lact_daemon::AMDGPU_FAMILY_GC_11_0_0;
 use lact_schema::{
-    config::{FanControlSettings, FanCurve, GpuConfig},
+    config::{FanControlSettings, FanCurve, FanPidSettings, GpuConfig},
     default_fan_curve, FanControlMode, SystemInfo,
 };
 use relm4::{
@@ -36,6 +36,7 @@ use std::{cell::Cell, rc::Rc};
 const AUTO_PAGE: &str = "automatic";
 const CURVE_PAGE: &str = "curve";
 const STATIC_PAGE: &str = "static";
+const TARGET_PAGE: &str = "target_temperature";
 
 pub struct ThermalsPage {
     fan_curve_frame: relm4::Controller<FanCurveFrame>,
@@ -52,6 +53,7 @@ pub struct ThermalsPage {
     throttling: String,
 
     static_speed_adj: Adjustment,
+    target_temperature_adj: Adjustment,
 }
 
 #[derive(Clone, Default)]
@@ -325,6 +327,45 @@ impl relm4::Component for ThermalsPage {
                                 },
                             },
                         },
+                        add_titled[Some(TARGET_PAGE), "Target"] = &gtk::Box {
+                            set_valign: gtk::Align::Start,
+                            set_orientation: gtk::Orientation::Vertical,
+                            set_spacing: 5,
+
+                            gtk::Label {
+                                set_label: "This GPU has a firmware target temperature option in the automatic mode, which should be used instead.",
+                                set_wrap: true,
+                                set_xalign: 0.0,
+                                #[watch]
+                                set_visible: model.has_pmfw,
+                            },
+
+                            #[template]
+                            #[name = "target_temperature_row"]
+                            FanSettingRow {
+                                #[watch]
+                                set_sensitive: !model.has_pmfw,
+
+                                #[template_child]
+                                label {
+                                    set_label: "Target Temperature (°C)",
+                                    set_tooltip: "The fan speed is adjusted continuously to keep the temperature at this value",
+                                },
+
+                                #[template_child]
+                                scale {
+                                    set_adjustment: &model.target_temperature_adj,
+                                    connect_value_changed => move |_| {
+                                        APP_BROKER.send(AppMsg::SettingsChanged);
+                                    } @ target_temperature_changed_signal,
+                                },
+
+                                #[template_child]
+                                spinbutton {
+                                    set_adjustment: &model.target_temperature_adj,
+                                },
+                            },
+                        },
 
                         add_binding: (&model.selected_mode, "visible-child-name"),
                         connect_visible_child_name_notify => move |_| {
@@ -380,6 +421,14 @@ impl relm4::Component for ThermalsPage {
             has_auto_threshold: false,
             fan_speed: None,
             static_speed_adj: Adjustment::new(50.0, 0.0, 100.0, 1.0, 5.0, 0.0),
+            target_temperature_adj: Adjustment::new(
+                f64::from(FanPidSettings::default().target_temperature),
+                30.0,
+                100.0,
+                1.0,
+                5.0,
+                0.0,
+            ),
             selected_mode: StringBinding::new(AUTO_PAGE),
         };
 
@@ -433,6 +482,7 @@ impl relm4::Component for ThermalsPage {
                             Some(mode) if stats.fan.control_enabled => match mode {
                                 FanControlMode::Static => STATIC_PAGE,
                                 FanControlMode::Curve => CURVE_PAGE,
+                                FanControlMode::TargetTemperature => TARGET_PAGE,
                             },
                             _ => AUTO_PAGE,
                         };
@@ -469,6 +519,18 @@ impl relm4::Component for ThermalsPage {
                             .scale
                             .unblock_signal(&widgets.static_speed_changed_signal);
 
+                        widgets
+                            .target_temperature_row
+                            .scale
+                            .block_signal(&widgets.target_temperature_changed_signal);
+                        self.target_temperature_adj.set_value(f64::from(
+                            stats.fan.pid.unwrap_or_default().target_temperature,
+                        ));
+                        widgets
+                            .target_temperature_row
+                            .scale
+                            .unblock_signal(&widgets.target_temperature_changed_signal);
+
                         let temperature_range = stats
                             .fan
                             .temperature_range
@@ -481,6 +543,9 @@ impl relm4::Component for ThermalsPage {
                             temperature_key: stats.fan.temperature_key.clone(),
                             spindown_delay: stats.fan.spindown_delay_ms,
                             change_threshold: stats.fan.change_threshold,
+                            falling_curve: stats.fan.falling_curve.clone(),
+                            hysteresis: stats.fan.hysteresis,
+                            interpolation: stats.fan.interpolation,
                             speed_range,
                             temperature_range,
                             auto_threshold_supported: self.has_auto_threshold,
@@ -541,8 +606,11 @@ impl ThermalsPage {
 
                     let fan_curve_model = self.fan_curve_frame.model();
                     fan_settings.curve = FanCurve(fan_curve_model.get_curve());
+                    fan_settings.interpolation = fan_curve_model.interpolation();
                     fan_settings.change_threshold = Some(fan_curve_model.change_threshold());
                     fan_settings.spindown_delay_ms = Some(fan_curve_model.spindown_delay());
+                    fan_settings.hysteresis = Some(fan_curve_model.hysteresis());
+                    fan_settings.falling_curve = fan_curve_model.falling_curve().map(FanCurve);
 
                     if let Some(threshold) = fan_curve_model.auto_threshold() {
                         fan_settings.auto_threshold = Some(threshold);
@@ -556,6 +624,13 @@ impl ThermalsPage {
                     fan_settings.mode = FanControlMode::Static;
                     fan_settings.static_speed = self.static_speed_adj.value() as f32 / 100.0;
                 }
+                TARGET_PAGE => {
+                    fan_settings.mode = FanControlMode::TargetTemperature;
+                    fan_settings
+                        .pid
+                        .get_or_insert_with(FanPidSettings::default)
+                        .target_temperature = self.target_temperature_adj.value() as f32;
+                }
                 _ => unreachable!("Invalid fan control page selected"),
             }
         }
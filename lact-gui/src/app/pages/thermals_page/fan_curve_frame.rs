@@ -13,15 +13,20 @@ use gtk::{
         SignalHandlerId,
     },
     prelude::{
-        AdjustmentExt, BoxExt, ButtonExt, DrawingAreaExtManual, OrientableExt, RangeExt, WidgetExt,
+        AdjustmentExt, BoxExt, ButtonExt, DrawingAreaExtManual, OrientableExt, RangeExt,
+        ToggleButtonExt, WidgetExt,
     },
 };
-use lact_schema::{default_fan_curve, FanCurveMap};
+use lact_schema::{config::FanCurveInterpolation, default_fan_curve, FanCurveMap};
 use plotters::{
     chart::ChartBuilder,
     prelude::{Circle, EmptyElement, IntoDrawingArea, Text},
     series::{LineSeries, PointSeries},
-    style::{full_palette::LIGHTBLUE, text_anchor::Pos, Color, ShapeStyle, TextStyle},
+    style::{
+        full_palette::{LIGHTBLUE, ORANGE},
+        text_anchor::Pos,
+        Color, ShapeStyle, TextStyle,
+    },
 };
 use plotters_cairo::CairoBackend;
 use relm4::{
@@ -41,19 +46,33 @@ pub const DEFAULT_SPEED_RANGE: RangeInclusive<f32> = 0.0..=1.0;
 const DEFAULT_CHANGE_THRESHOLD: u64 = 2;
 const DEFAULT_AUTO_THRESHOLD: u64 = 0;
 const DEFAULT_SPINDOWN_DELAY_MS: u64 = 5000;
+const DEFAULT_HYSTERESIS: u64 = 0;
+/// Order of the options in the interpolation dropdown
+const INTERPOLATION_MODES: [(FanCurveInterpolation, &str); 3] = [
+    (FanCurveInterpolation::Linear, "Linear"),
+    (FanCurveInterpolation::MonotoneCubic, "Smooth"),
+    (FanCurveInterpolation::Step, "Step"),
+];
 
 #[derive(Clone)]
 pub(super) struct FanCurveFrame {
     pmfw_options: PmfwOptions,
 
     data: Rc<RefCell<Vec<(i32, f32)>>>,
+    /// Separate curve for falling temperatures. When not set, it is derived from the hysteresis
+    falling_data: Rc<RefCell<Option<Vec<(i32, f32)>>>>,
+    /// Whether the points of the falling curve are edited instead of the main curve
+    edit_falling: Rc<Cell<bool>>,
     speed_range: Rc<RefCell<RangeInclusive<f32>>>,
     temperature_range: Rc<RefCell<RangeInclusive<f32>>>,
     temp_keys: gtk::StringList,
     current_temp_key: U32Binding,
+    /// Index in `INTERPOLATION_MODES`
+    interpolation: U32Binding,
 
     spindown_delay_adj: OcAdjustment,
     change_threshold_adj: OcAdjustment,
+    hysteresis_adj: OcAdjustment,
     auto_threshold_adj: OcAdjustment,
     change_signals: Rc<[(glib::Object, SignalHandlerId)]>,
 
@@ -73,6 +92,10 @@ pub(super) enum FanCurveFrameMsg {
     AddPoint,
     RemovePoint,
     DefaultCurve,
+    AddFallingCurve,
+    RemoveFallingCurve,
+    EditFalling(bool),
+    Redraw,
 }
 
 #[derive(Debug)]
@@ -85,6 +108,9 @@ pub(super) struct CurveSetupMsg {
     // Non-PMFW only
     pub spindown_delay: Option<u64>,
     pub change_threshold: Option<u64>,
+    pub falling_curve: Option<FanCurveMap>,
+    pub hysteresis: Option<u64>,
+    pub interpolation: Option<FanCurveInterpolation>,
     /// Nvidia only
     pub auto_threshold_supported: bool,
     pub auto_threshold: Option<u64>,
@@ -155,6 +181,34 @@ impl relm4::Component for FanCurveFrame {
                     set_visible: model.pmfw_options.is_empty(),
                 },
 
+                gtk::Button {
+                    set_label: "Separate Falling Curve",
+                    set_tooltip: "Use a separate curve for falling temperatures instead of the spindown hysteresis",
+                    connect_clicked => FanCurveFrameMsg::AddFallingCurve,
+                    #[watch]
+                    set_visible: model.pmfw_options.is_empty() && model.falling_data.borrow().is_none(),
+                },
+
+                gtk::ToggleButton {
+                    set_label: "Edit Falling Curve",
+                    set_tooltip: "Move, add and remove the points of the falling curve (orange) instead of the main one",
+                    connect_toggled[sender] => move |button| {
+                        sender.input(FanCurveFrameMsg::EditFalling(button.is_active()));
+                    },
+                    #[watch]
+                    set_active: model.edit_falling.get(),
+                    #[watch]
+                    set_visible: model.falling_data.borrow().is_some(),
+                },
+
+                gtk::Button {
+                    set_label: "Remove Falling Curve",
+                    set_tooltip: "Go back to using the spindown hysteresis",
+                    connect_clicked => FanCurveFrameMsg::RemoveFallingCurve,
+                    #[watch]
+                    set_visible: model.falling_data.borrow().is_some(),
+                },
+
                 gtk::Button {
                     set_label: "Default",
                     connect_clicked => FanCurveFrameMsg::DefaultCurve,
@@ -182,6 +236,25 @@ impl relm4::Component for FanCurveFrame {
                 },
             },
 
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 5,
+
+                gtk::Label {
+                    set_label: "Interpolation",
+                    set_tooltip: "How the speed is calculated between the curve points",
+                    set_xalign: 0.0,
+                    set_size_group: &label_size_group,
+                },
+
+                #[name = "interpolation_dropdown"]
+                gtk::DropDown::from_strings(&INTERPOLATION_MODES.map(|(_, name)| name)) {
+                    set_hexpand: true,
+                    set_halign: gtk::Align::End,
+                    add_binding: (&model.interpolation, "selected"),
+                },
+            },
+
             #[template]
             FanSettingRow {
                 #[watch]
@@ -229,6 +302,36 @@ impl relm4::Component for FanCurveFrame {
                 },
             },
 
+            #[template]
+            FanSettingRow {
+                #[watch]
+                set_visible: model.pmfw_options.is_empty(),
+
+                #[template_child]
+                label {
+                    set_label: "Spindown hysteresis (°C)",
+                    set_tooltip: "How many degrees the temperature has to drop before the fan slows down. The orange line shows the resulting curve for falling temperatures.
+
+Not used when a separate falling curve is set.",
+                    set_size_group: &label_size_group,
+                },
+
+                #[template_child]
+                scale {
+                    set_adjustment: &model.hysteresis_adj,
+                    #[watch]
+                    set_sensitive: model.falling_data.borrow().is_none(),
+                },
+
+                #[template_child]
+                spinbutton {
+                    set_adjustment: &model.hysteresis_adj,
+                    set_size_group: &spin_size_group,
+                    #[watch]
+                    set_sensitive: model.falling_data.borrow().is_none(),
+                },
+            },
+
             #[template]
             FanSettingRow {
                 #[watch]
@@ -317,13 +420,16 @@ This option allows to work around this limitation by only using the custom curve
             OcAdjustment::new(DEFAULT_SPINDOWN_DELAY_MS as f64, 0.0, 30_000.0, 10.0, 10.0);
         let change_threshold_adj =
             OcAdjustment::new(DEFAULT_CHANGE_THRESHOLD as f64, 0.0, 10.0, 1.0, 1.0);
+        let hysteresis_adj = OcAdjustment::new(DEFAULT_HYSTERESIS as f64, 0.0, 20.0, 1.0, 1.0);
         let auto_threshold_adj = OcAdjustment::new(0.0, 0.0, 0.0, 1.0, 5.0);
         let temp_keys = gtk::StringList::default();
         let current_temp_key = U32Binding::new(0u32);
+        let interpolation = U32Binding::new(0u32);
 
         let change_signals = [
             &spindown_delay_adj,
             &change_threshold_adj,
+            &hysteresis_adj,
             &auto_threshold_adj,
         ]
         .into_iter()
@@ -333,14 +439,32 @@ This option allows to work around this limitation by only using the custom curve
             });
             (adj.clone().upcast(), signal)
         })
-        .chain([(
-            current_temp_key.clone().upcast(),
-            current_temp_key.connect_value_notify(|_| {
-                APP_BROKER.send(AppMsg::SettingsChanged);
-            }),
-        )])
+        .chain([
+            (
+                current_temp_key.clone().upcast(),
+                current_temp_key.connect_value_notify(|_| {
+                    APP_BROKER.send(AppMsg::SettingsChanged);
+                }),
+            ),
+            (
+                interpolation.clone().upcast(),
+                interpolation.connect_value_notify(|_| {
+                    APP_BROKER.send(AppMsg::SettingsChanged);
+                }),
+            ),
+        ])
         .collect();
 
+        // The falling curve is derived from the hysteresis value
+        let redraw_sender = sender.clone();
+        hysteresis_adj.connect_value_changed(move |_| {
+            redraw_sender.input(FanCurveFrameMsg::Redraw);
+        });
+        let redraw_sender = sender.clone();
+        interpolation.connect_value_notify(move |_| {
+            redraw_sender.input(FanCurveFrameMsg::Redraw);
+        });
+
         let model = Self {
             pmfw_options,
             is_dragging: Rc::new(AtomicBool::new(false)),
@@ -348,11 +472,15 @@ This option allows to work around this limitation by only using the custom curve
             temperature_range: Rc::new(RefCell::new(DEFAULT_TEMP_RANGE)),
             spindown_delay_adj,
             change_threshold_adj,
+            hysteresis_adj,
             auto_threshold_adj,
             temp_keys,
             current_temp_key,
+            interpolation,
             change_signals,
             data: Rc::default(),
+            falling_data: Rc::default(),
+            edit_falling: Rc::default(),
             drag_coord: Rc::default(),
             drag_point: Rc::default(),
         };
@@ -376,6 +504,10 @@ This option allows to work around this limitation by only using the custom curve
                 *self.data.borrow_mut() =
                     normalize_fan_curve(msg.curve, &msg.temperature_range, &msg.speed_range)
                         .collect();
+                *self.falling_data.borrow_mut() = msg.falling_curve.map(|curve| {
+                    normalize_fan_curve(curve, &msg.temperature_range, &msg.speed_range).collect()
+                });
+                self.edit_falling.set(false);
                 *self.speed_range.borrow_mut() = msg.speed_range;
                 *self.temperature_range.borrow_mut() = msg.temperature_range.clone();
 
@@ -409,6 +541,17 @@ This option allows to work around this limitation by only using the custom curve
                 self.change_threshold_adj.set_initial_value(
                     msg.change_threshold.unwrap_or(DEFAULT_CHANGE_THRESHOLD) as f64,
                 );
+                self.hysteresis_adj
+                    .set_initial_value(msg.hysteresis.unwrap_or(DEFAULT_HYSTERESIS) as f64);
+
+                let interpolation = msg.interpolation.unwrap_or_default();
+                let interpolation_idx = INTERPOLATION_MODES
+                    .iter()
+                    .position(|(mode, _)| *mode == interpolation)
+                    .unwrap_or(0);
+                widgets
+                    .interpolation_dropdown
+                    .set_selected(interpolation_idx as u32);
 
                 if msg.auto_threshold_supported {
                     self.auto_threshold_adj.set_lower(0.0);
@@ -476,6 +619,43 @@ This option allows to work around this limitation by only using the custom curve
                     .set_value(DEFAULT_SPINDOWN_DELAY_MS as f64);
                 self.change_threshold_adj
                     .set_value(DEFAULT_CHANGE_THRESHOLD as f64);
+                self.hysteresis_adj.set_value(DEFAULT_HYSTERESIS as f64);
+                widgets.interpolation_dropdown.set_selected(0);
+                self.falling_data.take();
+                self.edit_falling.set(false);
+            }
+            FanCurveFrameMsg::AddFallingCurve => {
+                // Start from the curve that the hysteresis currently results in
+                let hysteresis = self.hysteresis() as i32;
+                let falling_curve = normalize_fan_curve(
+                    self.data
+                        .borrow()
+                        .iter()
+                        .map(|(temp, speed)| (temp - hysteresis, *speed))
+                        .collect::<Vec<_>>(),
+                    &self.temperature_range.borrow(),
+                    &self.speed_range.borrow(),
+                )
+                .collect();
+                *self.falling_data.borrow_mut() = Some(falling_curve);
+                self.edit_falling.set(true);
+
+                widgets.drawing_area.queue_draw();
+                APP_BROKER.send(AppMsg::SettingsChanged);
+            }
+            FanCurveFrameMsg::RemoveFallingCurve => {
+                self.falling_data.take();
+                self.edit_falling.set(false);
+
+                widgets.drawing_area.queue_draw();
+                APP_BROKER.send(AppMsg::SettingsChanged);
+            }
+            FanCurveFrameMsg::EditFalling(edit_falling) => {
+                self.edit_falling.set(edit_falling);
+                widgets.drawing_area.queue_draw();
+            }
+            FanCurveFrameMsg::Redraw => {
+                widgets.drawing_area.queue_draw();
             }
         }
         self.update_view(widgets, sender);
@@ -487,6 +667,13 @@ impl FanCurveFrame {
         self.data.borrow().iter().copied().collect()
     }
 
+    pub fn falling_curve(&self) -> Option<FanCurveMap> {
+        self.falling_data
+            .borrow()
+            .as_ref()
+            .map(|curve| curve.iter().copied().collect())
+    }
+
     pub fn spindown_delay(&self) -> u64 {
         self.spindown_delay_adj.value() as u64
     }
@@ -495,6 +682,17 @@ impl FanCurveFrame {
         self.change_threshold_adj.value() as u64
     }
 
+    pub fn hysteresis(&self) -> u64 {
+        self.hysteresis_adj.value() as u64
+    }
+
+    pub fn interpolation(&self) -> FanCurveInterpolation {
+        INTERPOLATION_MODES
+            .get(self.interpolation.value() as usize)
+            .map(|(mode, _)| *mode)
+            .unwrap_or_default()
+    }
+
     pub fn temperature_key(&self) -> Option<String> {
         if self.temp_keys_available() {
             self.temp_keys
@@ -518,20 +716,53 @@ impl FanCurveFrame {
     }
 
     fn edit_curve(&self, f: impl FnOnce(&mut Vec<(i32, f32)>), widgets: &FanCurveFrameWidgets) {
-        f(&mut self.data.borrow_mut());
+        self.with_edited_curve(f);
 
         widgets.drawing_area.queue_draw();
         APP_BROKER.send(AppMsg::SettingsChanged);
     }
 
+    /// Calls `f` with either the main or the falling curve, depending on which one is being edited
+    fn with_edited_curve<T>(&self, f: impl FnOnce(&mut Vec<(i32, f32)>) -> T) -> T {
+        if self.edit_falling.get() {
+            if let Some(curve) = self.falling_data.borrow_mut().as_mut() {
+                return f(curve);
+            }
+        }
+        f(&mut self.data.borrow_mut())
+    }
+
     fn draw_chart(&self, ctx: &cairo::Context, width: i32, height: i32, colors: PlotColorScheme) {
         let cairo_backend = CairoBackend::new(ctx, (width as u32, height as u32)).unwrap();
 
         let drag_coord = self.drag_coord.take();
 
+        let falling_data = match &*self.falling_data.borrow() {
+            Some(curve) => curve.clone(),
+            None => {
+                let hysteresis = self.hysteresis() as i32;
+                if hysteresis == 0 {
+                    Vec::new()
+                } else {
+                    self.data
+                        .borrow()
+                        .iter()
+                        .map(|(temp, speed)| (temp - hysteresis, *speed))
+                        .collect()
+                }
+            }
+        };
+
+        let edit_falling = self.edit_falling.get() && self.falling_data.borrow().is_some();
+
         let new_value = draw_chart(
             cairo_backend,
-            &self.data.borrow(),
+            &ChartCurves {
+                data: &self.data.borrow(),
+                falling_data: &falling_data,
+                edit_falling,
+                interpolation: self.interpolation(),
+            },
             drag_coord,
             colors,
             &self.temperature_range.borrow(),
@@ -541,9 +772,11 @@ impl FanCurveFrame {
             let drag_point_idx = match self.drag_point.get() {
                 Some(idx) => Some(idx),
                 None => {
-                    let point = self.data.borrow().iter().position(|(data_x, data_y)| {
-                        (*data_x as f32 - new_value.0).abs() <= 3.0
-                            && (*data_y - new_value.1).abs() <= 0.03
+                    let point = self.with_edited_curve(|curve| {
+                        curve.iter().position(|(data_x, data_y)| {
+                            (*data_x as f32 - new_value.0).abs() <= 3.0
+                                && (*data_y - new_value.1).abs() <= 0.03
+                        })
                     });
                     self.drag_point.set(point);
                     point
@@ -552,7 +785,11 @@ impl FanCurveFrame {
             if let Some(idx) = drag_point_idx {
                 normalize_to_range(&mut new_value.0, &self.temperature_range.borrow());
                 normalize_to_range(&mut new_value.1, &self.speed_range.borrow());
-                self.data.borrow_mut()[idx] = (new_value.0 as i32, new_value.1);
+                self.with_edited_curve(|curve| {
+                    if let Some(point) = curve.get_mut(idx) {
+                        *point = (new_value.0 as i32, new_value.1);
+                    }
+                });
 
                 APP_BROKER.send(AppMsg::SettingsChanged);
             }
@@ -578,14 +815,41 @@ fn normalize_to_range(value: &mut f32, range: &RangeInclusive<f32>) {
     *value = f32::min(*value, *range.end());
 }
 
+/// The curve sampled at every degree of the chart
+fn interpolated_line(
+    data: &[(i32, f32)],
+    interpolation: FanCurveInterpolation,
+    temp_range: &RangeInclusive<f32>,
+) -> impl Iterator<Item = (f32, f32)> {
+    let curve: FanCurveMap = data.iter().copied().collect();
+    (*temp_range.start() as i32..=*temp_range.end() as i32)
+        .map(move |temp| (temp as f32, interpolation.speed_at(&curve, temp)))
+}
+
+/// The curves to draw on the chart
+struct ChartCurves<'a> {
+    data: &'a [(i32, f32)],
+    falling_data: &'a [(i32, f32)],
+    /// Whether the points of the falling curve are shown instead of the main curve
+    edit_falling: bool,
+    interpolation: FanCurveInterpolation,
+}
+
 fn draw_chart(
     backend: CairoBackend,
-    data: &[(i32, f32)],
+    curves: &ChartCurves,
     translate_coord: Option<(f64, f64)>,
     colors: PlotColorScheme,
     temp_range: &RangeInclusive<f32>,
     speed_range: &RangeInclusive<f32>,
 ) -> Option<(f32, f32)> {
+    let &ChartCurves {
+        data,
+        falling_data,
+        edit_falling,
+        interpolation,
+    } = curves;
+
     let root = backend.into_drawing_area();
     root.fill(&colors.background).unwrap();
 
@@ -613,22 +877,36 @@ fn draw_chart(
         .draw()
         .unwrap();
 
-    chart
-        .draw_series(LineSeries::new(
-            data.first()
-                .map(|(_, y)| (*temp_range.start(), { *y }))
-                .into_iter()
-                .chain(data.iter().map(|(x, y)| (*x as f32, *y)))
-                .chain(data.last().map(|(_, y)| (*temp_range.end(), *y))),
-            &LIGHTBLUE,
-        ))
-        .unwrap();
+    if !data.is_empty() {
+        chart
+            .draw_series(LineSeries::new(
+                interpolated_line(data, interpolation, temp_range),
+                &LIGHTBLUE,
+            ))
+            .unwrap();
+    }
+
+    if !falling_data.is_empty() {
+        chart
+            .draw_series(LineSeries::new(
+                interpolated_line(falling_data, interpolation, temp_range),
+                &ORANGE,
+            ))
+            .unwrap();
+    }
+
+    // Only the points of the curve being edited are shown
+    let (points, point_color) = if edit_falling {
+        (falling_data, ORANGE)
+    } else {
+        (data, LIGHTBLUE)
+    };
 
     chart
         .draw_series(PointSeries::of_element(
-            data.iter().map(|(x, y)| (*x as f32, *y)),
+            points.iter().map(|(x, y)| (*x as f32, *y)),
             8,
-            ShapeStyle::from(&LIGHTBLUE).filled(),
+            ShapeStyle::from(&point_color).filled(),
             &|coord, size, style| {
                 EmptyElement::at(coord)
                     + Circle::new((0, 0), size, style)
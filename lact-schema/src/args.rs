@@ -1,6 +1,8 @@
 pub use clap;
 
+use crate::ApplyScriptFormat;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 pub struct Args {
@@ -44,4 +46,37 @@ pub enum CliCommand {
     Info,
     /// Generate debug snapshot
     Snapshot,
+    /// Import a CoreCtrl profile as a new profile
+    ImportCorectrl {
+        /// Path to a CoreCtrl profile (`.ccpro` archive or profile XML)
+        path: PathBuf,
+        /// Name of the new profile. Defaults to the name of the CoreCtrl profile
+        #[arg(long)]
+        name: Option<String>,
+        /// Only show the converted profile without creating it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print a script which applies the GPU settings without the daemon
+    ExportScript {
+        /// Profile to export the settings from. Uses the default settings when not specified
+        #[arg(long)]
+        profile: Option<String>,
+        /// Script format: `shell` or `systemd` (oneshot service unit)
+        #[arg(long, default_value = "shell")]
+        format: ApplyScriptFormat,
+    },
+    /// Run a command with a profile activated, going back to the previous profile when it exits.
+    /// Use the global `--gpu-id` option to only activate the profile for one GPU
+    Run {
+        /// Profile to activate while the command runs
+        #[arg(long)]
+        profile: String,
+        /// Go back to the previous profile after this many seconds, even if the command is still running
+        #[arg(long)]
+        timeout_secs: Option<u64>,
+        /// The command and its arguments, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
 }
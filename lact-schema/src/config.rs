@@ -2,6 +2,7 @@ use amdgpu_sysfs::gpu_handle::{PerformanceLevel, PowerLevelKind};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
+use std::ops::Bound;
 
 use crate::{
     default_fan_curve,
@@ -17,6 +18,23 @@ pub struct Profile {
     pub rule: Option<ProfileRule>,
     #[serde(default, skip_serializing_if = "ProfileHooks::is_empty")]
     pub hooks: ProfileHooks,
+    #[serde(default, skip_serializing_if = "ProfileTiming::is_empty")]
+    pub timing: ProfileTiming,
+    /// GPUs that this profile is activated for. An empty list means the profile applies to all GPUs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gpu_ids: Vec<String>,
+}
+
+impl Profile {
+    /// Whether the profile is limited to specific GPUs
+    pub fn is_scoped(&self) -> bool {
+        !self.gpu_ids.is_empty()
+    }
+
+    /// Whether the profile can be activated for the given GPU
+    pub fn applies_to(&self, gpu_id: &str) -> bool {
+        self.gpu_ids.is_empty() || self.gpu_ids.iter().any(|id| id == gpu_id)
+    }
 }
 
 #[skip_serializing_none]
@@ -24,6 +42,10 @@ pub struct Profile {
 pub struct ProfileHooks {
     pub activated: Option<String>,
     pub deactivated: Option<String>,
+    /// Time in seconds after which a hook command gets killed
+    pub timeout_secs: Option<u64>,
+    /// Run the hook commands as this user instead of root
+    pub user: Option<String>,
 }
 
 impl ProfileHooks {
@@ -32,6 +54,22 @@ impl ProfileHooks {
     }
 }
 
+/// Delays applied when automatically switching away from a profile
+#[skip_serializing_none]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct ProfileTiming {
+    /// Minimum time in seconds the profile stays active after it was switched to
+    pub min_active_secs: Option<u64>,
+    /// Time in seconds the rule has to stay unmatched before the profile is deactivated
+    pub deactivation_grace_secs: Option<u64>,
+}
+
+impl ProfileTiming {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 pub struct GpuConfig {
@@ -44,6 +82,9 @@ pub struct GpuConfig {
     pub performance_level: Option<PerformanceLevel>,
     #[serde(default, flatten)]
     pub clocks_configuration: ClocksConfiguration,
+    /// Name of a clocks preset from the config library.
+    /// Values set directly in this config take priority over the ones from the preset.
+    pub clocks_preset: Option<String>,
     pub power_profile_mode_index: Option<u16>,
     /// Outer vector is for power profile components, inner vector is for the heuristics within a component
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -76,6 +117,36 @@ pub struct ClocksConfiguration {
     pub voltage_offset: Option<i32>,
 }
 
+impl ClocksConfiguration {
+    /// Fills in all values that are not set in this configuration from the given base
+    pub fn merge_base(&mut self, base: &ClocksConfiguration) {
+        self.min_core_clock = self.min_core_clock.or(base.min_core_clock);
+        self.min_memory_clock = self.min_memory_clock.or(base.min_memory_clock);
+        self.min_voltage = self.min_voltage.or(base.min_voltage);
+        self.max_core_clock = self.max_core_clock.or(base.max_core_clock);
+        self.max_memory_clock = self.max_memory_clock.or(base.max_memory_clock);
+        self.max_voltage = self.max_voltage.or(base.max_voltage);
+        self.voltage_offset = self.voltage_offset.or(base.voltage_offset);
+
+        for (pstate, offset) in &base.gpu_clock_offsets {
+            self.gpu_clock_offsets.entry(*pstate).or_insert(*offset);
+        }
+        for (pstate, offset) in &base.mem_clock_offsets {
+            self.mem_clock_offsets.entry(*pstate).or_insert(*offset);
+        }
+    }
+}
+
+/// A named set of clock and power settings which can be shared between GPU configs
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ClocksPreset {
+    #[serde(default, flatten)]
+    pub clocks_configuration: ClocksConfiguration,
+    pub power_cap: Option<f64>,
+    pub performance_level: Option<PerformanceLevel>,
+}
+
 mod offsets {
     use indexmap::IndexMap;
     use serde::{de::Error, Deserialize, Deserializer};
@@ -138,6 +209,7 @@ impl GpuConfig {
             },
             ClockspeedType::Reset => {
                 *clocks = ClocksConfiguration::default();
+                self.clocks_preset = None;
                 assert!(!self.is_core_clocks_used());
             }
         }
@@ -153,6 +225,102 @@ impl Default for FanCurve {
     }
 }
 
+/// How the fan speed is calculated between the points of a curve
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FanCurveInterpolation {
+    /// Holds the speed of the closest lower point
+    Step,
+    #[default]
+    Linear,
+    /// A smooth curve through the points that never overshoots them
+    MonotoneCubic,
+}
+
+impl FanCurveInterpolation {
+    /// Speed at the given temperature, from 0 to 1.
+    /// The closest point is used outside of the curve.
+    ///
+    /// # Panics
+    /// If the curve has no points
+    pub fn speed_at(self, curve: &FanCurveMap, temp: i32) -> f32 {
+        let maybe_lower = curve.range(..=temp).next_back();
+        let maybe_higher = curve.range(temp..).next();
+
+        match (maybe_lower, maybe_higher) {
+            (Some((lower_temp, lower_speed)), Some((higher_temp, higher_speed)))
+                if lower_temp != higher_temp =>
+            {
+                match self {
+                    Self::Step => *lower_speed,
+                    Self::Linear => {
+                        let speed_ratio =
+                            (temp - lower_temp) as f32 / (higher_temp - lower_temp) as f32;
+                        lower_speed + (higher_speed - lower_speed) * speed_ratio
+                    }
+                    Self::MonotoneCubic => monotone_cubic_speed(
+                        curve,
+                        (*lower_temp, *lower_speed),
+                        (*higher_temp, *higher_speed),
+                        temp,
+                    ),
+                }
+            }
+            (Some((_, speed)), _) | (None, Some((_, speed))) => *speed,
+            (None, None) => panic!("Could not find fan speed on the curve! This is a bug."),
+        }
+    }
+}
+
+/// Cubic Hermite interpolation with the tangents limited so that the curve stays monotonic
+/// between the points (Fritsch-Butland). `lower` and `higher` are the points around the temperature,
+/// the tangents at them also depend on their other neighbours.
+fn monotone_cubic_speed(
+    curve: &FanCurveMap,
+    lower: (i32, f32),
+    higher: (i32, f32),
+    temp: i32,
+) -> f32 {
+    let point = |(temp, speed): (&i32, &f32)| (*temp as f32, *speed);
+    let previous = curve.range(..lower.0).next_back().map(point);
+    let next = curve
+        .range((Bound::Excluded(higher.0), Bound::Unbounded))
+        .next()
+        .map(point);
+
+    let (x0, y0) = (lower.0 as f32, lower.1);
+    let (x1, y1) = (higher.0 as f32, higher.1);
+    let width = x1 - x0;
+    let secant = (y1 - y0) / width;
+    let lower_tangent = previous.map_or(secant, |previous| {
+        monotone_tangent(previous, (x0, y0), (x1, y1))
+    });
+    let higher_tangent = next.map_or(secant, |next| monotone_tangent((x0, y0), (x1, y1), next));
+
+    let t = (temp as f32 - x0) / width;
+    let h00 = 2.0 * t.powi(3) - 3.0 * t.powi(2) + 1.0;
+    let h10 = t.powi(3) - 2.0 * t.powi(2) + t;
+    let h01 = -2.0 * t.powi(3) + 3.0 * t.powi(2);
+    let h11 = t.powi(3) - t.powi(2);
+
+    h00 * y0 + h10 * width * lower_tangent + h01 * y1 + h11 * width * higher_tangent
+}
+
+/// Tangent at a point from the secants to its neighbours, flat at local extremes
+fn monotone_tangent(previous: (f32, f32), point: (f32, f32), next: (f32, f32)) -> f32 {
+    let left_width = point.0 - previous.0;
+    let right_width = next.0 - point.0;
+    let left = (point.1 - previous.1) / left_width;
+    let right = (next.1 - point.1) / right_width;
+    if left * right <= 0.0 {
+        return 0.0;
+    }
+
+    let left_weight = 2.0 * right_width + left_width;
+    let right_weight = right_width + 2.0 * left_width;
+    (left_weight + right_weight) / (left_weight / left + right_weight / right)
+}
+
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct FanControlSettings {
@@ -162,10 +330,58 @@ pub struct FanControlSettings {
     pub static_speed: f32,
     pub temperature_key: String,
     pub interval_ms: u64,
+    #[serde(default)]
     pub curve: FanCurve,
+    #[serde(default)]
+    pub interpolation: FanCurveInterpolation,
+    /// Name of a fan curve from the config library, used instead of `curve` when set
+    pub curve_preset: Option<String>,
     pub spindown_delay_ms: Option<u64>,
     pub change_threshold: Option<u64>,
     pub auto_threshold: Option<u64>,
+    /// Curve used while the temperature is falling. The fan only slows down once this curve drops below the current speed.
+    pub falling_curve: Option<FanCurve>,
+    /// How many degrees the temperature has to drop before the fan slows down.
+    /// Ignored when `falling_curve` is set.
+    pub hysteresis: Option<u64>,
+    /// Sensors in addition to `temperature_key`. The fan runs at the highest speed requested by any of them.
+    ///
+    /// Like the falling curve and the hysteresis, only used by the curve mode.
+    /// The `target_temperature` mode only follows `temperature_key`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_sensors: Vec<FanSensorCurve>,
+    /// Used with the `target_temperature` mode. The defaults are used when not set.
+    pub pid: Option<FanPidSettings>,
+    /// The largest increase in fan speed (from 0 to 1) per second
+    pub max_speed_increase: Option<f32>,
+    /// The largest decrease in fan speed (from 0 to 1) per second
+    pub max_speed_decrease: Option<f32>,
+    /// Time constant for an exponential moving average of the temperature readings
+    pub temperature_smoothing_ms: Option<u64>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FanSensorCurve {
+    /// Name of the GPU sensor, or the sensor label when `hwmon` is set
+    pub temperature_key: String,
+    /// Name of a hwmon device outside of the GPU to read the sensor from, such as `k10temp` or `nvme`
+    pub hwmon: Option<String>,
+    /// Curve for this sensor. The main curve is used when not set.
+    pub curve: Option<FanCurve>,
+    /// Added to the temperature before it is looked up on the curve
+    #[serde(default)]
+    pub offset: i32,
+}
+
+impl FanSensorCurve {
+    /// Identifies the sensor among both the GPU and the system sensors
+    pub fn sensor_key(&self) -> String {
+        match &self.hwmon {
+            Some(hwmon) => format!("{hwmon}/{}", self.temperature_key),
+            None => self.temperature_key.clone(),
+        }
+    }
 }
 
 impl Default for FanControlSettings {
@@ -176,9 +392,47 @@ impl Default for FanControlSettings {
             temperature_key: "edge".to_owned(),
             interval_ms: 500,
             curve: FanCurve(default_fan_curve()),
+            interpolation: FanCurveInterpolation::default(),
+            curve_preset: None,
             spindown_delay_ms: None,
             change_threshold: None,
             auto_threshold: None,
+            falling_curve: None,
+            hysteresis: None,
+            extra_sensors: Vec::new(),
+            pid: None,
+            max_speed_increase: None,
+            max_speed_decrease: None,
+            temperature_smoothing_ms: None,
+        }
+    }
+}
+
+/// Controller settings for holding the `temperature_key` sensor at a target temperature.
+/// The gains work with the error in degrees and the fan speed from 0 to 1.
+#[skip_serializing_none]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FanPidSettings {
+    pub target_temperature: f32,
+    /// Speed change per degree above the target
+    pub kp: f32,
+    /// Speed change per degree above the target for each second it lasts
+    pub ki: f32,
+    /// Speed change per degree per second the temperature is rising
+    pub kd: f32,
+    /// The largest change in speed per second
+    pub max_speed_change: Option<f32>,
+}
+
+impl Default for FanPidSettings {
+    fn default() -> Self {
+        Self {
+            target_temperature: 70.0,
+            kp: 0.04,
+            ki: 0.004,
+            kd: 0.0,
+            max_speed_change: Some(0.05),
         }
     }
 }
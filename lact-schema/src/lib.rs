@@ -8,6 +8,7 @@ mod response;
 #[cfg(test)]
 mod tests;
 
+pub use profiles::ProcessEnvironment;
 pub use request::Request;
 pub use response::Response;
 
@@ -21,7 +22,7 @@ use amdgpu_sysfs::{
 };
 use indexmap::{IndexMap, IndexSet};
 use serde::{Deserialize, Serialize};
-use serde_with::skip_serializing_none;
+use serde_with::{skip_serializing_none, DeserializeFromStr, SerializeDisplay};
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
     fmt::{self, Debug, Display, Write},
@@ -29,7 +30,9 @@ use std::{
     sync::Arc,
 };
 
-use crate::config::ProfileHooks;
+use crate::config::{
+    FanCurveInterpolation, FanPidSettings, FanSensorCurve, Profile, ProfileHooks, ProfileTiming,
+};
 
 pub const GIT_COMMIT: &str = env!("VERGEN_GIT_SHA");
 
@@ -39,6 +42,8 @@ pub enum FanControlMode {
     Static,
     #[default]
     Curve,
+    /// Holds a temperature sensor at a target value by adjusting the speed with a PID controller
+    TargetTemperature,
 }
 
 impl FromStr for FanControlMode {
@@ -48,11 +53,33 @@ impl FromStr for FanControlMode {
         match s {
             "curve" => Ok(Self::Curve),
             "static" => Ok(Self::Static),
+            "target_temperature" => Ok(Self::TargetTemperature),
             _ => Err("unknown fan control mode".to_string()),
         }
     }
 }
 
+/// Format of a generated script for applying GPU settings without the daemon
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyScriptFormat {
+    #[default]
+    Shell,
+    Systemd,
+}
+
+impl FromStr for ApplyScriptFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shell" => Ok(Self::Shell),
+            "systemd" => Ok(Self::Systemd),
+            _ => Err("unknown script format, expected `shell` or `systemd`".to_string()),
+        }
+    }
+}
+
 pub type FanCurveMap = BTreeMap<i32, f32>;
 
 pub fn default_fan_curve() -> FanCurveMap {
@@ -496,6 +523,7 @@ pub struct FanStats {
     pub control_mode: Option<FanControlMode>,
     pub static_speed: Option<f32>,
     pub curve: Option<FanCurveMap>,
+    pub interpolation: Option<FanCurveInterpolation>,
     pub pwm_current: Option<u8>,
     pub speed_current: Option<u32>,
     pub speed_max: Option<u32>,
@@ -506,6 +534,9 @@ pub struct FanStats {
     pub temperature_key: Option<String>,
     pub spindown_delay_ms: Option<u64>,
     pub change_threshold: Option<u64>,
+    pub falling_curve: Option<FanCurveMap>,
+    pub hysteresis: Option<u64>,
+    pub pid: Option<FanPidSettings>,
     /// Nvidia-only
     pub auto_threshold: Option<u64>,
     // RDNA3+ params
@@ -611,10 +642,19 @@ pub struct FanOptions<'a> {
     pub mode: Option<FanControlMode>,
     pub static_speed: Option<f32>,
     pub curve: Option<FanCurveMap>,
+    pub interpolation: Option<FanCurveInterpolation>,
     #[serde(default)]
     pub pmfw: PmfwOptions,
     pub spindown_delay_ms: Option<u64>,
     pub change_threshold: Option<u64>,
+    /// An empty curve removes the existing falling curve
+    pub falling_curve: Option<FanCurveMap>,
+    pub hysteresis: Option<u64>,
+    pub extra_sensors: Option<Vec<FanSensorCurve>>,
+    pub pid: Option<FanPidSettings>,
+    pub max_speed_increase: Option<f32>,
+    pub max_speed_decrease: Option<f32>,
+    pub temperature_smoothing_ms: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -622,7 +662,15 @@ pub struct ProfilesInfo {
     pub profiles: IndexMap<String, Option<ProfileRule>>,
     #[serde(default)]
     pub profile_hooks: IndexMap<String, ProfileHooks>,
+    #[serde(default)]
+    pub profile_timing: IndexMap<String, ProfileTiming>,
+    /// GPUs each profile is limited to. Profiles that apply to all GPUs are not listed.
+    #[serde(default)]
+    pub profile_gpu_ids: IndexMap<String, Vec<String>>,
     pub current_profile: Option<String>,
+    /// The profile that is currently in effect for each GPU
+    #[serde(default)]
+    pub gpu_profiles: IndexMap<String, Option<String>>,
     pub auto_switch: bool,
     pub watcher_state: Option<ProfileWatcherState>,
 }
@@ -631,11 +679,22 @@ impl PartialEq for ProfilesInfo {
     fn eq(&self, other: &Self) -> bool {
         self.profiles.as_slice() == other.profiles.as_slice()
             && self.profile_hooks.as_slice() == other.profile_hooks.as_slice()
+            && self.profile_timing.as_slice() == other.profile_timing.as_slice()
+            && self.profile_gpu_ids.as_slice() == other.profile_gpu_ids.as_slice()
             && self.current_profile == other.current_profile
+            && self.gpu_profiles.as_slice() == other.gpu_profiles.as_slice()
             && self.auto_switch == other.auto_switch
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfileImportResult {
+    pub name: String,
+    pub profile: Profile,
+    /// Descriptions of the settings that could not be converted
+    pub unmapped: Vec<String>,
+}
+
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type", content = "filter", rename_all = "lowercase")]
@@ -644,6 +703,16 @@ pub enum ProfileRule {
     Gamemode(Option<ProcessProfileRule>),
     And(Vec<ProfileRule>),
     Or(Vec<ProfileRule>),
+    Not(Box<ProfileRule>),
+    Schedule(ScheduleProfileRule),
+    #[serde(rename = "power_source")]
+    PowerSource(PowerSource),
+    Sensor(SensorProfileRule),
+    Environment(EnvironmentProfileRule),
+    Cgroup(CgroupProfileRule),
+    Ancestor(AncestorProfileRule),
+    #[serde(rename = "gpu_usage")]
+    GpuUsage(GpuUsageProfileRule),
 }
 
 impl Default for ProfileRule {
@@ -657,6 +726,10 @@ impl Default for ProfileRule {
 pub struct ProcessProfileRule {
     pub name: Arc<str>,
     pub args: Option<String>,
+    /// How the name is matched, `Exact` when not specified
+    pub name_match: Option<MatchMode>,
+    /// How the arguments are matched, `Contains` when not specified
+    pub args_match: Option<MatchMode>,
 }
 
 impl Default for ProcessProfileRule {
@@ -664,10 +737,301 @@ impl Default for ProcessProfileRule {
         Self {
             name: String::new().into(),
             args: None,
+            name_match: None,
+            args_match: None,
+        }
+    }
+}
+
+/// Matches processes which have the given environment variable
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct EnvironmentProfileRule {
+    pub name: String,
+    /// Any value is accepted when not specified
+    pub value: Option<String>,
+    /// How the value is matched, `Exact` when not specified
+    pub value_match: Option<MatchMode>,
+}
+
+/// Matches processes which were started by a process matching `ancestor`, directly or through other processes
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AncestorProfileRule {
+    pub ancestor: ProcessProfileRule,
+    /// Any descendant is accepted when not specified
+    pub process: Option<ProcessProfileRule>,
+    /// Only direct children of the ancestor are accepted
+    #[serde(default)]
+    pub parent_only: bool,
+}
+
+/// Matches processes which are using a GPU, based on the GPU's process list
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct GpuUsageProfileRule {
+    /// All GPUs are checked when not specified
+    pub gpu_id: Option<String>,
+    /// Any process using the GPU is accepted when not specified
+    pub process: Option<ProcessProfileRule>,
+    /// Minimum utilization in percent. Having used the GPU at all is enough when not specified
+    pub min_usage: Option<u32>,
+    /// Engine whose utilization is compared with `min_usage`, any engine is accepted when not specified
+    pub engine: Option<ProcessUtilizationType>,
+}
+
+/// Matches processes by their cgroup, which systemd and Flatpak use to group the processes they start
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CgroupProfileRule {
+    #[serde(default)]
+    pub kind: CgroupMatchKind,
+    pub value: String,
+    /// How the value is matched, `Exact` when not specified
+    pub value_match: Option<MatchMode>,
+}
+
+/// Which part of the cgroup path is compared with the value of a [`CgroupProfileRule`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CgroupMatchKind {
+    /// The full cgroup path, such as `/system.slice/render.service`
+    #[default]
+    Path,
+    /// Any systemd service or scope in the path
+    Unit,
+    /// Any systemd slice in the path, so parent slices match as well
+    Slice,
+    /// The app ID of a Flatpak application
+    Flatpak,
+}
+
+impl CgroupMatchKind {
+    pub const ALL: [CgroupMatchKind; 4] = [
+        CgroupMatchKind::Path,
+        CgroupMatchKind::Unit,
+        CgroupMatchKind::Slice,
+        CgroupMatchKind::Flatpak,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    Exact,
+    Contains,
+    Glob,
+    Regex,
+}
+
+impl MatchMode {
+    pub const ALL: [MatchMode; 4] = [
+        MatchMode::Exact,
+        MatchMode::Contains,
+        MatchMode::Glob,
+        MatchMode::Regex,
+    ];
+}
+
+impl fmt::Display for MatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+/// Matches on the configured days of the week and times of day, in local time
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ScheduleProfileRule {
+    /// Days when the rule is active. Matches every day when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub weekdays: Vec<Weekday>,
+    /// Times of day when the rule is active. Matches the whole day when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub times: Vec<TimeRange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// All days, starting from Monday
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+}
+
+impl fmt::Display for Weekday {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+/// A range of time within a day. If the end is before the start, the range continues past midnight into the next day.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+}
+
+/// Time of day with minute precision, written as `HH:MM`
+#[derive(
+    SerializeDisplay, DeserializeFromStr, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    /// `24:00` is allowed to specify the end of the day
+    pub fn new(hour: u16, minute: u16) -> Option<Self> {
+        let minutes = hour.checked_mul(60)?.checked_add(minute)?;
+        (minute < 60 && minutes <= 24 * 60).then_some(Self(minutes))
+    }
+
+    /// Minutes since midnight
+    pub fn minutes(self) -> u16 {
+        self.0
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once(':')
+            .and_then(|(hour, minute)| Self::new(hour.parse().ok()?, minute.parse().ok()?))
+            .ok_or_else(|| format!("invalid time '{s}', expected HH:MM"))
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+impl fmt::Display for PowerSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerSource::Ac => "AC".fmt(f),
+            PowerSource::Battery => "Battery".fmt(f),
         }
     }
 }
 
+/// Matches when a GPU sensor value stays past the threshold for the dwell time.
+/// The rule then stays matched until the value goes back past the threshold by more than the hysteresis.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SensorProfileRule {
+    pub gpu_id: String,
+    pub sensor: ProfileSensor,
+    pub threshold: f64,
+    #[serde(default)]
+    pub direction: ThresholdDirection,
+    #[serde(default)]
+    pub hysteresis: f64,
+    #[serde(default)]
+    pub dwell_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThresholdDirection {
+    #[default]
+    Above,
+    Below,
+}
+
+/// A value from [`DeviceStats`] that can be used in profile rules
+#[derive(SerializeDisplay, DeserializeFromStr, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProfileSensor {
+    /// Temperature sensor by name, in °C
+    Temperature(String),
+    /// GPU usage in percent
+    BusyPercent,
+    /// Average power draw in watts
+    PowerAverage,
+    /// Current power draw in watts
+    PowerCurrent,
+    /// Fan speed in RPM
+    FanSpeed,
+    /// Fan PWM from 0 to 255
+    FanPwm,
+    /// GPU clockspeed in MHz
+    GpuClockspeed,
+    /// VRAM clockspeed in MHz
+    VramClockspeed,
+    /// GPU voltage in mV
+    GpuVoltage,
+    /// Used VRAM in MiB
+    VramUsed,
+}
+
+impl ProfileSensor {
+    const TEMPERATURE_PREFIX: &'static str = "temperature.";
+}
+
+impl FromStr for ProfileSensor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = s.strip_prefix(Self::TEMPERATURE_PREFIX) {
+            return Ok(Self::Temperature(name.to_owned()));
+        }
+
+        match s {
+            "busy_percent" => Ok(Self::BusyPercent),
+            "power_average" => Ok(Self::PowerAverage),
+            "power_current" => Ok(Self::PowerCurrent),
+            "fan_speed" => Ok(Self::FanSpeed),
+            "fan_pwm" => Ok(Self::FanPwm),
+            "gpu_clockspeed" => Ok(Self::GpuClockspeed),
+            "vram_clockspeed" => Ok(Self::VramClockspeed),
+            "gpu_voltage" => Ok(Self::GpuVoltage),
+            "vram_used" => Ok(Self::VramUsed),
+            _ => Err(format!("unknown sensor '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for ProfileSensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Temperature(name) => return write!(f, "{}{name}", Self::TEMPERATURE_PREFIX),
+            Self::BusyPercent => "busy_percent",
+            Self::PowerAverage => "power_average",
+            Self::PowerCurrent => "power_current",
+            Self::FanSpeed => "fan_speed",
+            Self::FanPwm => "fan_pwm",
+            Self::GpuClockspeed => "gpu_clockspeed",
+            Self::VramClockspeed => "vram_clockspeed",
+            Self::GpuVoltage => "gpu_voltage",
+            Self::VramUsed => "vram_used",
+        };
+        name.fmt(f)
+    }
+}
+
 pub type ProfileProcessMap = IndexMap<i32, ProfileProcessInfo>;
 
 #[derive(Serialize, Deserialize, Clone, Default)]
@@ -675,13 +1039,145 @@ pub struct ProfileWatcherState {
     pub process_list: ProfileProcessMap,
     pub gamemode_games: IndexSet<i32>,
     pub process_names_map: HashMap<Arc<str>, HashSet<i32>>,
+    /// `None` if the power source could not be detected
+    #[serde(default)]
+    pub power_source: Option<PowerSource>,
+    /// Sensor rules which are currently matched, as they depend on the value history
+    #[serde(default)]
+    pub active_sensor_rules: Vec<SensorProfileRule>,
+    /// Processes using each GPU, only sampled when there are GPU usage rules
+    #[serde(default)]
+    pub gpu_processes: HashMap<String, ProcessList>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HookKind {
+    Activated,
+    Deactivated,
+}
+
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookKind::Activated => write!(f, "activated"),
+            HookKind::Deactivated => write!(f, "deactivated"),
+        }
+    }
+}
+
+/// Outcome of running a profile hook command
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HookResult {
+    pub profile: String,
+    pub kind: HookKind,
+    pub command: String,
+    /// Unix timestamp in seconds
+    pub started_at: u64,
+    /// `None` if the command did not exit normally
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    /// Set when the command could not be started
+    pub error: Option<String>,
+}
+
+impl HookResult {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// A change of the active profile
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfileSwitch {
+    /// Unix timestamp in seconds
+    pub time: u64,
+    /// `None` means the default profile
+    pub old_profile: Option<String>,
+    pub new_profile: Option<String>,
+    /// Set when the profile was only switched for one GPU
+    pub gpu_id: Option<String>,
+    pub trigger: ProfileSwitchTrigger,
+    /// How long resetting the old settings and applying the new ones took
+    pub apply_duration_ms: u64,
+    /// Set when the new settings could not be applied, with a line for each GPU that failed
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProfileSwitchTrigger {
+    /// Selected with the `set_profile` request, or the active profile was deleted
+    Manual,
+    /// Selected by the rules of automatic profile switching
+    Rule {
+        /// PID and name of the process which caused the rule to match
+        process: Option<(i32, String)>,
+    },
+    /// A temporary profile lease was acquired, released or expired
+    Lease { id: u64 },
+}
+
+impl ProfileSwitchTrigger {
+    pub fn process(&self) -> Option<(i32, &str)> {
+        match self {
+            Self::Rule {
+                process: Some((pid, name)),
+            } => Some((*pid, name)),
+            _ => None,
+        }
+    }
+}
+
+/// Result of evaluating all profile rules, in the order they are checked
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfileSelectionExplanation {
+    pub profiles: Vec<ProfileExplanation>,
+    /// The first profile with a matching rule, `None` means the default profile
+    pub selected_profile: Option<String>,
+    /// Selection for each GPU which has profiles limited to it.
+    /// `None` as the selected profile of a GPU means that it follows the current profile.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub gpus: IndexMap<String, ProfileSelectionExplanation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfileExplanation {
+    pub name: String,
+    /// `None` if the profile has no rule, in which case it is never selected automatically
+    pub trace: Option<RuleTrace>,
+}
+
+/// Evaluation result of a rule and all of its nested rules
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RuleTrace {
+    pub rule: ProfileRule,
+    pub matched: bool,
+    /// Processes that matched a process or gamemode rule
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pids: Vec<i32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<RuleTrace>,
 }
 
 #[allow(clippy::module_name_repetitions)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ProfileProcessInfo {
     pub name: Arc<str>,
     pub cmdline: Box<str>,
+    /// Parent process ID, as it was when the process was started
+    #[serde(default)]
+    pub ppid: i32,
+    /// The unified (v2) cgroup path of the process
+    #[serde(default)]
+    pub cgroup: Box<str>,
+    #[serde(skip)]
+    pub environment: ProcessEnvironment,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -1,5 +1,12 @@
-use crate::{ProfileProcessInfo, ProfileWatcherState};
-use std::{collections::hash_map::Entry, fmt};
+use crate::{
+    GpuUsageProfileRule, MatchMode, ProcessProfileRule, ProcessUtilizationType, ProfileProcessInfo,
+    ProfileWatcherState,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt,
+    sync::OnceLock,
+};
 
 impl fmt::Debug for ProfileWatcherState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -7,6 +14,9 @@ impl fmt::Debug for ProfileWatcherState {
             .field("process_list", &self.process_list.len())
             .field("gamemode_games", &self.gamemode_games.len())
             .field("process_names_map", &self.process_names_map.len())
+            .field("power_source", &self.power_source)
+            .field("active_sensor_rules", &self.active_sensor_rules)
+            .field("gpu_processes", &self.gpu_processes.len())
             .finish()
     }
 }
@@ -43,3 +53,44 @@ impl ProfileWatcherState {
         None
     }
 }
+
+/// Environment variables of a process. They are only read when an environment rule needs them.
+#[derive(Clone, Default)]
+pub struct ProcessEnvironment(OnceLock<Box<[(Box<str>, Box<str>)]>>);
+
+impl ProcessEnvironment {
+    pub fn get_or_load(
+        &self,
+        load: impl FnOnce() -> Box<[(Box<str>, Box<str>)]>,
+    ) -> &[(Box<str>, Box<str>)] {
+        self.0.get_or_init(load)
+    }
+}
+
+impl fmt::Debug for ProcessEnvironment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProcessEnvironment")
+            .field("loaded", &self.0.get().is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProcessProfileRule {
+    /// Whether processes can be looked up by name instead of checking every one of them
+    pub fn has_exact_name(&self) -> bool {
+        matches!(self.name_match, None | Some(MatchMode::Exact))
+    }
+}
+
+impl GpuUsageProfileRule {
+    /// Checks the engine utilization of a process which is using the GPU
+    pub fn usage_matches(&self, util: &HashMap<ProcessUtilizationType, u32>) -> bool {
+        let Some(min_usage) = self.min_usage else {
+            return true;
+        };
+        match self.engine {
+            Some(engine) => util.get(&engine).is_some_and(|usage| *usage >= min_usage),
+            None => util.values().any(|usage| *usage >= min_usage),
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use std::fmt;
 
 use crate::{
-    config::{GpuConfig, Profile, ProfileHooks},
-    FanOptions, ProfileRule,
+    config::{GpuConfig, Profile, ProfileHooks, ProfileTiming},
+    ApplyScriptFormat, FanOptions, ProfileRule,
 };
 use amdgpu_sysfs::gpu_handle::{PerformanceLevel, PowerLevelKind};
 use serde::{Deserialize, Serialize};
@@ -73,6 +73,22 @@ pub enum Request<'a> {
         name: Option<String>,
         #[serde(default)]
         auto_switch: bool,
+        /// Only switch the profile of this GPU, leaving the others untouched
+        #[serde(default)]
+        gpu_id: Option<String>,
+    },
+    /// Activate a profile until the lease is released, the connection that acquired it is closed
+    /// or the timeout passes, then go back to the previous profile. Returns the lease ID
+    AcquireProfileLease {
+        name: Option<String>,
+        /// Only activate the profile for this GPU
+        #[serde(default)]
+        gpu_id: Option<String>,
+        timeout_secs: Option<u64>,
+    },
+    /// Returns whether the lease was still active
+    ReleaseProfileLease {
+        id: u64,
     },
     CreateProfile {
         name: String,
@@ -88,11 +104,39 @@ pub enum Request<'a> {
     EvaluateProfileRule {
         rule: ProfileRule,
     },
+    /// Evaluate the rules of all profiles and report why the current one gets selected
+    ExplainProfileSelection,
+    /// Recent results of profile hook commands, oldest first
+    ListHookResults,
+    /// Recent profile switches, oldest first
+    ListProfileSwitches,
+    /// The hooks, timing and GPU scope of the profile are kept unchanged when not specified
     SetProfileRule {
         name: String,
         rule: Option<ProfileRule>,
         #[serde(default)]
-        hooks: ProfileHooks,
+        hooks: Option<ProfileHooks>,
+        #[serde(default)]
+        timing: Option<ProfileTiming>,
+        #[serde(default)]
+        gpu_ids: Option<Vec<String>>,
+    },
+    /// Import a profile from CoreCtrl (either a `.ccpro` archive or the profile XML)
+    ImportCorectrlProfile {
+        data: Vec<u8>,
+        /// Name to use instead of the one in the imported profile
+        name: Option<String>,
+        /// Only convert the profile without saving it
+        #[serde(default)]
+        dry_run: bool,
+    },
+    /// Generate a script which applies the settings of a GPU without the daemon
+    ExportApplyScript {
+        id: &'a str,
+        /// Profile to take the settings from. Uses the default settings when not specified
+        profile: Option<String>,
+        #[serde(default)]
+        format: ApplyScriptFormat,
     },
     GetGpuConfig {
         id: &'a str,
@@ -173,7 +217,7 @@ impl fmt::Display for ProfileBase {
 mod tests {
     use crate::{
         request::{ClockspeedType, SetClocksCommand},
-        Request,
+        ApplyScriptFormat, Request,
     };
 
     #[test]
@@ -189,5 +233,16 @@ mod tests {
             serde_json::from_str(r#"{"command": "set_clocks_value", "args": {"id": "asd", "command": {"type": "max_core_clock", "value": 2000}}}"#)
                 .unwrap()
         );
+        assert_eq!(
+            Request::ExportApplyScript {
+                id: "asd",
+                profile: None,
+                format: ApplyScriptFormat::Shell,
+            },
+            serde_json::from_str(
+                r#"{"command": "export_apply_script", "args": {"id": "asd", "profile": null}}"#
+            )
+            .unwrap()
+        );
     }
 }
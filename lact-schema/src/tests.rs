@@ -1,4 +1,9 @@
-use crate::{FanControlMode, FanOptions, PmfwOptions, Pong, Request, Response};
+use crate::{
+    CgroupMatchKind, CgroupProfileRule, FanControlMode, FanOptions, MatchMode, PmfwOptions, Pong,
+    PowerSource, ProcessProfileRule, ProfileRule, ProfileSensor, ProfileSwitch,
+    ProfileSwitchTrigger, Request, Response, RuleTrace, ScheduleProfileRule, SensorProfileRule,
+    ThresholdDirection, TimeOfDay, TimeRange, Weekday,
+};
 use anyhow::anyhow;
 use serde_json::json;
 use std::collections::BTreeMap;
@@ -83,6 +88,260 @@ fn set_fan_clocks() {
         pmfw: PmfwOptions::default(),
         spindown_delay_ms: None,
         change_threshold: None,
+        falling_curve: None,
+        hysteresis: None,
+        extra_sensors: None,
+        pid: None,
+        interpolation: None,
+        max_speed_increase: None,
+        max_speed_decrease: None,
+        temperature_smoothing_ms: None,
     });
     assert_eq!(expected_request, request);
 }
+
+#[test]
+fn process_rule_match_modes() {
+    let value = json!({
+        "type": "process",
+        "filter": {
+            "name": "*.exe",
+            "args": "^--profile \\w+$",
+            "name_match": "glob",
+            "args_match": "regex"
+        }
+    });
+    let rule: ProfileRule = serde_json::from_value(value.clone()).unwrap();
+    let expected_rule = ProfileRule::Process(ProcessProfileRule {
+        name: "*.exe".into(),
+        args: Some("^--profile \\w+$".to_owned()),
+        name_match: Some(MatchMode::Glob),
+        args_match: Some(MatchMode::Regex),
+        ..Default::default()
+    });
+    assert_eq!(expected_rule, rule);
+    assert_eq!(value, serde_json::to_value(&rule).unwrap());
+}
+
+#[test]
+fn not_rule() {
+    let value = json!({
+        "type": "not",
+        "filter": {
+            "type": "process",
+            "filter": {
+                "name": "obs"
+            }
+        }
+    });
+    let rule: ProfileRule = serde_json::from_value(value.clone()).unwrap();
+    let expected_rule = ProfileRule::Not(Box::new(ProfileRule::Process(ProcessProfileRule {
+        name: "obs".into(),
+        ..Default::default()
+    })));
+    assert_eq!(expected_rule, rule);
+    assert_eq!(value, serde_json::to_value(rule).unwrap());
+}
+
+#[test]
+fn schedule_rule() {
+    let value = json!({
+        "type": "schedule",
+        "filter": {
+            "weekdays": ["saturday", "sunday"],
+            "times": [{"start": "22:30", "end": "07:00"}]
+        }
+    });
+    let rule: ProfileRule = serde_json::from_value(value.clone()).unwrap();
+    let expected_rule = ProfileRule::Schedule(ScheduleProfileRule {
+        weekdays: vec![Weekday::Saturday, Weekday::Sunday],
+        times: vec![TimeRange {
+            start: TimeOfDay::new(22, 30).unwrap(),
+            end: TimeOfDay::new(7, 0).unwrap(),
+        }],
+    });
+    assert_eq!(expected_rule, rule);
+    assert_eq!(value, serde_json::to_value(rule).unwrap());
+
+    assert!("24:01".parse::<TimeOfDay>().is_err());
+    assert!("7:60".parse::<TimeOfDay>().is_err());
+    assert_eq!("24:00", "24:00".parse::<TimeOfDay>().unwrap().to_string());
+}
+
+#[test]
+fn power_source_rule() {
+    let value = json!({
+        "type": "power_source",
+        "filter": "battery"
+    });
+    let rule: ProfileRule = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(ProfileRule::PowerSource(PowerSource::Battery), rule);
+    assert_eq!(value, serde_json::to_value(rule).unwrap());
+}
+
+#[test]
+fn sensor_rule() {
+    let value = json!({
+        "type": "sensor",
+        "filter": {
+            "gpu_id": "1002:744C-1EAE:7905-0000:09:00.0",
+            "sensor": "temperature.junction",
+            "threshold": 90.0,
+            "direction": "above",
+            "hysteresis": 10.0,
+            "dwell_secs": 30
+        }
+    });
+    let rule: ProfileRule = serde_json::from_value(value.clone()).unwrap();
+    let expected_rule = ProfileRule::Sensor(SensorProfileRule {
+        gpu_id: "1002:744C-1EAE:7905-0000:09:00.0".to_owned(),
+        sensor: ProfileSensor::Temperature("junction".to_owned()),
+        threshold: 90.0,
+        direction: ThresholdDirection::Above,
+        hysteresis: 10.0,
+        dwell_secs: 30,
+    });
+    assert_eq!(expected_rule, rule);
+    assert_eq!(value, serde_json::to_value(rule).unwrap());
+
+    assert_eq!(
+        ProfileSensor::BusyPercent,
+        "busy_percent".parse::<ProfileSensor>().unwrap()
+    );
+    assert!("junction".parse::<ProfileSensor>().is_err());
+}
+
+#[test]
+fn environment_rule() {
+    let value = json!({
+        "type": "environment",
+        "filter": {
+            "name": "SteamAppId",
+            "value": "1091500"
+        }
+    });
+    let rule: ProfileRule = serde_json::from_value(value.clone()).unwrap();
+    assert!(matches!(rule, ProfileRule::Environment(_)));
+    assert_eq!(value, serde_json::to_value(&rule).unwrap());
+}
+
+#[test]
+fn cgroup_rule() {
+    let value = json!({
+        "type": "cgroup",
+        "filter": {
+            "kind": "slice",
+            "value": "render-farm.slice"
+        }
+    });
+    let rule: ProfileRule = serde_json::from_value(value.clone()).unwrap();
+    let expected_rule = ProfileRule::Cgroup(CgroupProfileRule {
+        kind: CgroupMatchKind::Slice,
+        value: "render-farm.slice".to_owned(),
+        value_match: None,
+    });
+    assert_eq!(expected_rule, rule);
+    assert_eq!(value, serde_json::to_value(&rule).unwrap());
+}
+
+#[test]
+fn ancestor_rule() {
+    let value = json!({
+        "type": "ancestor",
+        "filter": {
+            "ancestor": {
+                "name": "steam"
+            },
+            "process": {
+                "name": "*.exe",
+                "name_match": "glob"
+            },
+            "parent_only": false
+        }
+    });
+    let rule: ProfileRule = serde_json::from_value(value.clone()).unwrap();
+    assert!(matches!(rule, ProfileRule::Ancestor(_)));
+    assert_eq!(value, serde_json::to_value(&rule).unwrap());
+}
+
+#[test]
+fn explain_profile_selection_request() {
+    let value = r#"{
+        "command": "explain_profile_selection"
+    }"#;
+    let request: Request = serde_json::from_str(value).unwrap();
+
+    assert_eq!(request, Request::ExplainProfileSelection);
+}
+
+#[test]
+fn rule_trace() {
+    let obs_rule = ProfileRule::Process(ProcessProfileRule {
+        name: "obs".into(),
+        ..Default::default()
+    });
+    let trace = RuleTrace {
+        rule: ProfileRule::Not(Box::new(obs_rule.clone())),
+        matched: false,
+        pids: vec![],
+        children: vec![RuleTrace {
+            rule: obs_rule,
+            matched: true,
+            pids: vec![1234],
+            children: vec![],
+        }],
+    };
+    let expected_value = json!({
+        "rule": {
+            "type": "not",
+            "filter": {
+                "type": "process",
+                "filter": {
+                    "name": "obs"
+                }
+            }
+        },
+        "matched": false,
+        "children": [
+            {
+                "rule": {
+                    "type": "process",
+                    "filter": {
+                        "name": "obs"
+                    }
+                },
+                "matched": true,
+                "pids": [1234]
+            }
+        ]
+    });
+    assert_eq!(expected_value, serde_json::to_value(&trace).unwrap());
+    assert_eq!(trace, serde_json::from_value(expected_value).unwrap());
+}
+
+#[test]
+fn profile_switch() {
+    let switch = ProfileSwitch {
+        time: 1_700_000_000,
+        old_profile: None,
+        new_profile: Some("game".to_owned()),
+        gpu_id: None,
+        trigger: ProfileSwitchTrigger::Rule {
+            process: Some((1234, "game.exe".to_owned())),
+        },
+        apply_duration_ms: 120,
+        error: None,
+    };
+    let expected_value = json!({
+        "time": 1_700_000_000,
+        "new_profile": "game",
+        "trigger": {
+            "type": "rule",
+            "process": [1234, "game.exe"]
+        },
+        "apply_duration_ms": 120
+    });
+    assert_eq!(expected_value, serde_json::to_value(&switch).unwrap());
+    assert_eq!(switch, serde_json::from_value(expected_value).unwrap());
+    assert_eq!(Some((1234, "game.exe")), switch.trigger.process());
+}